@@ -2,6 +2,7 @@
 
 struct PostProcessSettings {
     sss: u32,
+    submerged: u32,
 }
 
 @group(0) @binding(0) var screen_texture: texture_2d<f32>;
@@ -52,6 +53,19 @@ fn fragment(in: FullscreenVertexOutput) -> @location(0) vec4<f32> {
         return mix(base, vec4(1.0, 1.0, 1.0, 1.0), 0.8);
     }
 
+    let color = effect(in, base, size, pix);
+
+    if settings.submerged != 0u {
+        // blue-green tint that gets darker towards the edges
+        let vignette = smoothstep(0.9, 0.2, distance(in.uv, vec2(0.5)));
+        let tinted = mix(color.rgb, vec3(0.05, 0.3, 0.4), 0.55) * mix(0.6, 1.0, vignette);
+        return vec4(tinted, color.a);
+    }
+
+    return color;
+}
+
+fn effect(in: FullscreenVertexOutput, base: vec4<f32>, size: vec2<f32>, pix: vec2<i32>) -> vec4<f32> {
     switch(settings.sss) {
         case 1u: {
             let bayer = array<array<f32,2>,2>(
@@ -1,13 +1,43 @@
-#import bevy_pbr::forward_io::VertexOutput
+#import bevy_pbr::{
+    pbr_fragment::pbr_input_from_standard_material,
+    pbr_functions::alpha_discard,
+}
 
-@group(2) @binding(0) var<uniform> material_color: vec4<f32>;
-@group(2) @binding(1) var material_color_texture: texture_2d<f32>;
-@group(2) @binding(2) var material_color_sampler: sampler;
+#ifdef PREPASS_PIPELINE
+#import bevy_pbr::{
+    prepass_io::{VertexOutput, FragmentOutput},
+    pbr_deferred_functions::deferred_output,
+}
+#else
+#import bevy_pbr::{
+    forward_io::{VertexOutput, FragmentOutput},
+    pbr_functions::{apply_pbr_lighting, main_pass_post_lighting_processing},
+}
+#endif
 
-// unsed as of now
+@group(2) @binding(100) var<uniform> tile_size: vec2<f32>;
+
+// greedy meshes store uvs in tile space (0..quad size) and the tile origin in uv_b
 @fragment
 fn fragment(
-    mesh: VertexOutput,
-) -> @location(0) vec4<f32> {
-    return textureSample(material_color_texture, material_color_sampler, mesh.uv);
-}
\ No newline at end of file
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> FragmentOutput {
+    var tiled = in;
+#ifdef VERTEX_UVS_B
+    tiled.uv = in.uv_b + fract(in.uv) * tile_size;
+#endif
+
+    var pbr_input = pbr_input_from_standard_material(tiled, is_front);
+    pbr_input.material.base_color = alpha_discard(pbr_input.material, pbr_input.material.base_color);
+
+#ifdef PREPASS_PIPELINE
+    let out = deferred_output(tiled, pbr_input);
+#else
+    var out: FragmentOutput;
+    out.color = apply_pbr_lighting(pbr_input);
+    out.color = main_pass_post_lighting_processing(pbr_input, out.color);
+#endif
+
+    return out;
+}
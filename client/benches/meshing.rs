@@ -0,0 +1,63 @@
+use std::{collections::HashMap, sync::Arc};
+
+use bevy::math::IVec3;
+use client::bench::{Chunk, ChunkMesh, generate_chunk};
+use criterion::{Criterion, criterion_group, criterion_main};
+use ferriscraft::{Block, CHUNK_HEIGHT, CHUNK_SIZE, WorldGenConfig, terrain::get_noise_functions};
+
+const SEED: u32 = 1337;
+
+fn generation(c: &mut Criterion) {
+    let noises = get_noise_functions(SEED, WorldGenConfig::default());
+    c.bench_function("generate_chunk", |b| {
+        b.iter(|| generate_chunk(IVec3::ZERO, &noises, false))
+    });
+}
+
+fn meshing(c: &mut Criterion) {
+    let noises = get_noise_functions(SEED, WorldGenConfig::default());
+
+    // build looks at the left and back neighbours too
+    let terrain: HashMap<IVec3, Arc<Chunk>> = [IVec3::ZERO, IVec3::NEG_X, IVec3::NEG_Z]
+        .into_iter()
+        .map(|pos| (pos, Arc::new(generate_chunk(pos, &noises, false))))
+        .collect();
+    let chunk = &terrain[&IVec3::ZERO];
+    c.bench_function("build terrain", |b| {
+        b.iter(|| ChunkMesh::default().build(chunk, &terrain, &noises, false))
+    });
+    c.bench_function("build terrain greedy", |b| {
+        b.iter(|| ChunkMesh::default().build(chunk, &terrain, &noises, true))
+    });
+
+    // every other block solid, every block has all its faces showing
+    let mut checkerboard = Chunk::new(IVec3::ZERO);
+    for z in 0..CHUNK_SIZE {
+        for y in 0..CHUNK_HEIGHT {
+            for x in 0..CHUNK_SIZE {
+                if (x + y + z) % 2 == 0 {
+                    let index = x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_HEIGHT;
+                    checkerboard.blocks[index as usize] = Block::STONE;
+                }
+            }
+        }
+    }
+    checkerboard.uniform = checkerboard.uniform_sections();
+    let neighbours: HashMap<IVec3, Arc<Chunk>> = [IVec3::NEG_X, IVec3::NEG_Z]
+        .into_iter()
+        .map(|pos| {
+            let mut chunk = checkerboard.clone();
+            chunk.pos = pos;
+            (pos, Arc::new(chunk))
+        })
+        .collect();
+    c.bench_function("build checkerboard", |b| {
+        b.iter(|| ChunkMesh::default().build(&checkerboard, &neighbours, &noises, false))
+    });
+    c.bench_function("build checkerboard greedy", |b| {
+        b.iter(|| ChunkMesh::default().build(&checkerboard, &neighbours, &noises, true))
+    });
+}
+
+criterion_group!(benches, generation, meshing);
+criterion_main!(benches);
@@ -0,0 +1,159 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    image::{ImageAddressMode, ImageSampler, ImageSamplerDescriptor},
+    math::Affine2,
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use noiz::{
+    Noise,
+    prelude::{
+        FractalLayers, Normed, Persistence,
+        common_noise::{Fbm, Simplex},
+    },
+    rng::NoiseRng,
+};
+
+use ferriscraft::terrain::noise;
+
+use crate::{CHUNK_SIZE, GameInfo, daylight, player::Player, ui::GameState};
+
+const CLOUD_HEIGHT: f32 = 192.0;
+const CLOUD_PIXELS: u32 = 128; // a side of the texture
+const CLOUD_TILE: f32 = 512.0; // blocks before the pattern repeats, 4 per pixel
+const CLOUD_SPEED: Vec2 = vec2(2.0, 0.6); // blocks per second
+const CLOUD_COVER: f32 = 0.5; // noise above this is cloud
+const CLOUD_OPACITY: f32 = 0.8;
+const NIGHT_CLOUD_COLOR: Color = Color::srgb(0.08, 0.09, 0.14);
+
+pub struct CloudsPlugin;
+
+impl Plugin for CloudsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_clouds.run_if(not(in_state(GameState::Menu))));
+    }
+}
+
+// a flat layer following the player around, only the texture drifts
+#[derive(Component)]
+struct Clouds;
+
+fn update_clouds(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    clouds: Option<
+        Single<
+            (
+                &mut Transform,
+                &MeshMaterial3d<StandardMaterial>,
+                &mut Visibility,
+            ),
+            (With<Clouds>, Without<Player>),
+        >,
+    >,
+    player: Single<&Transform, With<Player>>,
+    game_info: Res<GameInfo>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    let Some(clouds) = clouds else {
+        let material = StandardMaterial {
+            base_color_texture: Some(images.add(cloud_texture(game_info.seed))),
+            alpha_mode: AlphaMode::Blend,
+            unlit: true,
+            cull_mode: None,
+            double_sided: true,
+            ..default()
+        };
+        commands.spawn((
+            Clouds,
+            Mesh3d(meshes.add(Plane3d::default())),
+            MeshMaterial3d(materials.add(material)),
+            Transform::from_xyz(0.0, CLOUD_HEIGHT, 0.0),
+            Visibility::Hidden,
+            NotShadowCaster,
+            NotShadowReceiver,
+            StateScoped(*game_state.get()),
+        ));
+        return;
+    };
+    let (mut transform, material, mut visibility) = clouds.into_inner();
+
+    visibility.set_if_neq(if game_info.settings.clouds {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    });
+    if !game_info.settings.clouds {
+        return;
+    }
+
+    // whole tiles past the render distance on every side. the layer only ever moves a whole
+    // tile at a time, so the pattern doesn't jump when it does
+    let far = game_info.settings.render_distance as f32 * CHUNK_SIZE as f32;
+    let tiles = (2.0 * far / CLOUD_TILE).ceil() + 1.0;
+    let center = (player.translation.xz() / CLOUD_TILE).round() * CLOUD_TILE;
+    transform.translation = vec3(center.x, CLOUD_HEIGHT, center.y);
+    transform.scale = vec3(tiles * CLOUD_TILE, 1.0, tiles * CLOUD_TILE);
+
+    let Some(material) = materials.get_mut(&material.0) else {
+        return;
+    };
+    let drift = (CLOUD_SPEED * time.elapsed_secs() / CLOUD_TILE).fract();
+    material.uv_transform = Affine2::from_scale_angle_translation(Vec2::splat(tiles), 0.0, -drift);
+    material.base_color = NIGHT_CLOUD_COLOR.mix(&Color::WHITE, daylight(&game_info));
+}
+
+// white with the clouds in the alpha, wraps around seamlessly
+fn cloud_texture(seed: u32) -> Image {
+    let cloud_noise = Noise {
+        noise: Fbm::<Simplex>::new(
+            Normed::default(),
+            Persistence(0.5),
+            FractalLayers {
+                amount: 3,
+                lacunarity: 2.0,
+                ..Default::default()
+            },
+        ),
+        frequency: 0.02,
+        seed: NoiseRng(seed + 5),
+    };
+
+    let mut data = Vec::with_capacity((CLOUD_PIXELS * CLOUD_PIXELS * 4) as usize);
+    for y in 0..CLOUD_PIXELS {
+        for x in 0..CLOUD_PIXELS {
+            let t = vec2(x as f32, y as f32) / CLOUD_PIXELS as f32;
+            let pos = t * CLOUD_TILE;
+            // blending in the samples a tile away makes opposite edges match
+            let sample = |offset: Vec2| noise(cloud_noise, pos - offset * CLOUD_TILE);
+            let value = sample(Vec2::ZERO) * (1.0 - t.x) * (1.0 - t.y)
+                + sample(Vec2::X) * t.x * (1.0 - t.y)
+                + sample(Vec2::Y) * (1.0 - t.x) * t.y
+                + sample(Vec2::ONE) * t.x * t.y;
+            let alpha = ((value - CLOUD_COVER) * 8.0).clamp(0.0, 1.0) * CLOUD_OPACITY;
+            data.extend([255, 255, 255, (alpha * 255.0) as u8]);
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: CLOUD_PIXELS,
+            height: CLOUD_PIXELS,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+        address_mode_u: ImageAddressMode::Repeat,
+        address_mode_v: ImageAddressMode::Repeat,
+        ..ImageSamplerDescriptor::nearest()
+    });
+    image
+}
@@ -0,0 +1,86 @@
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr, UdpSocket},
+};
+
+use bevy::prelude::*;
+use ferriscraft::{DISCOVERY_PORT, ServerBeacon};
+
+use crate::ui::MenuState;
+
+const SERVER_TIMEOUT: f32 = 3.0; // seconds without a beacon before a server is dropped
+
+pub struct DiscoveryPlugin;
+
+impl Plugin for DiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DiscoveredServers>()
+            .add_systems(OnEnter(MenuState::MultiPlayer), start_listening)
+            .add_systems(OnExit(MenuState::MultiPlayer), stop_listening)
+            .add_systems(
+                Update,
+                listen_for_servers.run_if(in_state(MenuState::MultiPlayer)),
+            );
+    }
+}
+
+// lan servers that announced themselves, only listened for in the multiplayer menu
+#[derive(Resource, Default)]
+pub struct DiscoveredServers {
+    socket: Option<UdpSocket>,
+    pub servers: HashMap<SocketAddr, (ServerBeacon, f32)>, // game address, (beacon, last heard)
+}
+
+fn start_listening(mut discovered: ResMut<DiscoveredServers>) {
+    discovered.servers.clear();
+    // a second client on the same machine can't have the port, it just won't see servers
+    discovered.socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, DISCOVERY_PORT))
+        .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+        .inspect_err(|error| warn!("lan discovery unavailable: {error}"))
+        .ok();
+}
+
+fn stop_listening(mut discovered: ResMut<DiscoveredServers>) {
+    discovered.socket = None;
+    discovered.servers.clear();
+}
+
+fn listen_for_servers(mut discovered: ResMut<DiscoveredServers>, time: Res<Time>) {
+    let now = time.elapsed_secs();
+    // last heard changes every second, only a new or different server rebuilds the list
+    let inner = discovered.bypass_change_detection();
+    let mut changed = false;
+
+    if let Some(socket) = &inner.socket {
+        let mut buf = [0; 1024];
+        while let Ok((len, from)) = socket.recv_from(&mut buf) {
+            let Some(beacon) = ServerBeacon::decode(&buf[..len]) else {
+                continue;
+            };
+            let addr = SocketAddr::new(from.ip(), beacon.port);
+            match inner.servers.get_mut(&addr) {
+                Some((known, last_heard)) => {
+                    *last_heard = now;
+                    if *known != beacon {
+                        *known = beacon;
+                        changed = true;
+                    }
+                }
+                None => {
+                    inner.servers.insert(addr, (beacon, now));
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let count = inner.servers.len();
+    inner
+        .servers
+        .retain(|_, (_, last_heard)| now - *last_heard < SERVER_TIMEOUT);
+    changed |= inner.servers.len() != count;
+
+    if changed {
+        discovered.set_changed();
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Sneak,
+    Sprint,
+    Zoom,
+    Chat,
+    SaveWorld,
+    Screenshot,
+    ToggleDebug,
+    ToggleHitboxes,
+    ToggleChunkBorders,
+    ToggleMinimap,
+    CyclePostProcess,
+    ToggleWireframe,
+    ToggleFullscreen,
+    RotateBlock,
+    ToggleSpectator,
+    ToggleNoclip,
+}
+
+impl Action {
+    pub const ALL: [Action; 21] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Sneak,
+        Action::Sprint,
+        Action::Zoom,
+        Action::Chat,
+        Action::SaveWorld,
+        Action::Screenshot,
+        Action::ToggleDebug,
+        Action::ToggleHitboxes,
+        Action::ToggleChunkBorders,
+        Action::ToggleMinimap,
+        Action::CyclePostProcess,
+        Action::ToggleWireframe,
+        Action::ToggleFullscreen,
+        Action::RotateBlock,
+        Action::ToggleSpectator,
+        Action::ToggleNoclip,
+    ];
+
+    pub fn default_key(self) -> KeyCode {
+        match self {
+            Action::MoveForward => KeyCode::KeyW,
+            Action::MoveBackward => KeyCode::KeyS,
+            Action::MoveLeft => KeyCode::KeyA,
+            Action::MoveRight => KeyCode::KeyD,
+            Action::Jump => KeyCode::Space,
+            Action::Sneak => KeyCode::ShiftLeft,
+            Action::Sprint => KeyCode::ControlLeft,
+            Action::Zoom => KeyCode::KeyC,
+            Action::Chat => KeyCode::KeyT,
+            Action::SaveWorld => KeyCode::F1,
+            Action::Screenshot => KeyCode::F2,
+            Action::ToggleDebug => KeyCode::F3,
+            Action::ToggleHitboxes => KeyCode::F4,
+            Action::ToggleChunkBorders => KeyCode::F6,
+            Action::ToggleMinimap => KeyCode::KeyM,
+            Action::CyclePostProcess => KeyCode::F7,
+            Action::ToggleWireframe => KeyCode::F8,
+            Action::ToggleFullscreen => KeyCode::F11,
+            Action::RotateBlock => KeyCode::KeyR,
+            Action::ToggleSpectator => KeyCode::KeyN,
+            Action::ToggleNoclip => KeyCode::F9,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::MoveForward => "Move Forward",
+            Action::MoveBackward => "Move Backward",
+            Action::MoveLeft => "Move Left",
+            Action::MoveRight => "Move Right",
+            Action::Jump => "Jump",
+            Action::Sneak => "Sneak",
+            Action::Sprint => "Sprint",
+            Action::Zoom => "Zoom",
+            Action::Chat => "Chat",
+            Action::SaveWorld => "Save World",
+            Action::Screenshot => "Screenshot",
+            Action::ToggleDebug => "Debug Menus",
+            Action::ToggleHitboxes => "Hitboxes",
+            Action::ToggleChunkBorders => "Chunk Borders",
+            Action::ToggleMinimap => "Minimap",
+            Action::CyclePostProcess => "Post Processing",
+            Action::ToggleWireframe => "Wireframe",
+            Action::ToggleFullscreen => "Fullscreen",
+            Action::RotateBlock => "Rotate Block",
+            Action::ToggleSpectator => "Spectator",
+            Action::ToggleNoclip => "Noclip",
+        }
+    }
+}
+
+// only rebound actions end up in the file, everything else falls back to the defaults
+#[derive(Serialize, Deserialize, Default)]
+pub struct KeyBindings(HashMap<Action, KeyCode>);
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        self.0.get(&action).copied().unwrap_or(action.default_key())
+    }
+
+    // whatever had the key before gets this action's old one, so no two actions share a key
+    pub fn rebind(&mut self, action: Action, key: KeyCode) {
+        let old = self.key(action);
+        if let Some(other) = Action::ALL
+            .into_iter()
+            .find(|&other| other != action && self.key(other) == key)
+        {
+            self.set(other, old);
+        }
+        self.set(action, key);
+    }
+
+    fn set(&mut self, action: Action, key: KeyCode) {
+        if key == action.default_key() {
+            self.0.remove(&action);
+        } else {
+            self.0.insert(action, key);
+        }
+    }
+
+    pub fn pressed(&self, keyboard: &ButtonInput<KeyCode>, action: Action) -> bool {
+        keyboard.pressed(self.key(action))
+    }
+
+    pub fn just_pressed(&self, keyboard: &ButtonInput<KeyCode>, action: Action) -> bool {
+        keyboard.just_pressed(self.key(action))
+    }
+}
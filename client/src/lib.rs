@@ -0,0 +1,743 @@
+#![allow(
+    clippy::too_many_arguments,
+    clippy::type_complexity,
+    clippy::match_like_matches_macro,
+    clippy::vec_init_then_push,
+    clippy::manual_map
+)]
+
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+
+use bevy::{
+    core_pipeline::{Skybox, experimental::taa::TemporalAntiAliasPlugin},
+    image::{ImageFilterMode, ImageSamplerDescriptor},
+    input::common_conditions::input_just_pressed,
+    pbr::wireframe::WireframeConfig,
+    prelude::*,
+    render::{
+        RenderPlugin,
+        primitives::Aabb,
+        settings::{RenderCreation, WgpuFeatures, WgpuSettings},
+        view::screenshot::{Screenshot, ScreenshotCaptured, save_to_disk},
+    },
+    window::{ExitCondition, PresentMode, PrimaryWindow, WindowMode},
+};
+use bevy_framepace::FramepacePlugin;
+use bevy_mod_billboard::plugin::BillboardPlugin;
+use ferriscraft::{
+    ATLAS, BlockKind, CHUNK_HEIGHT, CHUNK_SIZE, Direction, GameEntity, Persistent, SavedChunk,
+    SavedWorld, terrain::NoiseFunctions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clouds::CloudsPlugin,
+    discovery::DiscoveryPlugin,
+    keybindings::{Action, KeyBindings},
+    minimap::MinimapPlugin,
+    multiplayer::MultiplayerPlugin,
+    music::MusicPlugin,
+    particles::ParticlePlugin,
+    player::{Health, Inventory, Player, PlayerPlugin},
+    render_pipeline::{PostProcessSettings, RenderPipelinePlugin, VoxelExtension, VoxelMaterial},
+    singleplayer::SinglePlayerPlugin,
+    sounds::SoundPlugin,
+    ui::{GameState, MenuState, Rebinding, UIPlugin, hotbar_slots, toast},
+    utils::{get_block, set_cursor_grab},
+    viewmodel::{HeldBlock, ViewmodelPlugin},
+    world::{Chunk, ChunkMarker, SaveTask, WorldPlugin, systems::save_game_in_background},
+};
+
+mod clouds;
+mod discovery;
+mod keybindings;
+mod minimap;
+mod multiplayer;
+mod music;
+mod particles;
+mod player;
+mod render_pipeline;
+mod singleplayer;
+mod sounds;
+mod ui;
+mod utils;
+mod viewmodel;
+mod world;
+
+// what benches/meshing.rs needs, the rest of the client stays private
+#[doc(hidden)]
+pub mod bench {
+    pub use crate::world::{Chunk, mesher::ChunkMesh, utils::generate_chunk};
+}
+
+#[derive(SystemSet, Debug, Hash, PartialEq, Eq, Clone)]
+struct PausableSystems;
+
+pub fn run() {
+    App::new()
+        .add_plugins((
+            DefaultPlugins
+                .set(WindowPlugin {
+                    primary_window: Some(Window {
+                        title: "FerrisCraft".to_string(),
+                        mode: WindowMode::Windowed,
+                        present_mode: PresentMode::AutoNoVsync,
+                        ..default()
+                    }),
+                    exit_condition: ExitCondition::DontExit,
+                    ..default()
+                })
+                .set(ImagePlugin {
+                    // for low res textures
+                    default_sampler: ImageSamplerDescriptor {
+                        min_filter: ImageFilterMode::Nearest,
+                        mag_filter: ImageFilterMode::Nearest,
+                        mipmap_filter: ImageFilterMode::Nearest,
+                        ..default()
+                    },
+                })
+                .set(RenderPlugin {
+                    render_creation: RenderCreation::Automatic(WgpuSettings {
+                        features: WgpuFeatures::POLYGON_MODE_LINE,
+                        ..default()
+                    }),
+                    ..default()
+                }),
+            TemporalAntiAliasPlugin,
+            FramepacePlugin,
+            BillboardPlugin,
+        ))
+        .add_plugins((
+            SinglePlayerPlugin,
+            MultiplayerPlugin,
+            DiscoveryPlugin,
+            WorldPlugin,
+            PlayerPlugin,
+            ParticlePlugin,
+            ViewmodelPlugin,
+            SoundPlugin,
+            MusicPlugin,
+            MinimapPlugin,
+            CloudsPlugin,
+            UIPlugin,
+            RenderPipelinePlugin,
+        ))
+        .insert_resource(AmbientLight {
+            brightness: 1000.,
+            ..default()
+        })
+        .init_resource::<GameInfo>()
+        .insert_resource(Persistent::new(
+            PathBuf::from("saves").join("keybindings.toml"),
+            KeyBindings::default(),
+            true,
+        ))
+        .configure_sets(
+            Update,
+            PausableSystems.run_if(
+                |settings: Res<GameInfo>, game_state: Res<State<GameState>>| {
+                    !settings.paused || game_state.get() == &GameState::MultiPlayer
+                },
+            ),
+        )
+        .configure_sets(
+            FixedUpdate,
+            PausableSystems.run_if(
+                |settings: Res<GameInfo>, game_state: Res<State<GameState>>| {
+                    !settings.paused || game_state.get() == &GameState::MultiPlayer
+                },
+            ),
+        )
+        .add_systems(Startup, setup)
+        // escape button
+        .add_systems(
+            Update,
+            (|mut game_info: ResMut<GameInfo>,
+              mut window: Single<&mut Window, With<PrimaryWindow>>,
+              game_state: Res<State<GameState>>,
+              menu_state: Res<State<MenuState>>,
+              mut next_menu_state: ResMut<NextState<MenuState>>,
+              mut rebinding: ResMut<Rebinding>| {
+                if menu_state.get() == &MenuState::Controls && rebinding.0.is_some() {
+                    rebinding.0 = None;
+                } else if game_state.get() == &GameState::Menu {
+                    match menu_state.get() {
+                        MenuState::Main => {
+                            // game_info.paused = !game_info.paused;
+                            // toggle_grab_cursor(&mut window);
+                        }
+                        _ => {
+                            next_menu_state.set(MenuState::Main);
+                        }
+                    }
+                } else if menu_state.get() == &MenuState::Settings {
+                    next_menu_state.set(MenuState::None);
+                } else if game_info.chat_open {
+                    game_info.chat_open = false;
+                } else {
+                    game_info.paused = !game_info.paused;
+                    set_cursor_grab(&mut window, !game_info.paused);
+                }
+            })
+            .run_if(input_just_pressed(KeyCode::Escape)),
+        )
+        .add_systems(
+            Update,
+            (
+                handle_keybinds
+                    .run_if(|settings: Res<GameInfo>| !settings.paused && !settings.chat_open),
+                handle_gizmos.in_set(PausableSystems),
+                handle_fov,
+                (handle_submerged, handle_fog).chain(),
+                handle_skybox,
+            )
+                .run_if(not(in_state(GameState::Menu))),
+        )
+        .run();
+}
+
+fn setup(
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut voxel_materials: ResMut<Assets<VoxelMaterial>>,
+    mut game_info: ResMut<GameInfo>,
+    asset_server: Res<AssetServer>,
+) {
+    let atlas = asset_server.load("atlas.ktx2");
+    let mut mats = Vec::new();
+    mats.push(materials.add(StandardMaterial {
+        base_color_texture: Some(atlas.clone()),
+        reflectance: 0.0,
+        ..default()
+    }));
+    game_info.voxel_material = voxel_materials.add(VoxelMaterial {
+        base: StandardMaterial {
+            base_color_texture: Some(atlas),
+            reflectance: 0.0,
+            ..default()
+        },
+        extension: VoxelExtension {
+            tile_size: ATLAS.tile_size(),
+        },
+    });
+    let mut models = Vec::new();
+    models.push(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/ferris.glb")));
+    // remote players are ferris too until there's a model of their own
+    models.push(asset_server.load(GltfAssetLabel::Scene(0).from_asset("models/ferris.glb")));
+    game_info.materials = mats;
+    game_info.models = models;
+    game_info.skybox = asset_server.load("skybox.ktx2");
+}
+
+// index into GameInfo.models, after the GameEntityKinds
+const PLAYER_MODEL: usize = 1;
+
+#[derive(Resource)]
+struct GameInfo {
+    chunks: Arc<RwLock<HashMap<IVec3, Arc<Chunk>>>>, // mesh tasks hold on to these, edits copy
+    loading_chunks: Arc<RwLock<HashSet<IVec3>>>,
+    saved_chunks: Option<Arc<RwLock<HashMap<IVec3, SavedChunk>>>>,
+    unsaved_chunks: Arc<RwLock<HashSet<IVec3>>>, // saved_chunks changed since the last save
+    materials: Vec<Handle<StandardMaterial>>,
+    voxel_material: Handle<VoxelMaterial>,
+    models: Vec<Handle<Scene>>,
+    skybox: Handle<Image>,
+    noises: NoiseFunctions,
+    seed: u32,
+    current_block: BlockKind,
+    placement_direction: Option<Direction>, // None faces away from the clicked side
+    settings: Persistent<GameSettings>,
+    ui_err: Option<String>,
+    connection_addr: Option<SocketAddr>,
+    paused: bool,
+    chat_open: bool,
+    time_of_day: Option<f32>, // only known in multiplayer for now
+    spawn: Option<Vec3>,      // None is on top of the terrain at the world origin
+    creative: bool,           // infinite blocks and no fall damage, from the world or the server
+}
+
+impl Default for GameInfo {
+    fn default() -> Self {
+        let mut settings = Persistent::new(
+            PathBuf::from("saves").join("settings.toml"),
+            GameSettings::default(),
+            true,
+        );
+        // older settings files stored gravity as a negative number
+        settings.gravity = settings.gravity.abs();
+        // every slot needs a number key to select it
+        settings.hotbar_slots = settings.hotbar_slots.min(DIGIT_KEYS.len());
+
+        Self {
+            chunks: Default::default(),
+            loading_chunks: Default::default(),
+            saved_chunks: Default::default(),
+            unsaved_chunks: Default::default(),
+            materials: Default::default(),
+            voxel_material: Default::default(),
+            models: Default::default(),
+            skybox: Default::default(),
+            noises: Default::default(),
+            seed: Default::default(),
+            current_block: Default::default(),
+            placement_direction: Default::default(),
+            settings,
+            ui_err: Default::default(),
+            connection_addr: Default::default(),
+            paused: Default::default(),
+            chat_open: Default::default(),
+            time_of_day: Default::default(),
+            spawn: Default::default(),
+            creative: Default::default(),
+        }
+    }
+}
+
+const MAX_AUTOSAVE_INTERVAL: u32 = 1800; // seconds
+
+#[derive(Serialize, Deserialize, Reflect)]
+// missing fields fall back to the defaults instead of resetting the whole file
+#[serde(default)]
+struct GameSettings {
+    player_name: String,
+    server_addr: String,
+    render_distance: i32,
+    movement_speed: f32,
+    jump_force: f32,
+    sensitivity: f32,
+    fov: u32,
+    view_bob: bool,
+    view_bob_amount: f32, // blocks the camera dips at each step
+    volume: f32,
+    music_volume: f32, // on top of volume
+    gravity: f32,      // blocks per second squared, always pulls down
+    reach: f32,
+    fog: bool,
+    fog_density: f32, // part of the render distance the fog fades over
+    clouds: bool,
+    interaction_interval: f32, // seconds between repeats while a mouse button is held
+    autosave: bool,
+    autosave_interval: u32, // seconds
+    despawn_chunks: bool,
+    greedy_meshing: bool,
+    // chunks and meshes promoted per frame, process_tasks adapts between these
+    min_chunk_budget: usize,
+    max_chunk_budget: usize,
+    creative: bool, // what the new world menu starts on, remembered from the last world made
+    debug_menus: bool,
+    hitboxes: bool,
+    entity_collision: bool, // bump into other players and mobs
+    chunk_borders: bool,
+    minimap: bool,
+    hotbar_slots: usize, // filled with placeable blocks in order
+    screenshot_dir: PathBuf,
+    screenshot_format: ScreenshotFormat,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            player_name: Default::default(),
+            server_addr: Default::default(),
+            render_distance: 16,
+            movement_speed: 4.32,
+            jump_force: 7.7,
+            sensitivity: 1.2,
+            fov: 60,
+            view_bob: true,
+            view_bob_amount: 0.05,
+            volume: 1.0,
+            music_volume: 0.5,
+            gravity: 23.31,
+            reach: 5.0,
+            fog: true,
+            fog_density: 0.3,
+            clouds: true,
+            interaction_interval: 0.2,
+            autosave: true,
+            autosave_interval: 600,
+            despawn_chunks: true,
+            greedy_meshing: false,
+            min_chunk_budget: 4,
+            max_chunk_budget: 64,
+            creative: true,
+            #[cfg(debug_assertions)]
+            debug_menus: true,
+            #[cfg(not(debug_assertions))]
+            debug_menus: false,
+            hitboxes: false,
+            entity_collision: true,
+            chunk_borders: false,
+            minimap: true,
+            hotbar_slots: DIGIT_KEYS.len(),
+            screenshot_dir: PathBuf::from("screenshots"),
+            screenshot_format: ScreenshotFormat::Png,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Reflect, Clone, Copy, PartialEq, Eq)]
+enum ScreenshotFormat {
+    Png,
+    Jpeg,
+}
+
+impl ScreenshotFormat {
+    // save_to_disk picks the encoder from this
+    fn extension(self) -> &'static str {
+        match self {
+            ScreenshotFormat::Png => "png",
+            ScreenshotFormat::Jpeg => "jpg",
+        }
+    }
+}
+
+// visibility to put back once the screenshot is taken
+#[derive(Component)]
+pub struct HiddenForScreenshot(Visibility);
+
+// with overlays, they're hidden until the screenshot is taken
+fn take_screenshot(
+    commands: &mut Commands,
+    settings: &GameSettings,
+    overlays: Option<
+        Query<(Entity, &Visibility), Or<((With<Node>, Without<ChildOf>), With<HeldBlock>)>>,
+    >,
+) {
+    if let Err(e) = std::fs::create_dir_all(&settings.screenshot_dir) {
+        toast(
+            commands,
+            format!("Couldn't create the screenshot folder: {e}"),
+        );
+        return;
+    }
+    let path = settings.screenshot_dir.join(format!(
+        "screenshot-{}.{}",
+        chrono::Local::now().format("%Y-%m-%d-%H-%M-%S%.3fZ"),
+        settings.screenshot_format.extension()
+    ));
+
+    for (entity, visibility) in overlays.iter().flatten() {
+        commands
+            .entity(entity)
+            .insert((HiddenForScreenshot(*visibility), Visibility::Hidden));
+    }
+    commands
+        .spawn(Screenshot::primary_window())
+        .observe(save_to_disk(path.clone()))
+        .observe(
+            move |_trigger: Trigger<ScreenshotCaptured>,
+                  mut commands: Commands,
+                  hidden: Query<(Entity, &HiddenForScreenshot)>| {
+                for (entity, hidden) in &hidden {
+                    commands
+                        .entity(entity)
+                        .insert(hidden.0)
+                        .remove::<HiddenForScreenshot>();
+                }
+                toast(
+                    &mut commands,
+                    format!("Saved screenshot to {}", path.display()),
+                );
+            },
+        );
+}
+
+const DIGIT_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+fn handle_keybinds(
+    mut commands: Commands,
+    mut primary_window: Single<&mut Window, With<PrimaryWindow>>,
+    mut wireframe_config: ResMut<WireframeConfig>,
+    mut game_info: ResMut<GameInfo>,
+    mut camera: Single<(&Transform, &mut PostProcessSettings), With<Camera3d>>,
+    mut save_task: ResMut<SaveTask>,
+    persistent_world: Option<ResMut<Persistent<SavedWorld>>>,
+    player: Query<(&Transform, &Player, &Health, &Inventory)>,
+    overlays: Query<(Entity, &Visibility), Or<((With<Node>, Without<ChildOf>), With<HeldBlock>)>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keybindings: Res<Persistent<KeyBindings>>,
+) {
+    // borrowchecker...
+    if keybindings.just_pressed(&keyboard, Action::SaveWorld)
+        && let Some(mut persistent_world) = persistent_world
+        && !save_game_in_background(
+            &mut persistent_world,
+            &mut save_task,
+            &player,
+            Some(camera.0),
+            &game_info,
+        )
+    {
+        toast(&mut commands, "Still saving");
+    }
+    if keybindings.just_pressed(&keyboard, Action::Screenshot) {
+        take_screenshot(
+            &mut commands,
+            &game_info.settings,
+            keyboard
+                .any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+                .then_some(overlays),
+        );
+    }
+    if keybindings.just_pressed(&keyboard, Action::ToggleDebug) {
+        game_info.settings.debug_menus = !game_info.settings.debug_menus;
+    }
+    if keybindings.just_pressed(&keyboard, Action::ToggleHitboxes) {
+        game_info.settings.hitboxes = !game_info.settings.hitboxes;
+    }
+    if keybindings.just_pressed(&keyboard, Action::ToggleChunkBorders) {
+        game_info.settings.chunk_borders = !game_info.settings.chunk_borders;
+    }
+    if keybindings.just_pressed(&keyboard, Action::ToggleMinimap) {
+        game_info.settings.minimap = !game_info.settings.minimap;
+    }
+    if keybindings.just_pressed(&keyboard, Action::CyclePostProcess) {
+        camera.1.sss += 1;
+        if camera.1.sss > 8 {
+            camera.1.sss = 0;
+        }
+    }
+    if keybindings.just_pressed(&keyboard, Action::ToggleWireframe) {
+        wireframe_config.global = !wireframe_config.global;
+    }
+    if keybindings.just_pressed(&keyboard, Action::RotateBlock) {
+        // automatic, then every direction in turn
+        game_info.placement_direction = match game_info.placement_direction {
+            None => Some(Direction::ALL[0]),
+            Some(direction) => Direction::ALL
+                .iter()
+                .position(|&d| d == direction)
+                .and_then(|i| Direction::ALL.get(i + 1).copied()),
+        };
+    }
+    if keybindings.just_pressed(&keyboard, Action::ToggleFullscreen) {
+        primary_window.mode = if primary_window.mode == WindowMode::Windowed {
+            WindowMode::BorderlessFullscreen(MonitorSelection::Current)
+        } else {
+            WindowMode::Windowed
+        }
+    }
+    // number keys pick the first nine slots
+    let slots = hotbar_slots(&game_info.settings);
+    for (key, &kind) in DIGIT_KEYS.iter().zip(&slots) {
+        if keyboard.just_pressed(*key) {
+            game_info.current_block = kind;
+        }
+    }
+}
+
+const SPRINT_FOV_BOOST: f32 = 8.0;
+const FOV_TRANSITION: f32 = 0.15; // seconds
+
+// separate from the keybinds so fov changes from the settings menu show up while paused
+fn handle_fov(
+    mut projection: Single<&mut Projection, With<Camera3d>>,
+    mut current_fov: Local<Option<f32>>,
+    player: Query<&Player>,
+    game_info: Res<GameInfo>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keybindings: Res<Persistent<KeyBindings>>,
+    time: Res<Time>,
+) {
+    let base_fov = game_info.settings.fov as f32;
+    let target_fov = if !game_info.paused
+        && !game_info.chat_open
+        && keybindings.pressed(&keyboard, Action::Zoom)
+    {
+        10.0
+    } else if player.single().is_ok_and(|player| player.sprinting) {
+        base_fov + SPRINT_FOV_BOOST
+    } else {
+        base_fov
+    };
+
+    let fov = current_fov.get_or_insert(target_fov);
+    // ~95% of the way there after FOV_TRANSITION
+    *fov += (target_fov - *fov) * (1.0 - (-3.0 * time.delta_secs() / FOV_TRANSITION).exp());
+
+    **projection = Projection::Perspective(PerspectiveProjection {
+        fov: fov.to_radians(),
+        ..default()
+    });
+}
+
+// tints the screen while the camera is in water, handle_fog thickens the fog
+fn handle_submerged(
+    camera: Single<(&GlobalTransform, &mut PostProcessSettings), With<Camera3d>>,
+    game_info: Res<GameInfo>,
+) {
+    let (transform, mut settings) = camera.into_inner();
+
+    let submerged = get_block(&game_info, transform.translation())
+        .is_some_and(|block| block.kind == BlockKind::Water);
+    if submerged != (settings.submerged != 0) {
+        settings.submerged = submerged as u32;
+    }
+}
+
+// 0.0 at midnight, 1.0 at noon. singleplayer has no day cycle yet, it's always noon there
+fn daylight(game_info: &GameInfo) -> f32 {
+    game_info
+        .time_of_day
+        .map_or(1.0, |time| ((time - 0.25) * TAU).sin() * 0.5 + 0.5)
+}
+
+const DAY_SKYBOX_BRIGHTNESS: f32 = 1000.0;
+const NIGHT_SKYBOX_BRIGHTNESS: f32 = 30.0;
+
+// the sky dims with the time of day, brightness is in cd/m²
+fn handle_skybox(mut skybox: Single<&mut Skybox, With<Camera3d>>, game_info: Res<GameInfo>) {
+    let brightness = NIGHT_SKYBOX_BRIGHTNESS.lerp(DAY_SKYBOX_BRIGHTNESS, daylight(&game_info));
+    if (skybox.brightness - brightness).abs() > 0.1 {
+        skybox.brightness = brightness;
+    }
+}
+
+const DAY_FOG_COLOR: Color = Color::srgb(0.62, 0.76, 0.95);
+const NIGHT_FOG_COLOR: Color = Color::srgb(0.02, 0.03, 0.08);
+const WATER_FOG_COLOR: Color = Color::srgb(0.05, 0.25, 0.35);
+const WATER_FOG_END: f32 = 24.0;
+
+// hides the edge of the loaded terrain, underwater fog takes over when it's closer
+fn handle_fog(
+    mut commands: Commands,
+    camera: Single<(Entity, &PostProcessSettings, Option<&mut DistanceFog>), With<Camera3d>>,
+    game_info: Res<GameInfo>,
+) {
+    let (entity, post_process, fog) = camera.into_inner();
+    let settings = &game_info.settings;
+
+    // the last ring of chunks is often still loading, fade out before it
+    let far = (settings.render_distance - 1).max(1) as f32 * CHUNK_SIZE as f32;
+    let distance_fog = settings.fog.then(|| {
+        (
+            NIGHT_FOG_COLOR.mix(&DAY_FOG_COLOR, daylight(&game_info)),
+            far * (1.0 - settings.fog_density.clamp(0.0, 1.0)),
+            far,
+        )
+    });
+    let water_fog = (post_process.submerged != 0).then_some((WATER_FOG_COLOR, 1.0, WATER_FOG_END));
+
+    // color, start, end
+    let wanted = match (distance_fog, water_fog) {
+        (Some(distance), Some(water)) if distance.2 < water.2 => Some(distance),
+        (_, Some(water)) => Some(water),
+        (distance, None) => distance,
+    };
+
+    match (wanted, fog) {
+        (Some((color, start, end)), Some(mut fog)) => {
+            fog.color = color;
+            fog.falloff = FogFalloff::Linear { start, end };
+        }
+        (Some((color, start, end)), None) => {
+            commands.entity(entity).insert(DistanceFog {
+                color,
+                falloff: FogFalloff::Linear { start, end },
+                ..default()
+            });
+        }
+        (None, Some(_)) => {
+            commands.entity(entity).remove::<DistanceFog>();
+        }
+        (None, None) => {}
+    }
+}
+
+fn handle_gizmos(
+    mut gizmos: Gizmos,
+    player: Single<&Transform, With<Player>>,
+    game_info: Res<GameInfo>,
+    game_entities: Query<(Entity, &GameEntity)>,
+    chunk_bounds: Query<(&GlobalTransform, &Aabb, &ViewVisibility), With<ChunkMarker>>,
+) {
+    if game_info.settings.hitboxes {
+        for (_, entity) in game_entities {
+            let scale = entity.kind.size();
+            gizmos.cuboid(
+                Transform::from_translation(entity.pos + scale / 2.0)
+                    .with_scale(scale)
+                    .with_rotation(Quat::from_rotation_y(entity.rot)),
+                Color::srgb(1.0, 1.0, 1.0),
+            );
+        }
+    }
+
+    if game_info.settings.chunk_borders {
+        let player = player.translation.floor();
+        let chunk_size = CHUNK_SIZE as f32;
+        let mut chunk_size_vec = vec2(chunk_size, chunk_size);
+        let chunk_pos = vec3(
+            player.x.div_euclid(chunk_size) * chunk_size + chunk_size / 2.0,
+            CHUNK_HEIGHT as f32 / 2.0,
+            player.z.div_euclid(chunk_size) * chunk_size + chunk_size / 2.0,
+        );
+        for y in (0..CHUNK_HEIGHT).step_by(CHUNK_SIZE as usize) {
+            if y == CHUNK_HEIGHT - CHUNK_SIZE {
+                chunk_size_vec.y -= 1.0;
+            }
+            gizmos.rect(
+                Isometry3d::from_translation(
+                    chunk_pos.with_y(y as f32 + chunk_size_vec.y / 2.0)
+                        + Vec3::Z * chunk_size_vec.x / 2.0,
+                ),
+                chunk_size_vec,
+                Color::srgb(0.0, 1.0, 0.0),
+            );
+            gizmos.rect(
+                Isometry3d::from_translation(
+                    chunk_pos.with_y(y as f32 + chunk_size_vec.y / 2.0)
+                        - Vec3::Z * chunk_size_vec.x / 2.0,
+                ),
+                chunk_size_vec,
+                Color::srgb(0.0, 1.0, 0.0),
+            );
+            gizmos.rect(
+                Isometry3d::new(
+                    chunk_pos.with_y(y as f32 + chunk_size_vec.y / 2.0)
+                        + Vec3::X * chunk_size_vec.x / 2.0,
+                    Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+                ),
+                chunk_size_vec,
+                Color::srgb(0.0, 1.0, 0.0),
+            );
+            gizmos.rect(
+                Isometry3d::new(
+                    chunk_pos.with_y(y as f32 + chunk_size_vec.y / 2.0)
+                        - Vec3::X * chunk_size_vec.x / 2.0,
+                    Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+                ),
+                chunk_size_vec,
+                Color::srgb(0.0, 1.0, 0.0),
+            );
+        }
+
+        // the bounds frustum culling actually uses
+        for (transform, aabb, visibility) in chunk_bounds {
+            if !visibility.get() {
+                continue;
+            }
+            gizmos.cuboid(
+                Transform::from_translation(transform.transform_point(aabb.center.into()))
+                    .with_scale((aabb.half_extents * 2.0).into()),
+                Color::srgb(1.0, 1.0, 0.0),
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::render_resource::{Extent3d, TextureDimension, TextureFormat},
+};
+use ferriscraft::BlockKind;
+
+use crate::{
+    CHUNK_HEIGHT, GameInfo, HiddenForScreenshot, player::Player, ui::GameState,
+    utils::vec3_to_index, world::utils::chunk_coords,
+};
+
+const MAP_PIXELS: u32 = 96;
+const BLOCKS_PER_PIXEL: i32 = 2;
+const MAP_SCALE: f32 = 2.0; // screen pixels per map pixel
+const REFRESH_INTERVAL: f32 = 1.0; // seconds between terrain samples
+const UNLOADED_COLOR: [u8; 4] = [20, 20, 20, 255];
+const ARROW_COLOR: [u8; 4] = [230, 40, 40, 255];
+
+pub struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_minimap.run_if(not(in_state(GameState::Menu))),
+        );
+    }
+}
+
+#[derive(Component)]
+struct Minimap {
+    terrain: Vec<[u8; 4]>, // last sampled colors, without the arrow
+    refresh: f32,          // seconds until the terrain is sampled again
+    yaw: f32,              // the arrow was last drawn at
+}
+
+fn update_minimap(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    minimap: Option<
+        Single<(
+            &mut Minimap,
+            &ImageNode,
+            &mut Visibility,
+            Has<HiddenForScreenshot>,
+        )>,
+    >,
+    player: Single<&Transform, With<Player>>,
+    game_info: Res<GameInfo>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    let Some(minimap) = minimap else {
+        let image = Image::new_fill(
+            Extent3d {
+                width: MAP_PIXELS,
+                height: MAP_PIXELS,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &UNLOADED_COLOR,
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::MAIN_WORLD | RenderAssetUsages::RENDER_WORLD,
+        );
+        commands.spawn((
+            Minimap {
+                terrain: vec![UNLOADED_COLOR; (MAP_PIXELS * MAP_PIXELS) as usize],
+                refresh: 0.0,
+                yaw: f32::NAN,
+            },
+            ImageNode::new(images.add(image)),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(5.0),
+                right: Val::Px(5.0),
+                width: Val::Px(MAP_PIXELS as f32 * MAP_SCALE),
+                height: Val::Px(MAP_PIXELS as f32 * MAP_SCALE),
+                border: UiRect::all(Val::Px(2.0)),
+                ..default()
+            },
+            BorderColor(Color::BLACK),
+            StateScoped(*game_state.get()),
+        ));
+        return;
+    };
+    let (mut minimap, image_node, mut visibility, hidden_for_screenshot) = minimap.into_inner();
+
+    if !hidden_for_screenshot {
+        visibility.set_if_neq(if game_info.settings.minimap {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        });
+    }
+    if !game_info.settings.minimap {
+        return;
+    }
+
+    minimap.refresh -= time.delta_secs();
+    let resampled = minimap.refresh <= 0.0;
+    if resampled {
+        minimap.refresh = REFRESH_INTERVAL;
+        sample_terrain(&mut minimap.terrain, player.translation, &game_info);
+    }
+
+    let (yaw, _, _) = player.rotation.to_euler(EulerRot::YXZ);
+    if !resampled && yaw == minimap.yaw {
+        return;
+    }
+    minimap.yaw = yaw;
+
+    let Some(data) = images
+        .get_mut(&image_node.image)
+        .and_then(|image| image.data.as_mut())
+    else {
+        return;
+    };
+    data.copy_from_slice(minimap.terrain.as_flattened());
+    draw_arrow(data, (player.rotation * Vec3::NEG_Z).xz());
+}
+
+// north (-z) up, the player in the middle
+fn sample_terrain(terrain: &mut [[u8; 4]], center: Vec3, game_info: &GameInfo) {
+    let chunks = game_info.chunks.read().unwrap();
+    let center = center.floor().as_ivec3();
+    let half = MAP_PIXELS as i32 / 2;
+
+    for (i, pixel) in terrain.iter_mut().enumerate() {
+        let x = center.x + (i as i32 % MAP_PIXELS as i32 - half) * BLOCKS_PER_PIXEL;
+        let z = center.z + (i as i32 / MAP_PIXELS as i32 - half) * BLOCKS_PER_PIXEL;
+        let (chunk_pos, local_pos) = chunk_coords(ivec3(x, 0, z));
+        let (local_x, local_z) = (local_pos.x, local_pos.z);
+
+        *pixel = chunks
+            .get(&chunk_pos)
+            .and_then(|chunk| {
+                (0..CHUNK_HEIGHT).rev().find_map(|y| {
+                    let block = chunk.blocks[vec3_to_index(ivec3(local_x, y, local_z))];
+                    (block.kind != BlockKind::Air).then_some((block.kind, y))
+                })
+            })
+            .map_or(UNLOADED_COLOR, |(kind, y)| block_color(kind, y));
+    }
+}
+
+// higher ground is drawn lighter so hills stand out
+fn block_color(kind: BlockKind, y: i32) -> [u8; 4] {
+    let color = match kind {
+        BlockKind::Grass => Srgba::rgb(0.35, 0.6, 0.25),
+        BlockKind::Leaf => Srgba::rgb(0.2, 0.45, 0.15),
+        BlockKind::Dirt => Srgba::rgb(0.45, 0.32, 0.2),
+        BlockKind::Sand => Srgba::rgb(0.85, 0.8, 0.55),
+        BlockKind::Water => Srgba::rgb(0.2, 0.35, 0.75),
+        BlockKind::Snow => Srgba::rgb(0.95, 0.95, 0.97),
+        BlockKind::Wood | BlockKind::Plank => Srgba::rgb(0.5, 0.38, 0.22),
+        BlockKind::Bedrock => Srgba::rgb(0.15, 0.15, 0.15),
+        BlockKind::Glowstone => Srgba::rgb(0.9, 0.7, 0.35),
+        BlockKind::Stone | BlockKind::CoalOre | BlockKind::IronOre | BlockKind::Air => {
+            Srgba::rgb(0.5, 0.5, 0.5)
+        }
+    };
+    let shade = 0.7 + 0.6 * y as f32 / CHUNK_HEIGHT as f32;
+    Srgba::rgb(
+        (color.red * shade).min(1.0),
+        (color.green * shade).min(1.0),
+        (color.blue * shade).min(1.0),
+    )
+    .to_u8_array()
+}
+
+// small triangle in the middle pointing where the player looks
+fn draw_arrow(data: &mut [u8], forward: Vec2) {
+    let forward = forward.normalize_or(Vec2::NEG_Y);
+    let right = forward.perp();
+    let center = Vec2::splat(MAP_PIXELS as f32 / 2.0);
+    let corners = [
+        center + forward * 5.0,
+        center - forward * 3.0 + right * 3.5,
+        center - forward * 3.0 - right * 3.5,
+    ];
+
+    for y in MAP_PIXELS / 2 - 6..MAP_PIXELS / 2 + 6 {
+        for x in MAP_PIXELS / 2 - 6..MAP_PIXELS / 2 + 6 {
+            let p = vec2(x as f32, y as f32) + 0.5;
+            // inside when it's on the same side of every edge
+            let sides = [0, 1, 2].map(|i| {
+                let (a, b) = (corners[i], corners[(i + 1) % 3]);
+                (b - a).perp_dot(p - a) >= 0.0
+            });
+            if sides.iter().all(|&side| side) || sides.iter().all(|&side| !side) {
+                let i = (y * MAP_PIXELS + x) as usize * 4;
+                data[i..i + 4].copy_from_slice(&ARROW_COLOR);
+            }
+        }
+    }
+}
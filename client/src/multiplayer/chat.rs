@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use bevy_renet::renet::RenetClient;
+use ferriscraft::{ClientPacket, Persistent};
+
+use crate::{
+    GameInfo,
+    keybindings::{Action, KeyBindings},
+    ui::TextBox,
+};
+
+const CHAT_HISTORY: usize = 50;
+const CHAT_VISIBLE_LINES: usize = 10;
+const CHAT_FADE_DELAY: f32 = 5.0;
+const CHAT_FADE_DURATION: f32 = 1.0;
+
+#[derive(Resource, Default)]
+pub struct Chat {
+    pub lines: VecDeque<(String, f32)>, // message, received at
+    pub scroll: usize,
+}
+
+impl Chat {
+    pub fn push(&mut self, message: String, time: &Time) {
+        if self.lines.len() == CHAT_HISTORY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back((message, time.elapsed_secs()));
+    }
+}
+
+#[derive(Component)]
+pub struct ChatLine(usize);
+
+#[derive(Component)]
+pub struct ChatInput;
+
+pub fn handle_chat_input(
+    mut game_info: ResMut<GameInfo>,
+    mut chat: ResMut<Chat>,
+    mut input: Single<(&mut TextBox, &mut Text)>,
+    mut input_node: Single<&mut Node, With<ChatInput>>,
+    client: ResMut<RenetClient>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keybindings: Res<Persistent<KeyBindings>>,
+) {
+    let (textbox, text) = &mut *input;
+
+    // selecting a frame late so the key that opened the chat doesn't get typed
+    if game_info.chat_open && !textbox.selected {
+        textbox.selected = true;
+        input_node.display = Display::Flex;
+    } else if !game_info.chat_open && textbox.selected {
+        textbox.selected = false;
+        textbox.current_value.clear();
+        text.0.clear();
+        input_node.display = Display::None;
+        chat.scroll = 0;
+    }
+
+    if !game_info.chat_open {
+        if !game_info.paused && keybindings.just_pressed(&keyboard, Action::Chat) {
+            game_info.chat_open = true;
+        }
+    } else if keyboard.just_pressed(KeyCode::Enter) {
+        let message = textbox.current_value.trim();
+        if !message.is_empty() {
+            ClientPacket::ChatMessage(message.to_string()).send(Some(client));
+        }
+        game_info.chat_open = false;
+    }
+}
+
+pub fn update_chat_lines(
+    mut chat: ResMut<Chat>,
+    mut mouse_scroll: EventReader<MouseWheel>,
+    lines: Query<(&ChatLine, &mut Text, &mut TextColor, &mut Visibility), Without<TextBox>>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+) {
+    if game_info.chat_open {
+        let max_scroll = chat.lines.len().saturating_sub(CHAT_VISIBLE_LINES);
+        for ev in mouse_scroll.read() {
+            chat.scroll =
+                (chat.scroll as i32 + ev.y.signum() as i32).clamp(0, max_scroll as i32) as usize;
+        }
+    }
+
+    let end = chat.lines.len() - chat.scroll;
+
+    for (line, mut text, mut color, mut visibility) in lines {
+        // last slot shows the newest line
+        let Some((message, received_at)) = (end + line.0)
+            .checked_sub(CHAT_VISIBLE_LINES)
+            .and_then(|i| chat.lines.get(i))
+        else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+
+        let alpha = if game_info.chat_open {
+            1.0
+        } else {
+            let age = time.elapsed_secs() - received_at;
+            (1.0 - (age - CHAT_FADE_DELAY) / CHAT_FADE_DURATION).clamp(0.0, 1.0)
+        };
+
+        if text.0 != *message {
+            text.0 = message.clone();
+        }
+        color.0 = color.0.with_alpha(alpha);
+        *visibility = if alpha > 0.0 {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+pub fn chat_bundle(ui: Entity) -> impl Bundle {
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(5.0),
+            bottom: Val::Px(5.0),
+            width: Val::Px(500.0),
+            flex_direction: FlexDirection::Column,
+            ..default()
+        },
+        ChildOf(ui),
+    )
+}
+
+pub fn chat_line(chat: Entity, idx: usize) -> impl Bundle {
+    (
+        Text::default(),
+        TextFont {
+            font_size: 18.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.9, 0.9, 0.9)),
+        TextShadow::default(),
+        ChatLine(idx),
+        Visibility::Hidden,
+        ChildOf(chat),
+    )
+}
+
+pub fn chat_input(chat: Entity) -> impl Bundle {
+    (
+        Node {
+            display: Display::None,
+            padding: UiRect::all(Val::Px(4.0)),
+            margin: UiRect::top(Val::Px(4.0)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.5)),
+        ChatInput,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextBox {
+                selected: false,
+                current_value: String::new(),
+                placeholder: String::new(),
+                name: "Chat".to_string(),
+            },
+        )],
+        ChildOf(chat),
+    )
+}
+
+pub fn spawn_chat(commands: &mut Commands, ui: Entity) {
+    let chat = commands.spawn(chat_bundle(ui)).id();
+    for i in 0..CHAT_VISIBLE_LINES {
+        commands.spawn(chat_line(chat, i));
+    }
+    commands.spawn(chat_input(chat));
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashSet, net::UdpSocket, time::SystemTime};
+use std::{f32::consts::PI, net::UdpSocket, sync::Arc, time::SystemTime};
 
 use bevy::{
     core_pipeline::{Skybox, bloom::Bloom, experimental::taa::TemporalAntiAliasing},
@@ -14,32 +14,71 @@ use bevy_renet::{
     },
     renet::{ConnectionConfig, DefaultChannel, DisconnectReason, RenetClient},
 };
-use ferriscraft::{BlockKind, CHUNK_SIZE, ClientPacket, ServerPacket};
+use ferriscraft::{
+    BlockKind, ClientPacket, GameEntity, ServerPacket, WorldGenConfig, terrain::get_noise_functions,
+};
 use iyes_perf_ui::prelude::PerfUiAllEntries;
 
 use crate::{
-    GameInfo,
-    player::{OnlinePlayer, camera_bundle, player_bundle},
+    GameInfo, PLAYER_MODEL,
+    multiplayer::chat::{Chat, handle_chat_input, spawn_chat, update_chat_lines},
+    player::{Health, Inventory, OnlinePlayer, Player, camera_bundle, player_bundle},
     render_pipeline::PostProcessSettings,
-    ui::{GameState, MenuState, coords_bundle, hotbar_block, hotbar_bundle, root_ui_bundle},
-    utils::{get_noise_functions, set_cursor_grab},
-    world::{
-        ChunkMarker,
-        utils::{place_block, update_chunks},
+    ui::{
+        GameState, MenuState, coords_bundle, crosshair_bundle, root_ui_bundle, spawn_hotbar, toast,
     },
+    utils::set_cursor_grab,
+    world::{ChunkMarker, DirtyChunks, utils::place_block},
 };
 
+mod chat;
+
+// mob mirrored from the server
+#[derive(Component)]
+pub struct NetworkEntity(pub u64);
+
+// remote players are drawn between the last two updates the server sent
+#[derive(Component)]
+struct PlayerMotion {
+    from: (Vec3, f32), // pos, yaw
+    to: (Vec3, f32),
+    received: f32, // elapsed seconds when `to` arrived
+    interval: f32, // seconds between the last two updates
+}
+
+// drawn until the player model is loaded, and for good if it fails to
+#[derive(Component)]
+struct PlayerCapsule;
+
+// label shown until the server sends ConnectionInfo
+#[derive(Component)]
+struct Connecting(f32); // seconds waited
+
+const CONNECTION_TIMEOUT: f32 = 10.0;
+
 pub struct MultiplayerPlugin;
 
 impl Plugin for MultiplayerPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.add_plugins((RenetClientPlugin, NetcodeClientPlugin))
             .add_event::<ClientEvent>()
+            .init_resource::<Chat>()
             .add_systems(OnEnter(GameState::MultiPlayer), setup)
             .add_systems(OnExit(GameState::MultiPlayer), cleanup)
             .add_systems(
                 Update,
-                (client_event_handler, send_client_data, receive_server_data)
+                (
+                    client_event_handler,
+                    handle_connecting,
+                    send_client_data,
+                    send_health,
+                    send_inventory,
+                    receive_server_data,
+                    interpolate_players,
+                    swap_player_capsules,
+                    handle_chat_input,
+                    update_chat_lines,
+                )
                     .run_if(in_state(GameState::MultiPlayer)),
             );
     }
@@ -48,6 +87,7 @@ impl Plugin for MultiplayerPlugin {
 fn cleanup(
     mut commands: Commands,
     mut game_info: ResMut<GameInfo>,
+    mut chat: ResMut<Chat>,
     transport: Option<ResMut<NetcodeClientTransport>>,
     camera: Single<Entity, With<Camera3d>>,
 ) {
@@ -56,7 +96,12 @@ fn cleanup(
     }
     game_info.chunks = default();
     game_info.saved_chunks = default();
+    game_info.unsaved_chunks = default();
     game_info.loading_chunks = default();
+    game_info.chat_open = false;
+    game_info.time_of_day = None;
+    game_info.spawn = None;
+    *chat = default();
     // idfk it doesnt properly work without doing this
     commands.entity(*camera).remove::<(
         TemporalAntiAliasing,
@@ -64,13 +109,22 @@ fn cleanup(
         Skybox,
         Bloom,
         ScreenSpaceAmbientOcclusion,
+        DistanceFog,
         ChildOf,
     )>();
     commands.remove_resource::<RenetClient>();
     commands.remove_resource::<NetcodeClientTransport>();
 }
 
-fn setup(mut commands: Commands, game_info: Res<GameInfo>) {
+fn setup(mut commands: Commands, mut game_info: ResMut<GameInfo>) {
+    // whatever went wrong last time is stale now
+    game_info.ui_err = None;
+    let ui = commands
+        .spawn(root_ui_bundle())
+        .insert(StateScoped(GameState::MultiPlayer))
+        .id();
+    commands.spawn((Connecting(0.0), Text::new("Connecting"), ChildOf(ui)));
+
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("system clock is wrong");
@@ -101,8 +155,11 @@ fn setup(mut commands: Commands, game_info: Res<GameInfo>) {
 
 #[derive(Event)]
 pub enum ClientEvent {
-    Connected(u32, Vec3), // seed, pos
+    // seed, pos, generation, creative, health, inventory
+    Connected(u32, Vec3, WorldGenConfig, bool, u32, Vec<u32>),
     Disconnected(DisconnectReason),
+    Rejected(String), // reason
+    Kicked(String),   // reason
 }
 
 fn client_event_handler(
@@ -114,20 +171,44 @@ fn client_event_handler(
     mut game_state: ResMut<NextState<GameState>>,
     mut menu_state: ResMut<NextState<MenuState>>,
     camera: Single<(Entity, &mut Camera3d)>,
+    connecting: Query<&ChildOf, With<Connecting>>,
     asset_server: Res<AssetServer>,
 ) {
     for event in client_events.read() {
         match event {
-            ClientEvent::Disconnected(_reason) => {
-                info!("Disconnected from the server");
+            ClientEvent::Disconnected(reason) => {
+                info!("Disconnected from the server: {reason}");
+                // leaving on purpose isn't an error
+                game_info.ui_err = match reason {
+                    DisconnectReason::DisconnectedByClient => None,
+                    reason => Some(format!("Disconnected: {reason}")),
+                };
+                game_state.set(GameState::Menu);
+                menu_state.set(MenuState::MultiPlayer);
+            }
+            ClientEvent::Rejected(reason) => {
+                info!("Server refused the connection: {reason}");
+                game_info.ui_err = Some(reason.clone());
+                game_state.set(GameState::Menu);
+                menu_state.set(MenuState::MultiPlayer);
+            }
+            ClientEvent::Kicked(reason) => {
+                info!("Kicked from the server: {reason}");
+                game_info.ui_err = Some(format!("Kicked: {reason}"));
                 game_state.set(GameState::Menu);
                 menu_state.set(MenuState::MultiPlayer);
             }
-            &ClientEvent::Connected(seed, pos) => {
+            &ClientEvent::Connected(seed, pos, world_gen, creative, health, ref inventory) => {
                 info!("Connected to server");
+                for child_of in connecting {
+                    commands.entity(child_of.parent()).despawn();
+                }
 
-                game_info.noises = get_noise_functions(seed);
+                game_info.noises = get_noise_functions(seed, world_gen);
+                game_info.seed = seed;
                 game_info.current_block = BlockKind::Stone;
+                game_info.creative = creative;
+                game_info.ui_err = None;
 
                 set_cursor_grab(&mut window, true);
 
@@ -156,16 +237,21 @@ fn client_event_handler(
                         pos,
                         player_velocity,
                         player_yaw,
+                        health,
+                        game_info.spawn,
                         &game_info.noises,
                     ))
-                    .insert(StateScoped(GameState::MultiPlayer))
+                    .insert((
+                        StateScoped(GameState::MultiPlayer),
+                        Inventory::from_counts(inventory),
+                    ))
                     .id();
 
                 commands
                     .entity(camera.0)
                     .remove::<Camera3d>()
                     .insert(camera_bundle(
-                        asset_server.load("skybox.ktx2"),
+                        game_info.skybox.clone(),
                         player,
                         player_pitch,
                     ));
@@ -180,48 +266,92 @@ fn client_event_handler(
                     .id();
 
                 commands.spawn(coords_bundle(ui));
+                commands.spawn(crosshair_bundle(ui));
+                spawn_chat(&mut commands, ui);
 
-                let hotbar = commands.spawn(hotbar_bundle(ui)).id();
-
-                let node = ImageNode::new(asset_server.load("atlas.png"));
-
-                for i in 1..=10 {
-                    if i == BlockKind::Water as u8 {
-                        continue;
-                    }
-
-                    commands.spawn(hotbar_block(hotbar, node.clone(), i));
-                }
+                spawn_hotbar(
+                    &mut commands,
+                    ui,
+                    asset_server.load("atlas.png"),
+                    &game_info.settings,
+                );
             }
         }
     }
     // let &SavedWorld(seed, _, ref saved_chunks) = persistent_world.get();
 }
 
-fn send_client_data(
-    mut client_event: EventWriter<ClientEvent>,
-    client: ResMut<RenetClient>,
-    keyboard: Res<ButtonInput<KeyCode>>,
+fn handle_connecting(
+    connecting: Single<(&mut Text, &mut Connecting)>,
+    mut transport: ResMut<NetcodeClientTransport>,
+    mut game_info: ResMut<GameInfo>,
+    mut game_state: ResMut<NextState<GameState>>,
+    mut menu_state: ResMut<NextState<MenuState>>,
+    time: Res<Time>,
 ) {
+    let (mut text, mut connecting) = connecting.into_inner();
+    connecting.0 += time.delta_secs();
+    if connecting.0 > CONNECTION_TIMEOUT {
+        transport.disconnect();
+        game_info.ui_err = Some("Connection timed out".into());
+        game_state.set(GameState::Menu);
+        menu_state.set(MenuState::MultiPlayer);
+        return;
+    }
+    // moving dots so it doesn't look frozen
+    text.0 = format!("Connecting{}", ".".repeat(connecting.0 as usize % 4));
+}
+
+fn send_client_data(mut client_event: EventWriter<ClientEvent>, client: ResMut<RenetClient>) {
     if client.is_disconnected() {
         client_event.write(ClientEvent::Disconnected(
             client.disconnect_reason().unwrap(),
         ));
     }
-    if keyboard.just_pressed(KeyCode::KeyT) {
-        ClientPacket::ChatMessage("shice".into()).send(Some(client));
+}
+
+// the server keeps it for the next time the player joins
+fn send_health(
+    client: Option<ResMut<RenetClient>>,
+    health: Option<Single<&Health, (With<Player>, Changed<Health>)>>,
+) {
+    if let Some(health) = health {
+        ClientPacket::Health(health.0).send(client);
+    }
+}
+
+// same as the health, placing and breaking blocks change it
+fn send_inventory(
+    client: Option<ResMut<RenetClient>>,
+    inventory: Option<Single<&Inventory, (With<Player>, Changed<Inventory>)>>,
+) {
+    if let Some(inventory) = inventory {
+        ClientPacket::Inventory(inventory.counts()).send(client);
     }
 }
 
 fn receive_server_data(
     mut commands: Commands,
     mut client: ResMut<RenetClient>,
-    mut players: Query<(Entity, &mut Transform, &OnlinePlayer), Without<ChunkMarker>>,
+    mut players: Query<
+        (Entity, &Transform, &OnlinePlayer, &mut PlayerMotion),
+        Without<ChunkMarker>,
+    >,
+    mut player_visibility: Query<(&mut Visibility, &OnlinePlayer)>,
+    mut local_player: Option<
+        Single<(&mut Transform, &mut Player), (Without<OnlinePlayer>, Without<NetworkEntity>)>,
+    >,
+    mut entities: Query<
+        (Entity, &NetworkEntity, &mut GameEntity, &mut Transform),
+        (Without<OnlinePlayer>, Without<ChunkMarker>),
+    >,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut client_event: EventWriter<ClientEvent>,
-    chunks: Query<(Entity, &Transform), With<ChunkMarker>>,
-    game_info: Res<GameInfo>,
+    mut chat: ResMut<Chat>,
+    mut game_info: ResMut<GameInfo>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    time: Res<Time>,
     // transport: Res<NetcodeClientTransport>,
 ) {
     while let Some(message) = client.receive_message(DefaultChannel::ReliableOrdered) {
@@ -231,101 +361,216 @@ fn receive_server_data(
         match packet {
             ServerPacket::ChatMessage(player, message) => {
                 println!("[{player}] {message}");
+                chat.push(format!("[{player}] {message}"), &time);
             }
             ServerPacket::PlayerConnected(player, _pos) => {
                 println!("{player} joined the server");
+                chat.push(format!("{player} joined the server"), &time);
                 // if id != transport.client_id() {
 
                 // }
             }
             ServerPacket::PlayerDisconnected(player, reason) => {
-                if let Some((entity, _, _)) =
-                    players.iter_mut().find(|(_, _, other)| other.0 == player)
+                if let Some((entity, _, _, _)) = players
+                    .iter_mut()
+                    .find(|(_, _, other, _)| other.0 == player)
                 {
                     commands.entity(entity).despawn();
                 }
                 println!("{player} left the server: {reason}");
+                chat.push(format!("{player} left the server"), &time);
+            }
+            ServerPacket::ConnectionInfo(seed, pos, world_gen, creative, health, inventory) => {
+                client_event.write(ClientEvent::Connected(
+                    seed, pos, world_gen, creative, health, inventory,
+                ));
+            }
+            ServerPacket::ConnectionRejected(reason) => {
+                client_event.write(ClientEvent::Rejected(reason));
+            }
+            ServerPacket::Kicked(reason) => {
+                client_event.write(ClientEvent::Kicked(reason));
+            }
+            ServerPacket::Welcome(motd, online) => {
+                if !motd.is_empty() {
+                    toast(&mut commands, motd.clone());
+                    chat.push(motd, &time);
+                }
+                chat.push(
+                    format!("Players online ({}): {}", online.len(), online.join(", ")),
+                    &time,
+                );
+            }
+            ServerPacket::PlayerDied(player) => {
+                for (mut visibility, other) in &mut player_visibility {
+                    if other.0 == player {
+                        *visibility = Visibility::Hidden;
+                    }
+                }
+                println!("{player} died");
+                chat.push(format!("{player} died"), &time);
+            }
+            ServerPacket::PlayerRespawned(player) => {
+                for (mut visibility, other) in &mut player_visibility {
+                    if other.0 == player {
+                        *visibility = Visibility::Visible;
+                    }
+                }
             }
-            ServerPacket::ConnectionInfo(seed, pos) => {
-                client_event.write(ClientEvent::Connected(seed, pos));
+            ServerPacket::SpawnPoint(pos) => {
+                game_info.spawn = Some(pos);
+            }
+            // the server didn't accept a move
+            ServerPacket::Teleport(pos) => {
+                if let Some(player) = &mut local_player {
+                    player.0.translation = pos;
+                    player.1.velocity = Vec3::ZERO;
+                }
             }
             _ => {}
         }
     }
-    let mut chunks_to_update = HashSet::new();
     while let Some(message) = client.receive_message(DefaultChannel::ReliableUnordered) {
         let Ok(packet) = bincode::deserialize(&message) else {
             continue;
         };
         if let ServerPacket::ChunkUpdate(chunk_pos, chunk) = packet {
             let mut guard = game_info.chunks.write().unwrap();
-            if let Some(old_chunk) = guard.get_mut(&chunk_pos) {
-                chunks_to_update.insert(chunk_pos);
+            if let Some(old_chunk) = guard.get_mut(&chunk_pos).map(Arc::make_mut) {
                 // borrowchecker said no-no to .map()
                 let mut saved_chunks = if let Some(saved_chunks) = &game_info.saved_chunks {
                     Some(&mut *saved_chunks.write().unwrap())
                 } else {
                     None
                 };
-                for (pos, block) in chunk.blocks {
-                    if pos.x == 0 {
-                        chunks_to_update.insert(chunk_pos - IVec3::X);
-                    }
-                    if pos.x == CHUNK_SIZE - 1 {
-                        chunks_to_update.insert(chunk_pos + IVec3::X);
-                    }
-                    if pos.z == 0 {
-                        chunks_to_update.insert(chunk_pos - IVec3::Z);
-                    }
-                    if pos.z == CHUNK_SIZE - 1 {
-                        chunks_to_update.insert(chunk_pos + IVec3::Z);
-                    }
-                    place_block(old_chunk, pos, block, &mut saved_chunks, None, None);
+                let mut unsaved_chunks = game_info.unsaved_chunks.write().unwrap();
+                for (pos, block) in chunk.blocks.iter() {
+                    place_block(
+                        old_chunk,
+                        pos,
+                        block,
+                        &mut saved_chunks,
+                        &mut unsaved_chunks,
+                        None,
+                        Some(&mut dirty_chunks),
+                    );
                 }
             }
         }
     }
-    if !chunks_to_update.is_empty() {
-        update_chunks(
-            &mut commands,
-            chunks.iter().collect(),
-            chunks_to_update.into_iter().collect(),
-        );
-    }
 
     while let Some(message) = client.receive_message(DefaultChannel::Unreliable) {
         let Ok(packet) = bincode::deserialize(&message) else {
             continue;
         };
-        if let ServerPacket::PlayerData(data) = packet {
-            for (name, pos) in data {
-                if name == game_info.settings.player_name {
-                    continue;
+        match packet {
+            ServerPacket::PlayerData(data) => {
+                let now = time.elapsed_secs();
+                for (name, (pos, yaw)) in data {
+                    if name == game_info.settings.player_name {
+                        continue;
+                    }
+                    if let Some((_, transform, _, mut motion)) = players
+                        .iter_mut()
+                        .find(|(_, _, player, _)| player.0 == name)
+                    {
+                        // start from where it's drawn so a late update doesn't snap it back
+                        let (drawn_yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+                        motion.from = (transform.translation, drawn_yaw);
+                        motion.to = (pos, yaw);
+                        motion.interval = (now - motion.received).clamp(0.01, 0.5);
+                        motion.received = now;
+                    } else {
+                        commands
+                            .spawn((
+                                Transform::from_translation(pos)
+                                    .with_rotation(Quat::from_rotation_y(yaw)),
+                                PlayerMotion {
+                                    from: (pos, yaw),
+                                    to: (pos, yaw),
+                                    received: now,
+                                    interval: 0.1,
+                                },
+                                Name::new("Player ".to_string() + &name),
+                                OnlinePlayer(name.clone()),
+                                Visibility::Visible,
+                            ))
+                            .with_child((
+                                PlayerCapsule,
+                                Mesh3d(meshes.add(Capsule3d::new(0.35, 1.1))), // 2 x 0.35 + 1.1 = 1.8m height
+                                MeshMaterial3d(materials.add(Color::srgb(0.7, 0.7, 0.2))),
+                                Transform::from_translation(Vec3::Y * 0.9), // 1.8/2.0
+                            ))
+                            .with_child((
+                                BillboardText::new(name),
+                                Transform::from_translation(Vec3::Y * 2.25)
+                                    .with_scale(Vec3::splat(0.0125)),
+                            ));
+                    }
                 }
-                if let Some((_, mut transform, _)) =
-                    players.iter_mut().find(|(_, _, player)| player.0 == name)
-                {
-                    transform.translation = pos
-                } else {
-                    commands
-                        .spawn((
-                            Transform::from_translation(pos),
-                            Name::new("Player ".to_string() + &name),
-                            OnlinePlayer(name.clone()),
-                            Visibility::Visible,
-                        ))
-                        .with_child((
-                            Mesh3d(meshes.add(Capsule3d::new(0.35, 1.1))), // 2 x 0.35 + 1.1 = 1.8m height
-                            MeshMaterial3d(materials.add(Color::srgb(0.7, 0.7, 0.2))),
-                            Transform::from_translation(Vec3::Y * 0.9), // 1.8/2.0
-                        ))
-                        .with_child((
-                            BillboardText::new(name),
-                            Transform::from_translation(Vec3::Y * 2.25)
-                                .with_scale(Vec3::splat(0.0125)),
+            }
+            ServerPacket::EntityUpdate(data) => {
+                for (entity, id, _, _) in &entities {
+                    if !data.iter().any(|(other, _)| *other == id.0) {
+                        commands.entity(entity).despawn();
+                    }
+                }
+                for (id, game_entity) in data {
+                    let transform =
+                        Transform::from_translation(game_entity.pos + vec3(0.5, 0.0, 0.5))
+                            .with_scale(Vec3::splat(2.0))
+                            .with_rotation(Quat::from_rotation_y(game_entity.rot));
+                    if let Some((_, _, mut old, mut old_transform)) =
+                        entities.iter_mut().find(|(_, other, _, _)| other.0 == id)
+                    {
+                        *old = game_entity;
+                        *old_transform = transform;
+                    } else {
+                        commands.spawn((
+                            NetworkEntity(id),
+                            game_entity,
+                            SceneRoot(game_info.models[game_entity.kind as usize].clone()),
+                            transform,
+                            StateScoped(GameState::MultiPlayer),
                         ));
+                    }
                 }
             }
+            ServerPacket::TimeOfDay(time_of_day) => {
+                game_info.time_of_day = Some(time_of_day);
+            }
+            _ => {}
         }
     }
 }
+
+fn interpolate_players(mut players: Query<(&mut Transform, &PlayerMotion)>, time: Res<Time>) {
+    for (mut transform, motion) in &mut players {
+        let t = ((time.elapsed_secs() - motion.received) / motion.interval).min(1.0);
+        transform.translation = motion.from.0.lerp(motion.to.0, t);
+        // slerp takes the short way around
+        transform.rotation =
+            Quat::from_rotation_y(motion.from.1).slerp(Quat::from_rotation_y(motion.to.1), t);
+    }
+}
+
+fn swap_player_capsules(
+    mut commands: Commands,
+    capsules: Query<(Entity, &ChildOf), With<PlayerCapsule>>,
+    game_info: Res<GameInfo>,
+    asset_server: Res<AssetServer>,
+) {
+    let model = &game_info.models[PLAYER_MODEL];
+    if capsules.is_empty() || !asset_server.is_loaded_with_dependencies(model) {
+        return;
+    }
+    for (entity, child_of) in capsules {
+        commands.entity(entity).despawn();
+        commands.spawn((
+            SceneRoot(model.clone()),
+            // same scale as the mobs, gltf models face +z and the player looks down -z
+            Transform::from_scale(Vec3::splat(2.0)).with_rotation(Quat::from_rotation_y(PI)),
+            ChildOf(child_of.parent()),
+        ));
+    }
+}
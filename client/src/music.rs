@@ -0,0 +1,159 @@
+use bevy::{audio::Volume, prelude::*};
+use ferriscraft::terrain::{Biome, terrain_noise};
+
+use crate::{GameInfo, player::Player, ui::GameState};
+
+const FADE_TIME: f32 = 3.0; // seconds for a crossfade
+// how long the player has to stay in a new biome before the music follows,
+// walking along a coast shouldn't flip between tracks
+const SWITCH_DELAY: f32 = 4.0;
+
+pub struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MusicState>()
+            .add_systems(Update, (select_track, fade_tracks).chain());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Track {
+    Ocean,
+    Plains,
+    Mountains,
+    Night,
+}
+
+impl Track {
+    const ALL: [Track; 4] = [Track::Ocean, Track::Plains, Track::Mountains, Track::Night];
+
+    fn path(self) -> &'static str {
+        match self {
+            Track::Ocean => "music/ocean.wav",
+            Track::Plains => "music/plains.wav",
+            Track::Mountains => "music/mountains.wav",
+            Track::Night => "music/night.wav",
+        }
+    }
+}
+
+#[derive(Resource)]
+pub struct MusicState {
+    tracks: Vec<Handle<AudioSource>>, // indexed by Track
+    current: Option<Track>,
+    pending: Option<(Track, f32)>, // track, seconds it has been wanted for
+}
+
+impl FromWorld for MusicState {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Self {
+            tracks: Track::ALL
+                .iter()
+                .map(|track| asset_server.load(track.path()))
+                .collect(),
+            current: None,
+            pending: None,
+        }
+    }
+}
+
+// the playing track, fades towards full volume or out and gone
+#[derive(Component)]
+struct MusicTrack {
+    fade: f32, // 0.0 - 1.0
+    fading_out: bool,
+}
+
+fn select_track(
+    mut commands: Commands,
+    mut music: ResMut<MusicState>,
+    mut tracks: Query<&mut MusicTrack>,
+    player: Option<Single<&Transform, With<Player>>>,
+    game_info: Res<GameInfo>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    let wanted = match player {
+        Some(player) if game_state.get() != &GameState::Menu => {
+            // singleplayer has no day cycle yet, it's always day there
+            let night = game_info
+                .time_of_day
+                .is_some_and(|time| !(0.25..0.75).contains(&time));
+            let (_, biome) = terrain_noise(player.translation.xz(), &game_info.noises);
+            Some(if night {
+                Track::Night
+            } else {
+                match Biome::from_noise(biome) {
+                    Biome::Ocean => Track::Ocean,
+                    Biome::Plains => Track::Plains,
+                    Biome::Mountains => Track::Mountains,
+                }
+            })
+        }
+        _ => None,
+    };
+
+    if wanted == music.current {
+        music.pending = None;
+        return;
+    }
+
+    // the first track of a session and leaving the game don't wait
+    let waited = match (wanted, music.pending) {
+        (Some(track), Some((pending, waited))) if track == pending => waited + time.delta_secs(),
+        (Some(track), _) if music.current.is_some() => {
+            music.pending = Some((track, 0.0));
+            return;
+        }
+        _ => SWITCH_DELAY,
+    };
+    if waited < SWITCH_DELAY {
+        music.pending = wanted.map(|track| (track, waited));
+        return;
+    }
+
+    for mut track in &mut tracks {
+        track.fading_out = true;
+    }
+    if let Some(track) = wanted {
+        commands.spawn((
+            MusicTrack {
+                fade: 0.0,
+                fading_out: false,
+            },
+            AudioPlayer(music.tracks[track as usize].clone()),
+            PlaybackSettings::LOOP.with_volume(Volume::Linear(0.0)),
+        ));
+    }
+    music.current = wanted;
+    music.pending = None;
+}
+
+fn fade_tracks(
+    mut commands: Commands,
+    mut tracks: Query<(Entity, &mut MusicTrack, Option<&mut AudioSink>)>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+) {
+    let step = time.delta_secs() / FADE_TIME;
+    let volume = game_info.settings.volume * game_info.settings.music_volume;
+
+    for (entity, mut track, sink) in &mut tracks {
+        if track.fading_out {
+            track.fade -= step;
+            if track.fade <= 0.0 {
+                commands.entity(entity).despawn();
+                continue;
+            }
+        } else {
+            track.fade = (track.fade + step).min(1.0);
+        }
+
+        // the sink only shows up once the clip has loaded
+        if let Some(mut sink) = sink {
+            sink.set_volume(Volume::Linear(track.fade * volume));
+        }
+    }
+}
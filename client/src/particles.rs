@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+use ferriscraft::Block;
+
+use crate::{GameInfo, PausableSystems, utils::apply_gravity, world::mesher::block_mesh};
+
+const PARTICLE_COUNT: usize = 8;
+const PARTICLE_LIFETIME: f32 = 0.5;
+const PARTICLE_SIZE: f32 = 0.2;
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, animate_particles.in_set(PausableSystems));
+    }
+}
+
+#[derive(Component)]
+pub struct BlockBreakParticle {
+    pub velocity: Vec3,
+    pub lifetime: f32,
+}
+
+pub fn spawn_block_break_particles(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    game_info: &GameInfo,
+    pos: IVec3,
+    block: Block,
+) {
+    // shared by the whole burst
+    let mesh = meshes.add(block_mesh(block));
+
+    let center = pos.as_vec3() + Vec3::splat(0.5);
+
+    for _ in 0..PARTICLE_COUNT {
+        let offset = vec3(
+            rand::random_range(-0.3..0.3),
+            rand::random_range(-0.3..0.3),
+            rand::random_range(-0.3..0.3),
+        );
+        commands.spawn((
+            BlockBreakParticle {
+                velocity: offset * 6.0 + Vec3::Y * rand::random_range(2.0..4.0),
+                lifetime: PARTICLE_LIFETIME,
+            },
+            Mesh3d(mesh.clone()),
+            MeshMaterial3d(game_info.materials[0].clone()),
+            Transform::from_translation(center + offset).with_scale(Vec3::splat(PARTICLE_SIZE)),
+        ));
+    }
+}
+
+fn animate_particles(
+    mut commands: Commands,
+    particles: Query<(Entity, &mut Transform, &mut BlockBreakParticle)>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+
+    for (entity, mut transform, mut particle) in particles {
+        particle.lifetime -= delta;
+        if particle.lifetime <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        particle.velocity.y = apply_gravity(particle.velocity.y, game_info.settings.gravity, delta);
+        transform.translation += particle.velocity * delta;
+        // shrink out instead of fading, keeps the shared atlas material opaque
+        transform.scale = Vec3::splat(PARTICLE_SIZE * particle.lifetime / PARTICLE_LIFETIME);
+    }
+}
@@ -1,11 +1,17 @@
+use std::f32::consts::TAU;
+
 use crate::{
-    CHUNK_HEIGHT, CHUNK_SIZE, GameInfo, PausableSystems,
+    CHUNK_HEIGHT, DAY_SKYBOX_BRIGHTNESS, GameInfo, PausableSystems,
+    keybindings::{Action, KeyBindings},
+    particles::spawn_block_break_particles,
     render_pipeline::PostProcessSettings,
-    ui::GameState,
-    utils::{aabb_collision, ray_cast, vec3_to_index},
+    sounds::{BlockAction, SoundAssets, play_block_sound},
+    ui::{DeathScreen, GameState, death_screen},
+    utils::{aabb_collision, apply_gravity, ground_distance, ray_cast, set_cursor_grab},
+    viewmodel::HeldBlock,
     world::{
-        ChunkMarker,
-        utils::{NoiseFunctions, place_block, terrain_noise},
+        DirtyChunks,
+        utils::{chunk_coords, in_render_distance},
     },
 };
 use bevy::{
@@ -19,7 +25,10 @@ use bevy::{
     window::{CursorGrabMode, PrimaryWindow},
 };
 use bevy_renet::renet::RenetClient;
-use ferriscraft::{Block, ClientPacket};
+use ferriscraft::{
+    Block, BlockKind, ClientPacket, GameEntity, MAX_HEALTH, Persistent,
+    terrain::{NoiseFunctions, terrain_noise},
+};
 
 pub struct PlayerPlugin;
 
@@ -28,104 +37,270 @@ impl Plugin for PlayerPlugin {
         app.add_systems(
             Update,
             (camera_movement, handle_interactions).run_if(
-                not(in_state(GameState::Menu)).and(|game_info: Res<GameInfo>| !game_info.paused),
+                not(in_state(GameState::Menu))
+                    .and(player_alive)
+                    .and(|game_info: Res<GameInfo>| !game_info.paused && !game_info.chat_open),
             ),
         )
+        .add_systems(
+            Update,
+            (handle_death, view_bob).run_if(not(in_state(GameState::Menu))),
+        )
         .add_systems(
             FixedUpdate,
-            player_movement
-                .run_if(
-                    // only run if chunks have been loaded
-                    |game_info: Option<Res<GameInfo>>, mut is_loaded: Local<bool>| {
-                        if !*is_loaded && let Some(game_info) = game_info {
-                            *is_loaded = game_info.chunks.read().unwrap().len()
-                                == ((game_info.settings.render_distance * 2)
-                                    * (game_info.settings.render_distance * 2))
-                                    as usize;
-                        }
-                        *is_loaded
-                    },
-                )
+            (lift_out_of_terrain, player_movement)
+                .chain()
+                .run_if(chunks_around_player_loaded)
+                .run_if(player_alive)
                 .run_if(not(in_state(GameState::Menu)))
                 .in_set(PausableSystems),
         );
     }
 }
 
+// only run physics once the ground under the player exists
+fn chunks_around_player_loaded(
+    game_info: Res<GameInfo>,
+    player: Query<&Transform, With<Player>>,
+) -> bool {
+    let Ok(player) = player.single() else {
+        return false;
+    };
+    let (chunk_pos, _) = chunk_coords(player.translation.floor().as_ivec3());
+    // neighbours outside the render distance never get loaded
+    let render_distance = game_info.settings.render_distance;
+
+    let chunks = game_info.chunks.read().unwrap();
+    (-1..=1).all(|z| {
+        (-1..=1).all(|x| {
+            let pos = chunk_pos + ivec3(x, 0, z);
+            !in_render_distance(pos, player.translation, render_distance)
+                || chunks.contains_key(&pos)
+        })
+    })
+}
+
+// set by /tp, the target can be inside terrain that wasn't loaded when it was typed
+#[derive(Component)]
+pub struct Teleported;
+
+// once the chunks are in, moves a teleported player up to the first spot they fit
+fn lift_out_of_terrain(
+    mut commands: Commands,
+    game_info: Res<GameInfo>,
+    mut player: Query<(Entity, &mut Transform), (With<Player>, With<Teleported>)>,
+) {
+    let Ok((entity, mut transform)) = player.single_mut() else {
+        return;
+    };
+    let pos = transform.translation;
+    let blocked = |y: f32| {
+        let feet = pos.with_y(y).floor().as_ivec3();
+        let head = (y + PLAYER_SIZE.y).floor() as i32;
+        (feet.y..=head).any(|y| game_info.block_at(feet.with_y(y)).kind.is_solid())
+    };
+    let mut y = pos.y;
+    while y < CHUNK_HEIGHT as f32 && blocked(y) {
+        y = y.floor() + 1.0;
+    }
+    transform.translation.y = y;
+    commands.entity(entity).remove::<Teleported>();
+}
+
+fn player_alive(player: Query<&Health, With<Player>>) -> bool {
+    player.single().is_ok_and(|health| health.0 > 0)
+}
+
 #[derive(Component, Default, Clone, Copy)]
 pub struct Player {
     pub velocity: Vec3,
+    pub fall_distance: f32, // since the last time the player was grounded
+    pub sprinting: bool,
+    pub flight: Flight,
+    pub noclip: bool, // debug only, flies through everything in any game mode
+}
+
+// creative only, double tapping jump toggles flying
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Flight {
+    #[default]
+    Off,
+    Flying,    // no gravity, blocks still get in the way
+    Spectator, // no gravity or collisions at all
 }
 
+// blocks you can fall before taking damage
+const SAFE_FALL_DISTANCE: f32 = 3.0;
+// below this the void starts hurting
+const VOID_LEVEL: f32 = -10.0;
+const VOID_DAMAGE: u32 = 4; // every half a second
+const MOVE_SEND_RATE: f32 = 20.0; // moves sent per second at most
+const MOVE_EPSILON: f32 = 0.01; // blocks, anything less isn't sent
+const YAW_EPSILON: f32 = 0.001; // radians
+// ground rays start this far above the feet, so they still hit when sunk in a little
+const GROUND_RAY_START: f32 = 0.1;
+// how far below the feet still counts as standing, when not falling
+const GROUND_SNAP: f32 = 0.05;
+// hitbox from the feet, same size for remote players
+const PLAYER_SIZE: Vec3 = vec3(0.5, 1.8, 0.5);
+// others further than this can't be reached in a frame, not worth testing
+const ENTITY_COLLISION_RANGE: f32 = 4.0; // blocks
+const DOUBLE_TAP_TIME: f32 = 0.3; // seconds
+const FLY_SPEED_MULTIPLIER: f32 = 2.0; // of the movement speed
+const FLY_VERTICAL_SPEED: f32 = 8.0; // blocks per second
+// camera relative to the player's feet, view bob is layered on top of it
+const CAMERA_OFFSET: Vec3 = vec3(0.0, 1.62, -0.05); // minecraft way
+
+#[derive(Component)]
+pub struct Health(pub u32);
+
 #[derive(Component)]
 pub struct OnlinePlayer(pub String);
 
+// block counts indexed by BlockKind, unused in creative
+#[derive(Component, Default)]
+pub struct Inventory(pub [u32; BlockKind::ALL.len()]);
+
+impl Inventory {
+    // saves and packets keep a plain list, kinds added since are left at zero
+    pub fn from_counts(counts: &[u32]) -> Self {
+        let mut inventory = Inventory::default();
+        for (slot, &count) in inventory.0.iter_mut().zip(counts) {
+            *slot = count;
+        }
+        inventory
+    }
+
+    pub fn counts(&self) -> Vec<u32> {
+        self.0.to_vec()
+    }
+
+    pub fn count(&self, kind: BlockKind) -> u32 {
+        self.0[kind as usize]
+    }
+
+    pub fn add(&mut self, kind: BlockKind) {
+        if kind.is_solid() {
+            self.0[kind as usize] += 1;
+        }
+    }
+
+    // false if there's nothing to take
+    pub fn take(&mut self, kind: BlockKind) -> bool {
+        let count = &mut self.0[kind as usize];
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+}
+
 fn handle_interactions(
     mut commands: Commands,
     mut gizmos: Gizmos,
+    mut meshes: ResMut<Assets<Mesh>>,
     client: Option<ResMut<RenetClient>>,
     game_info: Res<GameInfo>,
-    player: Single<&Transform, With<Player>>,
+    player: Single<(&Transform, &mut Inventory), With<Player>>,
     camera: Single<&GlobalTransform, With<Camera3d>>,
-    chunks: Query<(Entity, &Transform), (With<ChunkMarker>, Without<OnlinePlayer>)>,
-    online_players: Query<&Transform, (With<OnlinePlayer>, Without<ChunkMarker>)>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    online_players: Query<&Transform, With<OnlinePlayer>>,
+    game_entities: Query<&GameEntity>,
+    mut held_block: Query<&mut HeldBlock>,
+    sounds: Res<SoundAssets>,
     mouse: Res<ButtonInput<MouseButton>>,
+    time: Res<Time>,
+    mut breaking: Local<(IVec3, f32)>, // block, seconds held
+    mut cooldown: Local<f32>,          // seconds until a held button repeats
 ) {
+    let (player, mut inventory) = player.into_inner();
+    let creative = game_info.creative;
+
+    // holding a button repeats it, the ray is cast again every frame so dragging works
+    *cooldown -= time.delta_secs();
+    let repeat = |button| mouse.just_pressed(button) || (mouse.pressed(button) && *cooldown <= 0.0);
+    let (left, right) = (repeat(MouseButton::Left), repeat(MouseButton::Right));
+    if mouse.just_pressed(MouseButton::Left) || right {
+        for mut held_block in &mut held_block {
+            held_block.swing();
+        }
+    }
+
     if let Some(hit) = ray_cast(
         &game_info,
         camera.translation(),
         (camera.rotation() * Vec3::NEG_Z).normalize_or_zero(),
-        5.0,
+        game_info.settings.reach,
     ) {
         let hit_global_position = hit.global_position;
-        let mut local_pos = hit.local_pos;
-        let mut chunk_pos = hit.chunk_pos;
 
+        // survival has to hold the button on the same block
+        let broken = if creative {
+            left
+        } else if mouse.pressed(MouseButton::Left) {
+            if breaking.0 != hit_global_position {
+                *breaking = (hit_global_position, 0.0);
+            }
+            breaking.1 += time.delta_secs();
+            breaking.1 >= hit.block.kind.break_time()
+        } else {
+            breaking.1 = 0.0;
+            false
+        };
+
+        let progress = (breaking.1 / hit.block.kind.break_time()).min(1.0);
         gizmos.cuboid(
             Transform::from_translation(hit_global_position.as_vec3() + Vec3::splat(0.5)),
-            Color::srgb(1.0, 0.0, 0.0),
+            Color::srgb(1.0, progress, 0.0),
+        );
+        // the face a placed block would attach to, inset so it doesn't overlap the outline
+        let normal = hit.normal.as_vec3();
+        gizmos.rect(
+            Isometry3d::new(
+                hit_global_position.as_vec3() + Vec3::splat(0.5) + normal * 0.501,
+                Quat::from_rotation_arc(Vec3::Z, normal),
+            ),
+            Vec2::splat(0.9),
+            Color::WHITE,
         );
 
-        if mouse.just_pressed(MouseButton::Left) {
-            if let Some(chunk) = game_info.chunks.write().unwrap().get_mut(&chunk_pos) {
-                let mut saved_chunks = if let Some(saved_chunks) = &game_info.saved_chunks {
-                    Some(&mut *saved_chunks.write().unwrap())
-                } else {
-                    None
-                };
-                place_block(
-                    chunk,
-                    local_pos,
-                    Block::AIR,
-                    &mut saved_chunks,
-                    client,
-                    Some((&mut commands, chunks.iter().collect())),
+        if broken {
+            breaking.1 = 0.0;
+            *cooldown = game_info.settings.interaction_interval;
+            if game_info.set_block(
+                hit_global_position,
+                Block::AIR,
+                client,
+                Some(&mut dirty_chunks),
+            ) {
+                if !creative {
+                    inventory.add(hit.block.kind);
+                }
+                spawn_block_break_particles(
+                    &mut commands,
+                    &mut meshes,
+                    &game_info,
+                    hit_global_position,
+                    hit.block,
+                );
+                play_block_sound(
+                    &mut commands,
+                    &sounds,
+                    &game_info,
+                    hit.block.kind,
+                    BlockAction::Break,
                 );
             }
-        } else if mouse.just_pressed(MouseButton::Right) {
-            local_pos += hit.normal.as_vec3().as_ivec3();
-
-            if local_pos.y >= 0 && local_pos.y < CHUNK_HEIGHT - 1 {
-                if local_pos.x < 0 {
-                    local_pos.x += CHUNK_SIZE;
-                    chunk_pos.x -= 1;
-                } else if local_pos.x >= CHUNK_SIZE {
-                    local_pos.x -= CHUNK_SIZE;
-                    chunk_pos.x += 1;
-                }
-
-                if local_pos.z < 0 {
-                    local_pos.z += CHUNK_SIZE;
-                    chunk_pos.z -= 1;
-                } else if local_pos.z >= CHUNK_SIZE {
-                    local_pos.z -= CHUNK_SIZE;
-                    chunk_pos.z += 1;
-                }
+        } else if right {
+            *cooldown = game_info.settings.interaction_interval;
+            let place_pos = hit_global_position + hit.normal.as_vec3().as_ivec3();
 
+            if place_pos.y >= 0 && place_pos.y < CHUNK_HEIGHT - 1 {
                 if aabb_collision(
                     player.translation,
                     vec3(0.25, 1.8, 0.25),
-                    hit_global_position.as_vec3() + hit.normal.as_vec3(),
+                    place_pos.as_vec3(),
                     vec3(1.25, 1.0, 1.25),
                 ) {
                     return;
@@ -135,41 +310,56 @@ fn handle_interactions(
                     if aabb_collision(
                         online_player.translation,
                         vec3(0.25, 1.8, 0.25),
-                        hit_global_position.as_vec3() + hit.normal.as_vec3(),
+                        place_pos.as_vec3(),
                         vec3(1.25, 1.0, 1.25),
                     ) {
                         return;
                     }
                 }
 
-                if let Some(chunk) = game_info.chunks.write().unwrap().get_mut(&chunk_pos) {
-                    if chunk.blocks[vec3_to_index(local_pos)] == Block::AIR {
-                        let mut saved_chunks = if let Some(saved_chunks) = &game_info.saved_chunks {
-                            Some(&mut *saved_chunks.write().unwrap())
-                        } else {
-                            None
-                        };
-                        place_block(
-                            chunk,
-                            local_pos,
-                            Block {
-                                kind: game_info.current_block,
-                                direction: if game_info.current_block.can_rotate() {
-                                    hit.normal
-                                } else {
-                                    Default::default()
-                                },
-                            },
-                            &mut saved_chunks,
-                            client,
-                            Some((&mut commands, chunks.iter().collect())),
+                // mobs would end up stuck inside the block
+                if game_entities.iter().any(|entity| {
+                    aabb_collision(
+                        entity.pos,
+                        entity.kind.size(),
+                        place_pos.as_vec3(),
+                        Vec3::ONE,
+                    )
+                }) {
+                    return;
+                }
+
+                let kind = game_info.current_block;
+                let block = Block {
+                    kind,
+                    direction: if kind.can_rotate() {
+                        game_info.placement_direction.unwrap_or(hit.normal)
+                    } else {
+                        Default::default()
+                    },
+                };
+                if game_info.block_at(place_pos) == Block::AIR
+                    && (creative || inventory.count(kind) > 0)
+                {
+                    if game_info.set_block(place_pos, block, client, Some(&mut dirty_chunks)) {
+                        if !creative {
+                            inventory.take(kind);
+                        }
+                        play_block_sound(
+                            &mut commands,
+                            &sounds,
+                            &game_info,
+                            kind,
+                            BlockAction::Place,
                         );
+                    } else {
+                        warn!("placing in a chunk that doesn't exist {place_pos}");
                     }
-                } else {
-                    warn!("placing in a chunk that doesn't exist {:?}", chunk_pos);
                 }
             }
         }
+    } else {
+        breaking.1 = 0.0;
     }
 }
 
@@ -199,40 +389,112 @@ fn camera_movement(
     }
 }
 
+// only touches the translation so camera_movement keeps owning the rotation
+fn view_bob(
+    mut camera: Single<&mut Transform, (With<Camera3d>, Without<Player>)>,
+    player: Single<&Player>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+    mut bob: Local<(f32, f32)>, // walk cycle phase, strength
+) {
+    let delta = time.delta_secs();
+    let speed = player.velocity.xz().length();
+    let (phase, strength) = &mut *bob;
+
+    let walking = game_info.settings.view_bob && !game_info.paused && speed > 0.1;
+    if walking {
+        *phase = (*phase + speed * delta * 2.5) % TAU;
+    }
+    // fade in and out instead of snapping when starting or stopping
+    *strength = strength.lerp(walking as u8 as f32, (delta * 8.0).min(1.0));
+
+    let amount = game_info.settings.view_bob_amount * *strength;
+    camera.translation = CAMERA_OFFSET + vec3(phase.sin() * 0.5, -phase.cos().abs(), 0.0) * amount;
+}
+
 fn player_movement(
     client: Option<ResMut<RenetClient>>,
-    player: Single<(&mut Transform, &mut Player)>,
+    player: Single<(&mut Transform, &mut Player, &mut Health)>,
     keyboard: Res<ButtonInput<KeyCode>>,
+    keybindings: Res<Persistent<KeyBindings>>,
     game_info: Res<GameInfo>,
+    online_players: Query<&Transform, (With<OnlinePlayer>, Without<Player>)>,
+    game_entities: Query<&GameEntity>,
     time: Res<Time>,
+    mut void_timer: Local<f32>,
+    mut move_throttle: Local<MoveThrottle>,
+    mut last_jump: Local<f32>, // elapsed seconds of the last jump press, for double taps
 ) {
-    let (mut transform, mut player) = player.into_inner();
+    let (mut transform, mut player, mut health) = player.into_inner();
 
     let delta = time.delta_secs();
+    let controls = !game_info.paused && !game_info.chat_open;
+
+    // survival loses flight right away, even mid air
+    if !game_info.creative {
+        player.flight = Flight::Off;
+    } else if controls {
+        if keybindings.just_pressed(&keyboard, Action::Jump) {
+            let now = time.elapsed_secs();
+            if now - *last_jump < DOUBLE_TAP_TIME {
+                player.flight = match player.flight {
+                    Flight::Off => Flight::Flying,
+                    _ => Flight::Off,
+                };
+                // a third tap starts a new double tap
+                *last_jump = f32::NEG_INFINITY;
+            } else {
+                *last_jump = now;
+            }
+        }
+        if keybindings.just_pressed(&keyboard, Action::ToggleSpectator) {
+            player.flight = match player.flight {
+                Flight::Spectator => Flight::Off,
+                _ => Flight::Spectator,
+            };
+        }
+    }
+    if !game_info.settings.debug_menus {
+        player.noclip = false;
+    } else if controls && keybindings.just_pressed(&keyboard, Action::ToggleNoclip) {
+        player.noclip = !player.noclip;
+    }
+    let flying = player.flight != Flight::Off || player.noclip;
+    let noclip = player.flight == Flight::Spectator || player.noclip;
+
+    if transform.translation.y < VOID_LEVEL {
+        *void_timer -= delta;
+        if *void_timer <= 0.0 {
+            health.0 = health.0.saturating_sub(VOID_DAMAGE);
+            *void_timer = 0.5;
+        }
+    } else {
+        *void_timer = 0.0;
+    }
 
     let mut move_dir = Vec3::ZERO;
     let mut sprint_multiplier = 1.0;
 
-    let sneaking = keyboard.pressed(KeyCode::ShiftLeft);
+    let sneaking = keybindings.pressed(&keyboard, Action::Sneak);
 
-    if !game_info.paused {
+    if controls {
         let local_z = transform.local_z();
         let forward = -Vec3::new(local_z.x, 0.0, local_z.z).normalize_or_zero();
         let right = Vec3::new(local_z.z, 0.0, -local_z.x).normalize_or_zero();
 
-        if keyboard.pressed(KeyCode::KeyW) {
-            if !sneaking && keyboard.pressed(KeyCode::ControlLeft) {
+        if keybindings.pressed(&keyboard, Action::MoveForward) {
+            if !sneaking && keybindings.pressed(&keyboard, Action::Sprint) {
                 sprint_multiplier = 1.3;
             }
             move_dir += forward;
         }
-        if keyboard.pressed(KeyCode::KeyS) {
+        if keybindings.pressed(&keyboard, Action::MoveBackward) {
             move_dir -= forward;
         }
-        if keyboard.pressed(KeyCode::KeyA) {
+        if keybindings.pressed(&keyboard, Action::MoveLeft) {
             move_dir -= right;
         }
-        if keyboard.pressed(KeyCode::KeyD) {
+        if keybindings.pressed(&keyboard, Action::MoveRight) {
             move_dir += right;
         }
 
@@ -244,8 +506,12 @@ fn player_movement(
         0.0,
         move_dir.z * game_info.settings.movement_speed * sprint_multiplier,
     );
+    if flying {
+        target_velocity *= FLY_SPEED_MULTIPLIER;
+    }
 
-    if sneaking {
+    // flying uses sneak to go down instead
+    if sneaking && !flying {
         target_velocity *= 0.3;
 
         // if ray_cast(&game_info, transform.translation, -Vec3::Y, 0.2).is_none() {
@@ -266,7 +532,34 @@ fn player_movement(
         vec3(0.0, 1.0, 0.0),
     ];
 
-    if target_velocity.x != 0.0 {
+    // nearby remote players and mobs as (min, size), to bump into alongside the blocks
+    let feet_offset = vec3(PLAYER_SIZE.x, 0.0, PLAYER_SIZE.z) / 2.0;
+    let obstacles = if game_info.settings.entity_collision {
+        online_players
+            .iter()
+            .map(|online_player| (online_player.translation - feet_offset, PLAYER_SIZE))
+            .chain(
+                game_entities
+                    .iter()
+                    .map(|entity| (entity.pos, entity.kind.size())),
+            )
+            .filter(|(min, _)| {
+                min.distance_squared(transform.translation)
+                    < ENTITY_COLLISION_RANGE * ENTITY_COLLISION_RANGE
+            })
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    // already overlapping doesn't block, so players can walk apart again
+    let bumps = |pos: Vec3, step: Vec3| {
+        obstacles.iter().any(|&(min, size)| {
+            aabb_collision(pos + step - feet_offset, PLAYER_SIZE, min, size)
+                && !aabb_collision(pos - feet_offset, PLAYER_SIZE, min, size)
+        })
+    };
+
+    if target_velocity.x != 0.0 && !noclip {
         let move_x = Vec3::new(target_velocity.x * delta, 0.0, 0.0);
         let dir_x = move_x.normalize_or_zero();
         let distance_x = move_x.length() + 0.05;
@@ -280,9 +573,12 @@ fn player_movement(
                 break;
             }
         }
+        if bumps(transform.translation, move_x) {
+            target_velocity.x = 0.0;
+        }
     }
 
-    if target_velocity.z != 0.0 {
+    if target_velocity.z != 0.0 && !noclip {
         let move_z = Vec3::new(0.0, 0.0, target_velocity.z * delta);
         let dir_z = move_z.normalize_or_zero();
         let distance_z = move_z.length() + 0.05;
@@ -296,37 +592,78 @@ fn player_movement(
                 break;
             }
         }
+        if bumps(transform.translation, move_z) {
+            target_velocity.z = 0.0;
+        }
     }
 
     player.velocity.x = target_velocity.x;
     player.velocity.z = target_velocity.z;
-
-    let mut grounded = false;
-    let mut closest_ground_distance = f32::MAX;
+    player.sprinting = sprint_multiplier > 1.0 && target_velocity != Vec3::ZERO;
 
     let grounded_offsets = &[
-        vec3(0.25, 0.1, 0.25),
-        vec3(-0.25, 0.1, 0.25),
-        vec3(0.25, 0.1, -0.25),
-        vec3(-0.25, 0.1, -0.25),
-        vec3(0.0, 0.1, 0.0),
+        vec3(0.25, 0.0, 0.25),
+        vec3(-0.25, 0.0, 0.25),
+        vec3(0.25, 0.0, -0.25),
+        vec3(-0.25, 0.0, -0.25),
+        vec3(0.0, 0.0, 0.0),
     ];
 
-    for offset in grounded_offsets {
-        let origin = transform.translation + *offset;
-        let fall_distance = player.velocity.y.abs() * delta + 0.1;
-
-        if let Some(hit) = ray_cast(&game_info, origin, -Vec3::Y, fall_distance) {
-            grounded = true;
+    if flying {
+        let rise = controls && keybindings.pressed(&keyboard, Action::Jump);
+        let sink = controls && sneaking;
+        player.velocity.y = (rise as i32 - sink as i32) as f32 * FLY_VERTICAL_SPEED;
+        player.fall_distance = 0.0;
+
+        if !noclip
+            && player.velocity.y > 0.0
+            && grounded_offsets.iter().any(|offset| {
+                let origin = transform.translation + Vec3::Y * PLAYER_SIZE.y + *offset;
+                ray_cast(
+                    &game_info,
+                    origin,
+                    Vec3::Y,
+                    player.velocity.y * delta + 0.05,
+                )
+                .is_some()
+            })
+        {
+            player.velocity.y = 0.0;
+        }
+    }
 
-            if hit.distance < closest_ground_distance {
-                closest_ground_distance = hit.distance;
+    // highest ground under any corner within this frame's fall, from the feet.
+    // not checked while going up, a jump would otherwise land again right away.
+    // flying only lands when going down, hovering just above the ground is fine
+    let check_ground = !noclip
+        && match player.flight {
+            Flight::Flying => player.velocity.y < 0.0,
+            _ => player.velocity.y <= 0.0,
+        };
+    let mut ground: Option<f32> = None;
+    if check_ground {
+        let fall = -player.velocity.y * delta + GROUND_SNAP;
+        for offset in grounded_offsets {
+            let feet = transform.translation + *offset;
+            if let Some(distance) = ground_distance(&game_info, feet, GROUND_RAY_START, fall) {
+                ground = Some(ground.map_or(distance, |d| d.min(distance)));
             }
         }
     }
 
-    if grounded {
-        if !game_info.paused && keyboard.pressed(KeyCode::Space) {
+    if let Some(distance) = ground {
+        // land exactly on the surface instead of moving by the velocity and snapping back
+        transform.translation.y -= distance;
+        player.fall_distance += distance.max(0.0);
+        player.flight = Flight::Off;
+
+        if !game_info.creative && player.fall_distance > SAFE_FALL_DISTANCE {
+            let damage = (player.fall_distance - SAFE_FALL_DISTANCE).ceil() as u32;
+            health.0 = health.0.saturating_sub(damage);
+        }
+        player.fall_distance = 0.0;
+
+        if controls && keybindings.pressed(&keyboard, Action::Jump) {
             let mut head_blocked = false;
             for offset in grounded_offsets {
                 let origin = transform.translation + Vec3::Y * 1.8 + *offset;
@@ -344,34 +681,96 @@ fn player_movement(
         } else {
             player.velocity.y = 0.0;
         }
+    } else if !flying {
+        player.velocity.y = apply_gravity(player.velocity.y, game_info.settings.gravity, delta);
+        player.fall_distance += (-player.velocity.y * delta).max(0.0);
+    }
+
+    transform.translation += player.velocity * delta;
+
+    // turning in place still has to show up for other players
+    let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+    move_throttle.send_move(client, transform.translation, yaw, delta);
+}
+
+// the server interpolates between moves, sending one every frame only costs bandwidth
+#[derive(Default)]
+pub struct MoveThrottle {
+    pos: Vec3, // last sent
+    yaw: f32,
+    cooldown: f32, // seconds until the next one can go out
+}
 
-        if player.velocity.y <= 0.0
-            && closest_ground_distance > 0.0
-            && closest_ground_distance < 0.1
+impl MoveThrottle {
+    pub fn send_move(
+        &mut self,
+        client: Option<ResMut<RenetClient>>,
+        pos: Vec3,
+        yaw: f32,
+        delta: f32,
+    ) {
+        self.cooldown -= delta;
+        if self.cooldown > 0.0
+            || (pos.distance_squared(self.pos) < MOVE_EPSILON * MOVE_EPSILON
+                && (yaw - self.yaw).abs() < YAW_EPSILON)
         {
-            transform.translation.y -= closest_ground_distance - 0.1;
+            return;
         }
-    } else {
-        player.velocity.y += game_info.settings.gravity * delta;
-        player.velocity.y = player.velocity.y.max(-78.4);
+        ClientPacket::Move(pos, yaw).send(client);
+        self.pos = pos;
+        self.yaw = yaw;
+        self.cooldown = 1.0 / MOVE_SEND_RATE;
     }
+}
 
-    transform.translation += player.velocity * delta;
-
-    if player.velocity.length() > 0.0 {
-        ClientPacket::Move(transform.translation).send(client);
+// shows the death screen once when health runs out
+fn handle_death(
+    mut commands: Commands,
+    mut window: Single<&mut Window, With<PrimaryWindow>>,
+    client: Option<ResMut<RenetClient>>,
+    player: Query<&Health, (With<Player>, Changed<Health>)>,
+    death_screen_query: Query<(), With<DeathScreen>>,
+) {
+    if let Ok(health) = player.single()
+        && health.0 == 0
+        && death_screen_query.is_empty()
+    {
+        set_cursor_grab(&mut window, false);
+        death_screen(&mut commands);
+        ClientPacket::Died.send(client);
     }
 }
 
+pub fn respawn(
+    transform: &mut Transform,
+    player: &mut Player,
+    health: &mut Health,
+    spawn: Option<Vec3>,
+    noises: &NoiseFunctions,
+    client: Option<ResMut<RenetClient>>,
+) {
+    transform.translation = spawn_point(spawn, noises);
+    *player = Player::default();
+    health.0 = MAX_HEALTH;
+    ClientPacket::Respawn(transform.translation).send(client);
+}
+
+// the world's spawn if one was set, otherwise on top of the terrain at the world origin
+pub fn spawn_point(spawn: Option<Vec3>, noises: &NoiseFunctions) -> Vec3 {
+    spawn.unwrap_or_else(|| vec3(0.0, 1.0 + terrain_noise(Vec2::ZERO, noises).0 as f32, 0.0))
+}
+
 pub fn player_bundle(
     player_pos: Vec3,
     player_velocity: Vec3,
     player_yaw: f32,
+    player_health: u32,
+    spawn: Option<Vec3>,
     noises: &NoiseFunctions,
 ) -> impl Bundle {
     (
         Transform::from_translation(if player_pos == Vec3::INFINITY {
-            vec3(0.0, 1.0 + terrain_noise(Vec2::ZERO, noises).0 as f32, 0.0)
+            spawn_point(spawn, noises)
         } else {
             player_pos
         })
@@ -379,7 +778,9 @@ pub fn player_bundle(
         Aabb::from_min_max(vec3(-0.25, 0.0, -0.25), vec3(0.25, 1.8, 0.25)),
         Player {
             velocity: player_velocity,
+            ..default()
         },
+        Health(player_health),
         Visibility::Visible,
     )
 }
@@ -396,13 +797,13 @@ pub fn camera_bundle(skybox: Handle<Image>, player: Entity, pitch: f32) -> impl
         PostProcessSettings::default(),
         Skybox {
             image: skybox,
-            brightness: 1000.0,
+            brightness: DAY_SKYBOX_BRIGHTNESS,
             ..default()
         },
         Bloom::NATURAL,
         Tonemapping::TonyMcMapface,
         ScreenSpaceAmbientOcclusion::default(),
-        Transform::from_xyz(0.0, 1.62, -0.05).with_rotation(Quat::from_rotation_x(pitch)), // minecraft way
+        Transform::from_translation(CAMERA_OFFSET).with_rotation(Quat::from_rotation_x(pitch)),
         ChildOf(player),
     )
 }
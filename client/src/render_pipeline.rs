@@ -6,6 +6,7 @@ use bevy::{
         fullscreen_vertex_shader::fullscreen_shader_vertex_state,
     },
     ecs::query::QueryItem,
+    pbr::{ExtendedMaterial, MaterialExtension},
     prelude::*,
     render::{
         RenderApp,
@@ -25,26 +26,27 @@ use bevy::{
     },
 };
 
-// * unused right now
-// #[derive(Asset, TypePath, AsBindGroup, Debug, Clone)]
-// pub struct VoxelMaterial {
-//     #[texture(1)]
-//     #[sampler(2)]
-//     pub color_texture: Option<Handle<Image>>,
-// }
+pub type VoxelMaterial = ExtendedMaterial<StandardMaterial, VoxelExtension>;
 
-// impl Material for VoxelMaterial {
-//     fn fragment_shader() -> ShaderRef {
-//         "shaders/voxel.wgsl".into()
-//     }
-// }
+// used by greedy meshes to repeat a single atlas tile across merged quads
+#[derive(Asset, AsBindGroup, Reflect, Debug, Clone)]
+pub struct VoxelExtension {
+    #[uniform(100)]
+    pub tile_size: Vec2,
+}
+
+impl MaterialExtension for VoxelExtension {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/voxel.wgsl".into()
+    }
+}
 
 pub struct RenderPipelinePlugin;
 
 impl Plugin for RenderPipelinePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins((
-            // MaterialPlugin::<VoxelMaterial>::default(),
+            MaterialPlugin::<VoxelMaterial>::default(),
             ExtractComponentPlugin::<PostProcessSettings>::default(),
             UniformComponentPlugin::<PostProcessSettings>::default(),
         ));
@@ -206,4 +208,5 @@ impl FromWorld for PostProcessPipeline {
 #[derive(Component, Default, Clone, Copy, ExtractComponent, ShaderType)]
 pub struct PostProcessSettings {
     pub sss: u32,
+    pub submerged: u32, // 1 while the camera is in water
 }
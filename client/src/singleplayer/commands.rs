@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use ferriscraft::Persistent;
+
+use crate::{
+    GameInfo,
+    keybindings::{Action, KeyBindings},
+    player::{Player, Teleported},
+    ui::{TextBox, toast},
+};
+
+// there's nobody to chat with in singleplayer, the chat key opens this instead
+#[derive(Component)]
+pub struct CommandPrompt;
+
+pub fn command_prompt(ui: Entity) -> impl Bundle {
+    (
+        Node {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            left: Val::Px(5.0),
+            bottom: Val::Px(5.0),
+            width: Val::Percent(40.0),
+            padding: UiRect::all(Val::Px(4.0)),
+            ..default()
+        },
+        BackgroundColor(Color::BLACK.with_alpha(0.5)),
+        CommandPrompt,
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 18.0,
+                ..default()
+            },
+            TextColor(Color::srgb(0.9, 0.9, 0.9)),
+            TextBox {
+                selected: false,
+                current_value: String::new(),
+                placeholder: String::new(),
+                name: "Command".to_string(),
+            },
+        )],
+        ChildOf(ui),
+    )
+}
+
+pub fn handle_command_prompt(
+    mut commands: Commands,
+    mut game_info: ResMut<GameInfo>,
+    mut input: Single<(&mut TextBox, &mut Text)>,
+    mut prompt_node: Single<&mut Node, With<CommandPrompt>>,
+    mut player: Single<(Entity, &mut Transform, &mut Player)>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    keybindings: Res<Persistent<KeyBindings>>,
+) {
+    let (textbox, text) = &mut *input;
+
+    // selecting a frame late so the key that opened the prompt doesn't get typed
+    if game_info.chat_open && !textbox.selected {
+        textbox.selected = true;
+        prompt_node.display = Display::Flex;
+    } else if !game_info.chat_open && textbox.selected {
+        textbox.selected = false;
+        textbox.current_value.clear();
+        text.0.clear();
+        prompt_node.display = Display::None;
+    }
+
+    if !game_info.chat_open {
+        if !game_info.paused && keybindings.just_pressed(&keyboard, Action::Chat) {
+            game_info.chat_open = true;
+        }
+    } else if keyboard.just_pressed(KeyCode::Enter) {
+        let command = textbox.current_value.trim();
+        let command = command.strip_prefix('/').unwrap_or(command);
+        let args = command.split_whitespace().collect::<Vec<_>>();
+        let (entity, transform, player) = &mut *player;
+
+        match args.as_slice() {
+            [] => {}
+            ["tp", x, y, z] => match (x.parse::<f32>(), y.parse::<f32>(), z.parse::<f32>()) {
+                (Ok(x), Ok(y), Ok(z)) if x.is_finite() && y.is_finite() && z.is_finite() => {
+                    // physics waits for the chunks around the new position to load, then
+                    // the player gets lifted out of any terrain they landed in
+                    transform.translation = vec3(x, y, z);
+                    player.velocity = Vec3::ZERO;
+                    player.fall_distance = 0.0;
+                    commands.entity(*entity).insert(Teleported);
+                    toast(&mut commands, format!("Teleported to {x} {y} {z}"));
+                }
+                _ => toast(&mut commands, "Coordinates must be numbers"),
+            },
+            ["tp", ..] => toast(&mut commands, "Usage: /tp <x> <y> <z>"),
+            ["setspawn"] => {
+                let pos = transform.translation;
+                game_info.spawn = Some(pos);
+                toast(
+                    &mut commands,
+                    format!("Spawn set to {:.1} {:.1} {:.1}", pos.x, pos.y, pos.z),
+                );
+            }
+            ["setspawn", ..] => toast(&mut commands, "Usage: /setspawn"),
+            [name, ..] => toast(&mut commands, format!("Unknown command: {name}")),
+        }
+        game_info.chat_open = false;
+    }
+}
@@ -10,29 +10,38 @@ use bevy::{
     prelude::*,
     window::PrimaryWindow,
 };
-use ferriscraft::{BlockKind, Persistent, SavedWorld};
+use ferriscraft::{
+    BlockKind, MAX_HEALTH, Persistent, SavedWorld, WorldGenConfig, terrain::get_noise_functions,
+};
 use iyes_perf_ui::prelude::PerfUiAllEntries;
 
 use crate::{
     GameInfo,
-    player::{Player, camera_bundle, player_bundle},
+    player::{Health, Inventory, Player, camera_bundle, player_bundle},
     render_pipeline::PostProcessSettings,
-    ui::{GameState, coords_bundle, hotbar_block, hotbar_bundle, root_ui_bundle},
-    utils::{get_noise_functions, set_cursor_grab},
-    world::systems::save_game,
+    singleplayer::commands::{command_prompt, handle_command_prompt},
+    ui::{GameState, coords_bundle, crosshair_bundle, root_ui_bundle, spawn_hotbar},
+    utils::set_cursor_grab,
+    world::{SaveTask, systems::save_game},
 };
 
+mod commands;
+
 pub struct SinglePlayerPlugin;
 
 impl Plugin for SinglePlayerPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::SinglePlayer), setup)
-            .add_systems(OnExit(GameState::SinglePlayer), cleanup);
+            .add_systems(OnExit(GameState::SinglePlayer), cleanup)
+            .add_systems(
+                Update,
+                handle_command_prompt.run_if(in_state(GameState::SinglePlayer)),
+            );
     }
 }
 
 #[derive(Resource)]
-pub struct SPNewWorld(pub String, pub u32);
+pub struct SPNewWorld(pub String, pub u32, pub bool, pub WorldGenConfig); // name, seed, creative, generation
 
 #[derive(Resource)]
 pub struct SPSavedWorld(pub String);
@@ -40,24 +49,35 @@ pub struct SPSavedWorld(pub String);
 fn cleanup(
     mut commands: Commands,
     mut game_info: ResMut<GameInfo>,
+    mut save_task: ResMut<SaveTask>,
     persistent_world: Option<ResMut<Persistent<SavedWorld>>>,
-    player: Query<(&Transform, &Player)>,
+    player: Query<(&Transform, &Player, &Health, &Inventory)>,
     camera: Single<(Entity, &Transform), With<Camera3d>>,
 ) {
-    save_game(persistent_world, player, Some(camera.1), &game_info);
+    save_game(
+        persistent_world,
+        &mut save_task,
+        player,
+        Some(camera.1),
+        &game_info,
+    );
     commands.remove_resource::<Persistent<SavedWorld>>();
     commands.remove_resource::<SPNewWorld>();
     commands.remove_resource::<SPSavedWorld>();
 
     game_info.chunks = default();
     game_info.saved_chunks = default();
+    game_info.unsaved_chunks = default();
     game_info.loading_chunks = default();
+    game_info.chat_open = false;
+    game_info.spawn = None;
     commands.entity(camera.0).remove::<(
         TemporalAntiAliasing,
         PostProcessSettings,
         Skybox,
         Bloom,
         ScreenSpaceAmbientOcclusion,
+        DistanceFog,
         ChildOf,
     )>();
 }
@@ -72,22 +92,23 @@ fn setup(
     asset_server: Res<AssetServer>,
 ) {
     let persistent = if let Some(new_world) = new_world {
-        let SPNewWorld(name, seed) = new_world.into_inner();
-        Persistent::<SavedWorld>::new(
+        let SPNewWorld(name, seed, creative, world_gen) = new_world.into_inner();
+        SavedWorld::persistent(
             Path::new("saves").join(format!("{}.ferris", name)),
             SavedWorld {
                 seed: *seed,
                 players: HashMap::new(),
                 chunks: HashMap::new(),
+                creative: *creative,
+                world_gen: *world_gen,
+                spawn: None,
             },
-            false,
         )
     } else {
         let SPSavedWorld(name) = saved_world.unwrap().into_inner();
-        Persistent::<SavedWorld>::new(
+        SavedWorld::persistent(
             Path::new("saves").join(format!("{}.ferris", name)),
             SavedWorld::default(),
-            false,
         )
     };
 
@@ -95,11 +116,17 @@ fn setup(
         seed,
         players,
         chunks,
+        creative,
+        world_gen,
+        spawn,
     } = &*persistent;
 
-    game_info.noises = get_noise_functions(*seed);
+    game_info.noises = get_noise_functions(*seed, *world_gen);
+    game_info.seed = *seed;
     game_info.saved_chunks = Some(Arc::new(RwLock::new(chunks.clone())));
     game_info.current_block = BlockKind::Stone;
+    game_info.creative = *creative;
+    game_info.spawn = *spawn;
 
     set_cursor_grab(&mut window, true);
 
@@ -119,25 +146,36 @@ fn setup(
         StateScoped(GameState::SinglePlayer),
     ));
 
-    let &(player_pos, player_velocity, player_yaw, player_pitch) = players
-        .get(&"Player".to_string())
-        .unwrap_or(&(Vec3::INFINITY, Vec3::ZERO, 0.0, 0.0));
+    // saved under the player name, worlds from before multiplayer used "Player"
+    let saved_player = players
+        .get(&game_info.settings.player_name)
+        .or_else(|| players.get("Player"));
+    let (player_pos, player_velocity, player_yaw, player_pitch, player_health) = saved_player
+        .map_or(
+            (Vec3::INFINITY, Vec3::ZERO, 0.0, 0.0, MAX_HEALTH),
+            |player| (player.0, player.1, player.2, player.3, player.4),
+        );
+    let inventory = saved_player.map_or_else(Inventory::default, |player| {
+        Inventory::from_counts(&player.5)
+    });
 
     let player = commands
         .spawn(player_bundle(
             player_pos,
             player_velocity,
             player_yaw,
+            player_health,
+            game_info.spawn,
             &game_info.noises,
         ))
-        .insert(StateScoped(GameState::SinglePlayer))
+        .insert((StateScoped(GameState::SinglePlayer), inventory))
         .id();
 
     commands
         .entity(*camera)
         .remove::<Camera3d>()
         .insert(camera_bundle(
-            asset_server.load("skybox.ktx2"),
+            game_info.skybox.clone(),
             player,
             player_pitch,
         ));
@@ -152,18 +190,15 @@ fn setup(
         .id();
 
     commands.spawn(coords_bundle(ui));
-
-    let hotbar = commands.spawn(hotbar_bundle(ui)).id();
-
-    let node = ImageNode::new(asset_server.load("atlas.png"));
-
-    for i in 1..=10 {
-        if i == BlockKind::Water as u8 {
-            continue;
-        }
-
-        commands.spawn(hotbar_block(hotbar, node.clone(), i));
-    }
+    commands.spawn(crosshair_bundle(ui));
+    commands.spawn(command_prompt(ui));
+
+    spawn_hotbar(
+        &mut commands,
+        ui,
+        asset_server.load("atlas.png"),
+        &game_info.settings,
+    );
 
     commands.insert_resource(persistent);
 }
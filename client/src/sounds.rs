@@ -0,0 +1,161 @@
+use bevy::{audio::Volume, prelude::*};
+use ferriscraft::BlockKind;
+
+use crate::{GameInfo, PausableSystems, player::Player, ui::GameState, utils::ray_cast};
+
+const STRIDE: f32 = 1.6; // blocks walked between footsteps
+const STEP_VOLUME: f32 = 0.3;
+
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SoundAssets>().add_systems(
+            Update,
+            footsteps
+                .in_set(PausableSystems)
+                .run_if(not(in_state(GameState::Menu))),
+        );
+    }
+}
+
+// what a block sounds like, blocks that share a material share a clip
+#[derive(Clone, Copy)]
+enum BlockSound {
+    Stone,
+    Grass,
+    Dirt,
+    Wood,
+    Sand,
+    Snow,
+}
+
+impl BlockSound {
+    const ALL: [BlockSound; 6] = [
+        BlockSound::Stone,
+        BlockSound::Grass,
+        BlockSound::Dirt,
+        BlockSound::Wood,
+        BlockSound::Sand,
+        BlockSound::Snow,
+    ];
+
+    fn of(kind: BlockKind) -> Option<Self> {
+        match kind {
+            BlockKind::Stone
+            | BlockKind::Bedrock
+            | BlockKind::CoalOre
+            | BlockKind::IronOre
+            | BlockKind::Glowstone => Some(BlockSound::Stone),
+            BlockKind::Grass | BlockKind::Leaf => Some(BlockSound::Grass),
+            BlockKind::Dirt => Some(BlockSound::Dirt),
+            BlockKind::Plank | BlockKind::Wood => Some(BlockSound::Wood),
+            BlockKind::Sand => Some(BlockSound::Sand),
+            BlockKind::Snow => Some(BlockSound::Snow),
+            BlockKind::Air | BlockKind::Water => None,
+        }
+    }
+
+    fn path(self) -> &'static str {
+        match self {
+            BlockSound::Stone => "sounds/stone.wav",
+            BlockSound::Grass => "sounds/grass.wav",
+            BlockSound::Dirt => "sounds/dirt.wav",
+            BlockSound::Wood => "sounds/wood.wav",
+            BlockSound::Sand => "sounds/sand.wav",
+            BlockSound::Snow => "sounds/snow.wav",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum BlockAction {
+    Step,
+    Place,
+    Break,
+}
+
+#[derive(Resource)]
+pub struct SoundAssets(Vec<Handle<AudioSource>>); // indexed by BlockSound
+
+impl FromWorld for SoundAssets {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Self(
+            BlockSound::ALL
+                .iter()
+                .map(|sound| asset_server.load(sound.path()))
+                .collect(),
+        )
+    }
+}
+
+pub fn play_block_sound(
+    commands: &mut Commands,
+    sounds: &SoundAssets,
+    game_info: &GameInfo,
+    kind: BlockKind,
+    action: BlockAction,
+) {
+    let Some(sound) = BlockSound::of(kind) else {
+        return;
+    };
+    // one clip per material, pitched and scaled per action
+    let (volume, speed) = match action {
+        BlockAction::Step => (STEP_VOLUME, 1.0),
+        BlockAction::Place => (0.8, 1.2),
+        BlockAction::Break => (1.0, 0.8),
+    };
+    let volume = volume * game_info.settings.volume;
+    if volume <= 0.0 {
+        return;
+    }
+
+    commands.spawn((
+        AudioPlayer(sounds.0[sound as usize].clone()),
+        PlaybackSettings::DESPAWN
+            .with_volume(Volume::Linear(volume))
+            // so repeated sounds don't all come out the same
+            .with_speed(speed * rand::random_range(0.9..1.1)),
+    ));
+}
+
+fn footsteps(
+    mut commands: Commands,
+    sounds: Res<SoundAssets>,
+    player: Single<(&Transform, &Player)>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+    mut walked: Local<f32>, // since the last step
+) {
+    let (transform, player) = player.into_inner();
+
+    let speed = player.velocity.xz().length();
+    if speed < 0.1 {
+        // the first step after stopping comes a bit sooner
+        *walked = walked.max(STRIDE * 0.5);
+        return;
+    }
+
+    // the block under the feet, nothing while in the air
+    let Some(hit) = ray_cast(
+        &game_info,
+        transform.translation + Vec3::Y * 0.1,
+        -Vec3::Y,
+        0.3,
+    ) else {
+        return;
+    };
+
+    *walked += speed * time.delta_secs();
+    if *walked >= STRIDE {
+        *walked -= STRIDE;
+        play_block_sound(
+            &mut commands,
+            &sounds,
+            &game_info,
+            hit.block.kind,
+            BlockAction::Step,
+        );
+    }
+}
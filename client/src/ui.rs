@@ -5,7 +5,7 @@ use std::{
 
 use bevy::{
     diagnostic::{
-        EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+        DiagnosticPath, DiagnosticsStore, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
         SystemInformationDiagnosticsPlugin,
     },
     input::{ButtonState, keyboard::KeyboardInput, mouse::MouseWheel},
@@ -14,15 +14,21 @@ use bevy::{
     window::PrimaryWindow,
 };
 use bevy_inspector_egui::{bevy_egui::EguiPlugin, quick::WorldInspectorPlugin};
-use ferriscraft::{BlockKind, DEFAULT_SERVER_PORT};
+use bevy_renet::renet::RenetClient;
+use ferriscraft::{
+    ATLAS, BlockKind, DEFAULT_SERVER_PORT, MAX_HEALTH, Persistent, SavedWorld, WorldPreset,
+    terrain::{Biome, terrain_noise},
+};
 use iyes_perf_ui::{PerfUiPlugin, prelude::PerfUiEntryFPS};
 
 use crate::{
-    CHUNK_SIZE, GameInfo,
-    player::Player,
+    GameInfo, GameSettings, HiddenForScreenshot, MAX_AUTOSAVE_INTERVAL, ScreenshotFormat,
+    discovery::DiscoveredServers,
+    keybindings::{Action, KeyBindings},
+    player::{Health, Inventory, Player, respawn},
     singleplayer::{SPNewWorld, SPSavedWorld},
-    utils::set_cursor_grab,
-    world::utils::terrain_noise,
+    utils::{ray_cast, set_cursor_grab},
+    world::{ComputeChunk, ComputeChunkMesh, utils::chunk_coords},
 };
 
 pub struct UIPlugin;
@@ -81,10 +87,43 @@ impl Plugin for UIPlugin {
         .add_systems(OnEnter(MenuState::SinglePlayer), singleplayer_menu)
         .add_systems(OnEnter(MenuState::SinglePlayerNewWorld), sp_new_world_menu)
         .add_systems(OnEnter(MenuState::MultiPlayer), multiplayer_menu)
+        .add_systems(OnEnter(MenuState::Controls), controls_menu)
+        .add_systems(OnEnter(MenuState::Settings), settings_menu)
+        .add_systems(
+            OnExit(MenuState::Settings),
+            |mut pause_menu: Query<&mut Visibility, With<PuaseMenu>>,
+             game_info: Res<GameInfo>| {
+                game_info.settings.write().ok();
+                for mut visibility in &mut pause_menu {
+                    *visibility = Visibility::Inherited;
+                }
+            },
+        )
         .add_systems(OnEnter(GameState::Menu), enter_menu)
         .add_systems(OnExit(GameState::Menu), exit_menu)
-        .add_systems(Update, (handle_errors, handle_buttons, handle_textboxes))
-        .add_systems(Update, handle_hud.run_if(not(in_state(GameState::Menu))))
+        .init_resource::<Rebinding>()
+        .init_non_send_resource::<Clipboard>()
+        .add_systems(
+            Update,
+            (handle_errors, handle_buttons, handle_textboxes, handle_toasts),
+        )
+        .add_systems(Update, handle_rebinding.run_if(in_state(MenuState::Controls)))
+        .add_systems(
+            Update,
+            update_server_list.run_if(
+                in_state(MenuState::MultiPlayer).and(resource_changed::<DiscoveredServers>),
+            ),
+        )
+        .add_systems(
+            Update,
+            update_setting_labels.run_if(
+                in_state(MenuState::Settings).and(resource_changed::<GameInfo>),
+            ),
+        )
+        .add_systems(
+            Update,
+            (handle_hud, handle_crosshair).run_if(not(in_state(GameState::Menu))),
+        )
         .add_systems(Update, pause_menu.run_if(not(in_state(GameState::Menu)))
             .run_if(|game_info: Res<GameInfo>, mut was_paused: Local<bool>| {
                 if !*was_paused && game_info.paused {
@@ -104,11 +143,11 @@ impl Plugin for UIPlugin {
 fn enter_menu(
     mut commands: Commands,
     mut window: Single<&mut Window, With<PrimaryWindow>>,
-    pause_menu_query: Query<Entity, With<PuaseMenu>>,
+    overlay_query: Query<Entity, Or<(With<PuaseMenu>, With<DeathScreen>)>>,
 ) {
     set_cursor_grab(&mut window, false);
-    for pause_menu in pause_menu_query.iter() {
-        commands.entity(pause_menu).despawn();
+    for overlay in overlay_query.iter() {
+        commands.entity(overlay).despawn();
     }
 }
 
@@ -120,7 +159,16 @@ fn exit_menu(mut window: Single<&mut Window, With<PrimaryWindow>>) {
 struct CoordsText;
 
 #[derive(Component)]
-struct HotbarBlock(u8);
+struct HotbarBlock(BlockKind);
+
+#[derive(Component)]
+struct HotbarCount(BlockKind);
+
+#[derive(Component)]
+struct GameModeButton;
+
+#[derive(Component)]
+struct WorldPresetButton(WorldPreset);
 
 #[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
 #[states(scoped_entities)]
@@ -140,6 +188,8 @@ pub enum MenuState {
     SinglePlayer,
     SinglePlayerNewWorld,
     MultiPlayer,
+    Controls,
+    Settings,
 }
 
 #[derive(Component)]
@@ -148,6 +198,143 @@ pub struct ErrorText;
 #[derive(Component)]
 pub struct SavedWorldMarker(pub bool);
 
+#[derive(Component)]
+struct DeleteWorldButton(bool); // clicked once, waiting for the confirm
+
+#[derive(Component)]
+struct RenameWorldButton(Option<Entity>); // the new name box, once it's open
+
+#[derive(Component)]
+struct ServerList;
+
+#[derive(Component)]
+struct KeyBindButton(Action);
+
+// opened on the first copy and kept for the whole run, on linux the copied text goes away
+// with the clipboard. non-send so it's only ever touched from the main thread
+#[derive(Default)]
+struct Clipboard(Option<arboard::Clipboard>);
+
+// action waiting for a key press, escape cancels it instead of leaving the menu
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<Action>);
+
+#[derive(Component, Clone, Copy)]
+enum Setting {
+    RenderDistance,
+    Fov,
+    Sensitivity,
+    MovementSpeed,
+    ViewBob,
+    Fog,
+    Clouds,
+    Volume,
+    MusicVolume,
+    DespawnChunks,
+    Autosave,
+    AutosaveInterval,
+    ScreenshotFormat,
+}
+
+impl Setting {
+    const ALL: [Setting; 13] = [
+        Setting::RenderDistance,
+        Setting::Fov,
+        Setting::Sensitivity,
+        Setting::MovementSpeed,
+        Setting::ViewBob,
+        Setting::Fog,
+        Setting::Clouds,
+        Setting::Volume,
+        Setting::MusicVolume,
+        Setting::DespawnChunks,
+        Setting::Autosave,
+        Setting::AutosaveInterval,
+        Setting::ScreenshotFormat,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Setting::RenderDistance => "Render Distance",
+            Setting::Fov => "FOV",
+            Setting::Sensitivity => "Sensitivity",
+            Setting::MovementSpeed => "Movement Speed",
+            Setting::ViewBob => "View Bob",
+            Setting::Fog => "Fog",
+            Setting::Clouds => "Clouds",
+            Setting::Volume => "Volume",
+            Setting::MusicVolume => "Music Volume",
+            Setting::DespawnChunks => "Despawn Chunks",
+            Setting::Autosave => "Autosave",
+            Setting::AutosaveInterval => "Autosave Interval",
+            Setting::ScreenshotFormat => "Screenshot Format",
+        }
+    }
+
+    fn value(self, settings: &GameSettings) -> String {
+        match self {
+            Setting::RenderDistance => settings.render_distance.to_string(),
+            Setting::Fov => settings.fov.to_string(),
+            Setting::Sensitivity => format!("{:.1}", settings.sensitivity),
+            Setting::MovementSpeed => format!("{:.2}", settings.movement_speed),
+            Setting::ViewBob => (if settings.view_bob { "On" } else { "Off" }).into(),
+            Setting::Fog => (if settings.fog { "On" } else { "Off" }).into(),
+            Setting::Clouds => (if settings.clouds { "On" } else { "Off" }).into(),
+            Setting::Volume => format!("{:.0}%", settings.volume * 100.0),
+            Setting::MusicVolume => format!("{:.0}%", settings.music_volume * 100.0),
+            Setting::DespawnChunks => (if settings.despawn_chunks { "On" } else { "Off" }).into(),
+            Setting::Autosave => (if settings.autosave { "On" } else { "Off" }).into(),
+            Setting::AutosaveInterval => format!(
+                "{}:{:02}",
+                settings.autosave_interval / 60,
+                settings.autosave_interval % 60
+            ),
+            Setting::ScreenshotFormat => settings.screenshot_format.extension().to_uppercase(),
+        }
+    }
+
+    // dir is -1 or 1, toggles ignore it
+    fn step(self, settings: &mut GameSettings, dir: i32) {
+        match self {
+            Setting::RenderDistance => {
+                settings.render_distance = (settings.render_distance + dir).clamp(2, 32);
+            }
+            Setting::Fov => {
+                settings.fov = (settings.fov as i32 + dir * 5).clamp(30, 120) as u32;
+            }
+            Setting::Sensitivity => {
+                settings.sensitivity = (settings.sensitivity + dir as f32 * 0.1).clamp(0.1, 5.0);
+            }
+            Setting::MovementSpeed => {
+                settings.movement_speed =
+                    (settings.movement_speed + dir as f32 * 0.5).clamp(1.0, 20.0);
+            }
+            Setting::ViewBob => settings.view_bob = !settings.view_bob,
+            Setting::Fog => settings.fog = !settings.fog,
+            Setting::Clouds => settings.clouds = !settings.clouds,
+            Setting::Volume => {
+                settings.volume = (settings.volume + dir as f32 * 0.1).clamp(0.0, 1.0);
+            }
+            Setting::MusicVolume => {
+                settings.music_volume = (settings.music_volume + dir as f32 * 0.1).clamp(0.0, 1.0);
+            }
+            Setting::DespawnChunks => settings.despawn_chunks = !settings.despawn_chunks,
+            Setting::Autosave => settings.autosave = !settings.autosave,
+            Setting::AutosaveInterval => {
+                settings.autosave_interval = (settings.autosave_interval as i32 + dir * 30)
+                    .clamp(30, MAX_AUTOSAVE_INTERVAL as i32)
+                    as u32;
+            }
+            Setting::ScreenshotFormat => {
+                settings.screenshot_format = match settings.screenshot_format {
+                    ScreenshotFormat::Png => ScreenshotFormat::Jpeg,
+                    ScreenshotFormat::Jpeg => ScreenshotFormat::Png,
+                };
+            }
+        }
+    }
+}
+
 fn setup(mut commands: Commands, camera: Query<Entity, With<Camera>>) {
     if camera.single().is_err() {
         commands.spawn(Camera3d::default());
@@ -171,6 +358,11 @@ fn pause_menu(
 
         let vertical = commands.spawn(vertical_ui_bundle(ui)).id();
 
+        commands.spawn((
+            Text::new(format!("Seed: {}", game_info.seed)),
+            TextShadow::default(),
+            ChildOf(vertical),
+        ));
         commands
             .spawn(button("Back", vertical, 300.0, 60.0))
             .observe(
@@ -181,12 +373,36 @@ fn pause_menu(
                     set_cursor_grab(&mut window, true);
                 },
             );
-        commands.spawn(button("Settings", vertical, 300.0, 60.0));
-        // .observe(
-        //     |_trigger: Trigger<Pointer<Released>>, mut state: ResMut<NextState<MenuState>>| {
-        //         state.set(MenuState::MultiPlayer);
-        //     },
-        // );
+        commands
+            .spawn(button("Settings", vertical, 300.0, 60.0))
+            .observe(
+                |_trigger: Trigger<Pointer<Released>>,
+                 mut state: ResMut<NextState<MenuState>>,
+                 mut pause_menu: Query<&mut Visibility, With<PuaseMenu>>| {
+                    for mut visibility in &mut pause_menu {
+                        *visibility = Visibility::Hidden;
+                    }
+                    state.set(MenuState::Settings);
+                },
+            );
+        commands
+            .spawn(button("Copy Seed", vertical, 300.0, 60.0))
+            .observe(
+                |_trigger: Trigger<Pointer<Released>>,
+                 game_info: Res<GameInfo>,
+                 mut clipboard: NonSendMut<Clipboard>| {
+                    if clipboard.0.is_none() {
+                        clipboard.0 = arboard::Clipboard::new()
+                            .inspect_err(|e| warn!("Couldn't open the clipboard: {e}"))
+                            .ok();
+                    }
+                    if let Some(clipboard) = clipboard.0.as_mut()
+                        && let Err(e) = clipboard.set_text(game_info.seed.to_string())
+                    {
+                        warn!("Couldn't copy the seed: {e}");
+                    }
+                },
+            );
         commands
             .spawn(button("Leave", vertical, 300.0, 60.0))
             .observe(
@@ -211,6 +427,54 @@ fn pause_menu(
     }
 }
 
+#[derive(Component)]
+pub struct DeathScreen;
+
+pub fn death_screen(commands: &mut Commands) {
+    let ui = commands
+        .spawn(root_ui_bundle())
+        .insert(BackgroundColor(Color::srgba(0.5, 0.0, 0.0, 0.5)))
+        .insert(DeathScreen)
+        .id();
+
+    let vertical = commands.spawn(vertical_ui_bundle(ui)).id();
+
+    commands.spawn((
+        Text::new("You died!"),
+        TextFont {
+            font_size: 48.0,
+            ..default()
+        },
+        TextShadow::default(),
+        ChildOf(vertical),
+    ));
+    commands
+        .spawn(button("Respawn", vertical, 300.0, 60.0))
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>,
+             mut commands: Commands,
+             mut window: Single<&mut Window, With<PrimaryWindow>>,
+             client: Option<ResMut<RenetClient>>,
+             game_info: Res<GameInfo>,
+             player: Single<(&mut Transform, &mut Player, &mut Health)>,
+             death_screen_query: Query<Entity, With<DeathScreen>>| {
+                let (mut transform, mut player, mut health) = player.into_inner();
+                respawn(
+                    &mut transform,
+                    &mut player,
+                    &mut health,
+                    game_info.spawn,
+                    &game_info.noises,
+                    client,
+                );
+                for entity in death_screen_query.iter() {
+                    commands.entity(entity).despawn();
+                }
+                set_cursor_grab(&mut window, !game_info.paused);
+            },
+        );
+}
+
 fn main_menu(mut commands: Commands) {
     let ui = commands
         .spawn(root_ui_bundle())
@@ -233,6 +497,20 @@ fn main_menu(mut commands: Commands) {
                 state.set(MenuState::MultiPlayer);
             },
         );
+    commands
+        .spawn(button("Settings", vertical, 300.0, 60.0))
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>, mut state: ResMut<NextState<MenuState>>| {
+                state.set(MenuState::Settings);
+            },
+        );
+    commands
+        .spawn(button("Controls", vertical, 300.0, 60.0))
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>, mut state: ResMut<NextState<MenuState>>| {
+                state.set(MenuState::Controls);
+            },
+        );
     commands
         .spawn(button("Quit", vertical, 300.0, 60.0))
         .observe(
@@ -250,6 +528,19 @@ fn singleplayer_menu(mut commands: Commands) {
 
     let vertical = commands.spawn(vertical_ui_bundle(ui)).id();
 
+    // renamed worlds are respawned in here, above the buttons
+    let list = commands
+        .spawn((
+            Node {
+                align_items: AlignItems::Center,
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(10.0),
+                ..default()
+            },
+            ChildOf(vertical),
+        ))
+        .id();
+
     let mut world_count = 0;
 
     if let Ok(dir) = Path::new("saves").read_dir() {
@@ -261,36 +552,7 @@ fn singleplayer_menu(mut commands: Commands) {
                 let mut name = entry.file_name().to_string_lossy().to_string();
                 if name.ends_with(".ferris") {
                     name = name.replace(".ferris", "");
-                    commands
-                        .spawn(button(&name, vertical, 500.0, 75.0))
-                        .insert(SavedWorldMarker(false))
-                        .observe(
-                            move |trigger: Trigger<Pointer<Pressed>>,
-                                  mut commands: Commands,
-                                  mut menu_state: ResMut<NextState<MenuState>>,
-                                  mut game_state: ResMut<NextState<GameState>>,
-                                  buttons: Query<(
-                                &SavedWorldMarker,
-                                Option<&Children>,
-                                Entity,
-                            )>| {
-                                for (marker, children_opt, entity) in buttons.iter() {
-                                    // shit way
-                                    let pressed_on = trigger.target == entity
-                                        || children_opt
-                                            .map(|children| {
-                                                children.iter().any(|c| c == trigger.target)
-                                            })
-                                            .unwrap_or(false);
-
-                                    if marker.0 && pressed_on {
-                                        commands.insert_resource(SPSavedWorld(name.clone()));
-                                        menu_state.set(MenuState::None);
-                                        game_state.set(GameState::SinglePlayer);
-                                    }
-                                }
-                            },
-                        );
+                    spawn_world_row(&mut commands, list, name);
                     world_count += 1;
                 }
             }
@@ -301,6 +563,14 @@ fn singleplayer_menu(mut commands: Commands) {
         commands.spawn((Text::new("No saves found"), ChildOf(vertical)));
     }
 
+    commands.spawn((
+        ErrorText,
+        Text::new(""),
+        TextColor(Color::srgb(1.0, 0.0, 0.0)),
+        TextLayout::new_with_justify(JustifyText::Center),
+        ChildOf(vertical),
+    ));
+
     let horizontal = commands.spawn(horizontal_ui_bundle(vertical)).id();
 
     commands
@@ -319,7 +589,203 @@ fn singleplayer_menu(mut commands: Commands) {
         );
 }
 
-fn sp_new_world_menu(mut commands: Commands) {
+// load button, info line, rename and delete for one save
+fn spawn_world_row(commands: &mut Commands, list: Entity, name: String) {
+    let path = Path::new("saves").join(format!("{name}.ferris"));
+
+    let row = commands
+        .spawn((
+            Node {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                ..default()
+            },
+            ChildOf(list),
+        ))
+        .id();
+    let column = commands
+        .spawn((
+            Node {
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            ChildOf(row),
+        ))
+        .id();
+
+    let world_name = name.clone();
+    commands
+        .spawn(button(&name, column, 500.0, 75.0))
+        .insert(SavedWorldMarker(false))
+        .observe(
+            move |trigger: Trigger<Pointer<Pressed>>,
+                  mut commands: Commands,
+                  mut menu_state: ResMut<NextState<MenuState>>,
+                  mut game_state: ResMut<NextState<GameState>>,
+                  buttons: Query<(&SavedWorldMarker, Option<&Children>, Entity)>| {
+                for (marker, children_opt, entity) in buttons.iter() {
+                    // shit way
+                    let pressed_on = trigger.target == entity
+                        || children_opt
+                            .map(|children| children.iter().any(|c| c == trigger.target))
+                            .unwrap_or(false);
+
+                    if marker.0 && pressed_on {
+                        commands.insert_resource(SPSavedWorld(world_name.clone()));
+                        menu_state.set(MenuState::None);
+                        game_state.set(GameState::SinglePlayer);
+                    }
+                }
+            },
+        );
+    commands.spawn((
+        Text::new(world_info(&path)),
+        TextFont {
+            font_size: 14.0,
+            ..default()
+        },
+        TextColor(Color::srgb(0.7, 0.7, 0.7)),
+        ChildOf(column),
+    ));
+
+    let old_path = path.clone();
+    commands
+        .spawn(button("Rename", row, 120.0, 50.0))
+        .insert(RenameWorldButton(None))
+        .observe(
+            move |trigger: Trigger<Pointer<Released>>,
+                  mut commands: Commands,
+                  mut game_info: ResMut<GameInfo>,
+                  mut buttons: Query<(&mut RenameWorldButton, &Children)>,
+                  mut texts: Query<&mut Text>,
+                  children: Query<&Children>,
+                  textboxes: Query<&TextBox>| {
+                let Ok((mut rename, button_children)) = buttons.get_mut(trigger.target()) else {
+                    return;
+                };
+                // the first click opens a box for the new name
+                let Some(text_box_entity) = rename.0 else {
+                    rename.0 = Some(
+                        commands
+                            .spawn(text_box(
+                                "New Name",
+                                Some(name.clone()),
+                                "e.g. my_lovely_world",
+                                column,
+                                400.0,
+                                50.0,
+                            ))
+                            .id(),
+                    );
+                    if let Some(&child) = button_children.first()
+                        && let Ok(mut text) = texts.get_mut(child)
+                    {
+                        text.0 = "Save".into();
+                    }
+                    return;
+                };
+
+                let Some(new_name) = children
+                    .iter_descendants(text_box_entity)
+                    .find_map(|entity| textboxes.get(entity).ok())
+                    .map(|text_box| text_box.current_value.clone())
+                else {
+                    return;
+                };
+                if let Err(error) = validate_world_name(&new_name) {
+                    game_info.ui_err = Some(error.into());
+                    return;
+                }
+                if new_name == name {
+                    game_info.ui_err = None;
+                    return;
+                }
+                let new_path = Path::new("saves").join(format!("{new_name}.ferris"));
+                if new_path.exists() {
+                    game_info.ui_err = Some("World by that name already exists".into());
+                    return;
+                }
+                match std::fs::rename(&old_path, &new_path) {
+                    Ok(()) => {
+                        game_info.ui_err = None;
+                        commands.entity(row).despawn();
+                        spawn_world_row(&mut commands, list, new_name);
+                    }
+                    Err(error) => {
+                        game_info.ui_err = Some(format!("Couldn't rename world - {error}"));
+                    }
+                }
+            },
+        );
+
+    commands
+        .spawn(button("Delete", row, 120.0, 50.0))
+        .insert(DeleteWorldButton(false))
+        .observe(
+            move |trigger: Trigger<Pointer<Released>>,
+                  mut commands: Commands,
+                  mut game_info: ResMut<GameInfo>,
+                  mut buttons: Query<(&mut DeleteWorldButton, &Children)>,
+                  mut texts: Query<&mut Text>| {
+                let Ok((mut delete, children)) = buttons.get_mut(trigger.target()) else {
+                    return;
+                };
+                // the first click only asks
+                if !delete.0 {
+                    delete.0 = true;
+                    if let Some(&child) = children.first()
+                        && let Ok(mut text) = texts.get_mut(child)
+                    {
+                        text.0 = "Confirm?".into();
+                    }
+                    return;
+                }
+                match std::fs::remove_file(&path) {
+                    Ok(()) => {
+                        game_info.ui_err = None;
+                        commands.entity(row).despawn();
+                    }
+                    Err(error) => {
+                        game_info.ui_err = Some(format!("Couldn't delete world - {error}"));
+                    }
+                }
+            },
+        );
+}
+
+// the name is the file name, keep it to something every filesystem takes
+fn validate_world_name(name: &str) -> Result<(), &'static str> {
+    if name.is_empty() {
+        return Err("Name cannot be empty");
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        return Err("Name contains illegal characters");
+    }
+    if name.len() > 20 {
+        return Err("Name is too long");
+    }
+    Ok(())
+}
+
+// seed and when the world was last saved, shown under its button
+fn world_info(path: &Path) -> String {
+    let seed = SavedWorld::read_seed(path).map_or("?".to_string(), |seed| seed.to_string());
+    let modified = path
+        .metadata()
+        .and_then(|metadata| metadata.modified())
+        .map_or("?".to_string(), |time| {
+            chrono::DateTime::<chrono::Local>::from(time)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        });
+    format!("Seed: {seed} | Last played: {modified}")
+}
+
+fn sp_new_world_menu(mut commands: Commands, game_info: Res<GameInfo>) {
     let ui = commands
         .spawn(root_ui_bundle())
         .insert(StateScoped(MenuState::SinglePlayerNewWorld))
@@ -337,6 +803,59 @@ fn sp_new_world_menu(mut commands: Commands) {
     ));
     commands.spawn(text_box("Seed", None, "e.g. 69420", vertical, 400.0, 60.0));
 
+    // defaults to whatever the last world was
+    commands
+        .spawn(button(
+            game_mode_label(game_info.settings.creative),
+            vertical,
+            400.0,
+            60.0,
+        ))
+        .insert(GameModeButton)
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>,
+             mut game_info: ResMut<GameInfo>,
+             mut texts: Query<&mut Text>,
+             buttons: Query<&Children, With<GameModeButton>>| {
+                game_info.settings.creative = !game_info.settings.creative;
+                for children in buttons {
+                    if let Some(&child) = children.first()
+                        && let Ok(mut text) = texts.get_mut(child)
+                    {
+                        text.0 = game_mode_label(game_info.settings.creative).to_string();
+                    }
+                }
+            },
+        );
+
+    commands
+        .spawn(button(
+            &world_preset_label(WorldPreset::Default),
+            vertical,
+            400.0,
+            60.0,
+        ))
+        .insert(WorldPresetButton(WorldPreset::Default))
+        .observe(
+            |trigger: Trigger<Pointer<Released>>,
+             mut texts: Query<&mut Text>,
+             mut buttons: Query<(&mut WorldPresetButton, &Children)>| {
+                let Ok((mut preset, children)) = buttons.get_mut(trigger.target()) else {
+                    return;
+                };
+                let next = WorldPreset::ALL
+                    .iter()
+                    .position(|&p| p == preset.0)
+                    .map_or(0, |i| (i + 1) % WorldPreset::ALL.len());
+                preset.0 = WorldPreset::ALL[next];
+                if let Some(&child) = children.first()
+                    && let Ok(mut text) = texts.get_mut(child)
+                {
+                    text.0 = world_preset_label(preset.0);
+                }
+            },
+        );
+
     commands.spawn((
         ErrorText,
         Text::new(""),
@@ -359,7 +878,8 @@ fn sp_new_world_menu(mut commands: Commands) {
              mut game_info: ResMut<GameInfo>,
              mut menu_state: ResMut<NextState<MenuState>>,
              mut game_state: ResMut<NextState<GameState>>,
-             textbox: Query<&mut TextBox>| {
+             textbox: Query<&mut TextBox>,
+             preset: Single<&WorldPresetButton>| {
                 let mut name = String::new();
                 let mut seed = String::new();
                 for t in textbox.iter() {
@@ -371,33 +891,27 @@ fn sp_new_world_menu(mut commands: Commands) {
                     }
                 }
 
-                if name.is_empty() {
-                    game_info.ui_err = Some("Name cannot be empty".into());
-                    return;
-                }
-
-                if !name
-                    .chars()
-                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
-                {
-                    game_info.ui_err = Some("Name contains illegal characters".into());
-                    return;
-                }
-
-                if name.len() > 20 {
-                    game_info.ui_err = Some("Name is too long".into());
+                if let Err(error) = validate_world_name(&name) {
+                    game_info.ui_err = Some(error.into());
                     return;
                 }
 
+                let creative = game_info.settings.creative;
+                let world_gen = preset.0.config();
                 if !Path::new("saves").join(format!("{}.ferris", name)).exists() {
                     if seed.is_empty() {
                         game_info.ui_err = None;
-                        commands.insert_resource(SPNewWorld(name, rand::random()));
+                        commands.insert_resource(SPNewWorld(
+                            name,
+                            rand::random(),
+                            creative,
+                            world_gen,
+                        ));
                         menu_state.set(MenuState::None);
                         game_state.set(GameState::SinglePlayer);
                     } else if let Ok(seed) = seed.parse::<u32>() {
                         game_info.ui_err = None;
-                        commands.insert_resource(SPNewWorld(name, seed));
+                        commands.insert_resource(SPNewWorld(name, seed, creative, world_gen));
                         menu_state.set(MenuState::None);
                         game_state.set(GameState::SinglePlayer);
                     } else {
@@ -417,6 +931,18 @@ fn sp_new_world_menu(mut commands: Commands) {
         );
 }
 
+fn game_mode_label(creative: bool) -> &'static str {
+    if creative {
+        "Mode: Creative"
+    } else {
+        "Mode: Survival"
+    }
+}
+
+fn world_preset_label(preset: WorldPreset) -> String {
+    format!("World: {}", preset.name())
+}
+
 fn multiplayer_menu(mut commands: Commands, game_info: Res<GameInfo>) {
     let ui = commands
         .spawn(root_ui_bundle())
@@ -425,9 +951,10 @@ fn multiplayer_menu(mut commands: Commands, game_info: Res<GameInfo>) {
 
     let vertical = commands.spawn(vertical_ui_bundle(ui)).id();
 
+    // the reason is set before the menu exists when the connection drops
     commands.spawn((
         ErrorText,
-        Text::new(""),
+        Text::new(game_info.ui_err.clone().unwrap_or_default()),
         TextColor(Color::srgb(1.0, 0.0, 0.0)),
         Node {
             max_width: Val::Px(375.0),
@@ -454,6 +981,18 @@ fn multiplayer_menu(mut commands: Commands, game_info: Res<GameInfo>) {
         60.0,
     ));
 
+    // lan servers, filled in by update_server_list as their beacons come in
+    commands.spawn((
+        ServerList,
+        Node {
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(6.0),
+            ..default()
+        },
+        ChildOf(vertical),
+    ));
+
     let horizontal = commands.spawn(horizontal_ui_bundle(vertical)).id();
 
     commands
@@ -474,58 +1013,292 @@ fn multiplayer_menu(mut commands: Commands, game_info: Res<GameInfo>) {
                         address = t.current_value.clone();
                     }
                 }
-                if name.is_empty() {
-                    game_info.ui_err = Some("Player name cannot be empty".into());
-                    return;
-                }
-                if name.len() < 3 {
-                    game_info.ui_err =
-                        Some("Player name cannot be shorter than 3 characters".into());
-                    return;
-                }
-                if name.len() > 16 {
-                    game_info.ui_err =
-                        Some("Player name cannot be longer than 16 characters".into());
-                    return;
-                }
-                if !name
-                    .chars()
-                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
-                {
-                    game_info.ui_err = Some("Name contains illegal characters".into());
-                    return;
-                }
-                if address.is_empty() {
-                    game_info.ui_err = Some("Server address cannot be empty".into());
-                    return;
-                }
-                let addr = address
-                    .parse::<SocketAddrV4>()
-                    .map(SocketAddr::V4)
-                    .ok()
-                    .or(address
-                        .parse::<Ipv4Addr>()
-                        .ok()
-                        .map(|ip| SocketAddr::V4(SocketAddrV4::new(ip, DEFAULT_SERVER_PORT))))
-                    .or((if address.contains(':') {
-                        address.to_string()
-                    } else {
-                        format!("{}:{}", address, DEFAULT_SERVER_PORT)
-                    })
-                    .to_socket_addrs()
-                    .ok()
-                    .and_then(|mut x| x.find(|x| x.is_ipv4())));
-                if let Some(addr) = addr {
-                    println!("Connecting to {}", addr);
-                    game_info.settings.player_name = name;
-                    game_info.settings.server_addr = address;
-                    game_info.connection_addr = Some(addr);
-                    game_info.settings.write().ok();
+                connect(
+                    &mut game_info,
+                    &mut menu_state,
+                    &mut game_state,
+                    name,
+                    address,
+                );
+            },
+        );
+    // same server and name as last time, without resolving the address again
+    if game_info.connection_addr.is_some() {
+        commands
+            .spawn(button("Reconnect", horizontal, 150.0, 50.0))
+            .observe(
+                |_trigger: Trigger<Pointer<Released>>,
+                 mut menu_state: ResMut<NextState<MenuState>>,
+                 mut game_state: ResMut<NextState<GameState>>| {
                     menu_state.set(MenuState::None);
                     game_state.set(GameState::MultiPlayer);
+                },
+            );
+    }
+    commands
+        .spawn(button("Back", horizontal, 150.0, 50.0))
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>, mut state: ResMut<NextState<MenuState>>| {
+                state.set(MenuState::Main);
+            },
+        );
+}
+
+fn update_server_list(
+    mut commands: Commands,
+    list: Single<Entity, With<ServerList>>,
+    discovered: Res<DiscoveredServers>,
+) {
+    commands.entity(*list).despawn_related::<Children>();
+
+    let mut servers = discovered.servers.iter().collect::<Vec<_>>();
+    servers.sort_by(|a, b| a.1.0.name.cmp(&b.1.0.name));
+    for (&addr, (beacon, _)) in servers {
+        let mut label = format!(
+            "{} - {}/{}",
+            beacon.name, beacon.players, beacon.max_players
+        );
+        if beacon.version != env!("CARGO_PKG_VERSION") {
+            label += &format!(" (v{})", beacon.version);
+        }
+        commands.spawn(button(&label, *list, 400.0, 50.0)).observe(
+            move |_trigger: Trigger<Pointer<Released>>,
+                  mut game_info: ResMut<GameInfo>,
+                  mut menu_state: ResMut<NextState<MenuState>>,
+                  mut game_state: ResMut<NextState<GameState>>,
+                  textbox: Query<&TextBox>| {
+                let name = textbox
+                    .iter()
+                    .find(|t| t.name == "Player Name")
+                    .map(|t| t.current_value.clone())
+                    .unwrap_or_default();
+                connect(
+                    &mut game_info,
+                    &mut menu_state,
+                    &mut game_state,
+                    name,
+                    addr.to_string(),
+                );
+            },
+        );
+    }
+}
+
+// validates the name and resolves the address, the connection itself starts with the state
+fn connect(
+    game_info: &mut GameInfo,
+    menu_state: &mut NextState<MenuState>,
+    game_state: &mut NextState<GameState>,
+    name: String,
+    address: String,
+) {
+    if name.is_empty() {
+        game_info.ui_err = Some("Player name cannot be empty".into());
+        return;
+    }
+    if name.len() < 3 {
+        game_info.ui_err = Some("Player name cannot be shorter than 3 characters".into());
+        return;
+    }
+    if name.len() > 16 {
+        game_info.ui_err = Some("Player name cannot be longer than 16 characters".into());
+        return;
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+    {
+        game_info.ui_err = Some("Name contains illegal characters".into());
+        return;
+    }
+    if address.is_empty() {
+        game_info.ui_err = Some("Server address cannot be empty".into());
+        return;
+    }
+    let addr = address
+        .parse::<SocketAddrV4>()
+        .map(SocketAddr::V4)
+        .ok()
+        .or(address
+            .parse::<Ipv4Addr>()
+            .ok()
+            .map(|ip| SocketAddr::V4(SocketAddrV4::new(ip, DEFAULT_SERVER_PORT))))
+        .or((if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, DEFAULT_SERVER_PORT)
+        })
+        .to_socket_addrs()
+        .ok()
+        .and_then(|mut x| x.find(|x| x.is_ipv4())));
+    if let Some(addr) = addr {
+        println!("Connecting to {}", addr);
+        game_info.settings.player_name = name;
+        game_info.settings.server_addr = address;
+        game_info.connection_addr = Some(addr);
+        game_info.settings.write().ok();
+        menu_state.set(MenuState::None);
+        game_state.set(GameState::MultiPlayer);
+    } else {
+        game_info.ui_err = Some("Invalid address".into());
+    }
+}
+
+fn settings_menu(mut commands: Commands, game_info: Res<GameInfo>) {
+    let ui = commands
+        .spawn(root_ui_bundle())
+        .insert((
+            StateScoped(MenuState::Settings),
+            BackgroundColor(Color::BLACK.with_alpha(0.7)),
+        ))
+        .id();
+
+    let vertical = commands.spawn(vertical_ui_bundle(ui)).id();
+
+    for setting in Setting::ALL {
+        let row = commands
+            .spawn(Node {
+                align_items: AlignItems::Center,
+                column_gap: Val::Px(10.0),
+                width: Val::Px(460.0),
+                ..default()
+            })
+            .insert(ChildOf(vertical))
+            .id();
+
+        commands.spawn((
+            Text::new(setting.name()),
+            Node {
+                flex_grow: 1.0,
+                ..default()
+            },
+            ChildOf(row),
+        ));
+
+        let toggle = matches!(
+            setting,
+            Setting::ViewBob
+                | Setting::Fog
+                | Setting::Clouds
+                | Setting::DespawnChunks
+                | Setting::Autosave
+                | Setting::ScreenshotFormat
+        );
+
+        if !toggle {
+            commands.spawn(button("-", row, 50.0, 40.0)).observe(
+                move |_trigger: Trigger<Pointer<Released>>, mut game_info: ResMut<GameInfo>| {
+                    setting.step(&mut game_info.settings, -1);
+                },
+            );
+        }
+        commands.spawn((
+            setting,
+            Text::new(setting.value(&game_info.settings)),
+            TextLayout::new_with_justify(JustifyText::Center),
+            Node {
+                width: Val::Px(if toggle { 170.0 } else { 60.0 }),
+                ..default()
+            },
+            ChildOf(row),
+        ));
+        commands
+            .spawn(button(if toggle { "Toggle" } else { "+" }, row, 50.0, 40.0))
+            .observe(
+                move |_trigger: Trigger<Pointer<Released>>, mut game_info: ResMut<GameInfo>| {
+                    setting.step(&mut game_info.settings, 1);
+                },
+            );
+    }
+
+    commands
+        .spawn(button("Back", vertical, 150.0, 50.0))
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>,
+             mut state: ResMut<NextState<MenuState>>,
+             game_state: Res<State<GameState>>| {
+                state.set(if game_state.get() == &GameState::Menu {
+                    MenuState::Main
                 } else {
-                    game_info.ui_err = Some("Invalid address".into());
-                }
+                    MenuState::None
+                });
+            },
+        );
+}
+
+fn update_setting_labels(mut labels: Query<(&Setting, &mut Text)>, game_info: Res<GameInfo>) {
+    for (setting, mut text) in &mut labels {
+        text.0 = setting.value(&game_info.settings);
+    }
+}
+
+fn controls_menu(
+    mut commands: Commands,
+    mut rebinding: ResMut<Rebinding>,
+    keybindings: Res<Persistent<KeyBindings>>,
+) {
+    rebinding.0 = None;
+
+    let ui = commands
+        .spawn(root_ui_bundle())
+        .insert(StateScoped(MenuState::Controls))
+        .id();
+
+    let vertical = commands.spawn(vertical_ui_bundle(ui)).id();
+    let columns = commands.spawn(horizontal_ui_bundle(vertical)).id();
+
+    // two columns so everything fits on smaller screens
+    for actions in Action::ALL.chunks(Action::ALL.len().div_ceil(2)) {
+        let column = commands
+            .spawn(Node {
+                flex_direction: FlexDirection::Column,
+                row_gap: Val::Px(8.0),
+                ..default()
+            })
+            .insert(ChildOf(columns))
+            .id();
+
+        for &action in actions {
+            let row = commands
+                .spawn(Node {
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::SpaceBetween,
+                    column_gap: Val::Px(20.0),
+                    width: Val::Px(420.0),
+                    ..default()
+                })
+                .insert(ChildOf(column))
+                .id();
+
+            commands.spawn((Text::new(action.name()), ChildOf(row)));
+            commands
+                .spawn(button(
+                    &format!("{:?}", keybindings.key(action)),
+                    row,
+                    200.0,
+                    40.0,
+                ))
+                .insert(KeyBindButton(action))
+                .observe(
+                    move |_trigger: Trigger<Pointer<Released>>,
+                          mut rebinding: ResMut<Rebinding>| {
+                        rebinding.0 = Some(action);
+                    },
+                );
+        }
+    }
+
+    let horizontal = commands.spawn(horizontal_ui_bundle(vertical)).id();
+
+    commands
+        .spawn(button("Reset", horizontal, 150.0, 50.0))
+        .observe(
+            |_trigger: Trigger<Pointer<Released>>,
+             mut rebinding: ResMut<Rebinding>,
+             mut keybindings: ResMut<Persistent<KeyBindings>>| {
+                keybindings.update(|k| *k = KeyBindings::default()).ok();
+                // also refreshes the labels
+                rebinding.0 = None;
             },
         );
     commands
@@ -537,6 +1310,38 @@ fn multiplayer_menu(mut commands: Commands, game_info: Res<GameInfo>) {
         );
 }
 
+fn handle_rebinding(
+    mut rebinding: ResMut<Rebinding>,
+    mut keybindings: ResMut<Persistent<KeyBindings>>,
+    mut texts: Query<&mut Text>,
+    buttons: Query<(&KeyBindButton, &Children)>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+) {
+    // escape is left to the escape button system, which cancels
+    if let Some(action) = rebinding.0
+        && let Some(&key) = keyboard
+            .get_just_pressed()
+            .find(|&&key| key != KeyCode::Escape)
+    {
+        keybindings.update(|k| k.rebind(action, key)).ok();
+        rebinding.0 = None;
+    }
+
+    if rebinding.is_changed() {
+        for (button, children) in buttons {
+            if let Some(&child) = children.first()
+                && let Ok(mut text) = texts.get_mut(child)
+            {
+                text.0 = if rebinding.0 == Some(button.0) {
+                    "> press a key <".to_string()
+                } else {
+                    format!("{:?}", keybindings.key(button.0))
+                };
+            }
+        }
+    }
+}
+
 fn handle_errors(mut error_text: Single<&mut Text, With<ErrorText>>, game_info: Res<GameInfo>) {
     if game_info.is_changed()
         && let Some(err) = &game_info.ui_err
@@ -545,6 +1350,44 @@ fn handle_errors(mut error_text: Single<&mut Text, With<ErrorText>>, game_info:
     }
 }
 
+const TOAST_TIME: f32 = 3.0; // seconds
+const TOAST_FADE: f32 = 1.0; // of those, spent fading out
+
+// short message at the top of the screen that goes away on its own
+#[derive(Component)]
+pub struct Toast(f32); // seconds left
+
+pub fn toast(commands: &mut Commands, message: impl Into<String>) {
+    commands.spawn((
+        Text::new(message),
+        TextShadow::default(),
+        TextLayout::new_with_justify(JustifyText::Center),
+        Node {
+            position_type: PositionType::Absolute,
+            top: Val::Px(40.0),
+            width: Val::Percent(100.0),
+            ..default()
+        },
+        GlobalZIndex(i32::MAX),
+        Toast(TOAST_TIME),
+    ));
+}
+
+fn handle_toasts(
+    mut commands: Commands,
+    toasts: Query<(Entity, &mut Toast, &mut TextColor)>,
+    time: Res<Time>,
+) {
+    for (entity, mut toast, mut color) in toasts {
+        toast.0 -= time.delta_secs();
+        if toast.0 <= 0.0 {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        color.0 = color.0.with_alpha((toast.0 / TOAST_FADE).min(1.0));
+    }
+}
+
 fn handle_buttons(
     mut interaction_query: Query<
         (&Interaction, &mut BackgroundColor),
@@ -570,14 +1413,16 @@ fn handle_textboxes(
     mut key_evr: EventReader<KeyboardInput>,
     mut query: Query<(&mut Text, &mut TextBox)>,
 ) {
+    // read every frame so keys pressed before a textbox gets selected don't leak into it
+    let events = key_evr.read().collect::<Vec<_>>();
     for (mut text, mut textbox) in query.iter_mut() {
         if textbox.selected {
-            for ev in key_evr.read() {
+            for ev in &events {
                 if ev.state == ButtonState::Pressed {
                     if ev.key_code == KeyCode::Backspace {
                         textbox.current_value.pop();
                     } else if let Some(t) = &ev.text {
-                        for ch in t.chars() {
+                        for ch in t.chars().filter(|ch| !ch.is_control()) {
                             textbox.current_value.push(ch);
                         }
                     }
@@ -596,11 +1441,16 @@ fn handle_hud(
     mut mouse_scroll: EventReader<MouseWheel>,
     mut game_info: ResMut<GameInfo>,
     mut coords_text: Single<&mut Text, With<CoordsText>>,
-    player: Single<&Transform, With<Player>>,
-    perf_ui: Query<&mut Visibility, With<PerfUiEntryFPS>>,
+    mut hotbar_counts: Query<(&mut Text, &HotbarCount), Without<CoordsText>>,
+    player: Single<(&Transform, &Inventory, &Health), With<Player>>,
+    perf_ui: Query<&mut Visibility, (With<PerfUiEntryFPS>, Without<HiddenForScreenshot>)>,
+    chunk_tasks: Query<(), With<ComputeChunk>>,
+    mesh_tasks: Query<(), With<ComputeChunkMesh>>,
+    camera: Single<&GlobalTransform, With<Camera3d>>,
+    diagnostics: Res<DiagnosticsStore>,
 ) {
     for (mut image, block) in hotbar_blocks.iter_mut() {
-        if block.0 == game_info.current_block as u8 {
+        if block.0 == game_info.current_block {
             image.image_mode = NodeImageMode::Sliced(TextureSlicer {
                 border: BorderRect::all(2.0),
                 ..default()
@@ -612,31 +1462,84 @@ fn handle_hud(
         }
     }
 
+    let (player, inventory, health) = player.into_inner();
+
+    for (mut text, count) in &mut hotbar_counts {
+        text.0 = if game_info.creative {
+            String::new()
+        } else {
+            inventory.count(count.0).to_string()
+        };
+    }
+
     let (_, biome) = terrain_noise(player.translation.xz(), &game_info.noises);
 
     let deg = player.rotation.to_euler(EulerRot::YXZ).0.to_degrees();
     let deg = if deg < 0.0 { deg + 360.0 } else { deg };
+    let time = game_info
+        .time_of_day
+        .map(|time| {
+            let minutes = (time * 24.0 * 60.0) as u32;
+            format!("\nTime: {:02}:{:02}", minutes / 60, minutes % 60)
+        })
+        .unwrap_or_default();
+    let health = if game_info.creative {
+        String::new()
+    } else {
+        format!("\nHealth: {}/{}", health.0, MAX_HEALTH)
+    };
+    let in_hand = if game_info.current_block.can_rotate() {
+        match game_info.placement_direction {
+            Some(direction) => format!("{:?} ({:?})", game_info.current_block, direction),
+            None => format!("{:?} (Auto)", game_info.current_block),
+        }
+    } else {
+        format!("{:?}", game_info.current_block)
+    };
+    // rest of the f3 screen, chunk counts show when process_tasks falls behind
+    let debug = if game_info.settings.debug_menus {
+        let smoothed = |path: &DiagnosticPath| {
+            diagnostics
+                .get(path)
+                .and_then(|diagnostic| diagnostic.smoothed())
+                .unwrap_or_default()
+        };
+        let looking_at = ray_cast(
+            &game_info,
+            camera.translation(),
+            camera.forward().into(),
+            game_info.settings.reach,
+        )
+        .map_or("Nothing".into(), |hit| {
+            format!(
+                "{:?} at {} ({:?})",
+                hit.block.kind, hit.global_position, hit.normal
+            )
+        });
+        format!(
+            "\nFPS: {:.0} ({:.2}ms)\nLooking At: {}\nChunk Tasks: {}\nMesh Tasks: {}\nLoaded Chunks: {}\nLoading Chunks: {}\nSaved Chunks: {}",
+            smoothed(&FrameTimeDiagnosticsPlugin::FPS),
+            smoothed(&FrameTimeDiagnosticsPlugin::FRAME_TIME),
+            looking_at,
+            chunk_tasks.iter().count(),
+            mesh_tasks.iter().count(),
+            game_info.chunks.read().unwrap().len(),
+            game_info.loading_chunks.read().unwrap().len(),
+            game_info
+                .saved_chunks
+                .as_ref()
+                .map_or(0, |saved_chunks| saved_chunks.read().unwrap().len()),
+        )
+    } else {
+        String::new()
+    };
+    let (chunk_pos, local_pos) = chunk_coords(player.translation.floor().as_ivec3());
     coords_text.0 = format!(
-        "Coord: {:.02}\nBlock: {}\nChunk: {}\nBiome: {}\nFacing: {} - {}deg\nIn Hand: {:?}",
+        "Coord: {:.02}\nBlock: {}\nChunk: {}\nBiome: {:?}\nFacing: {} - {}deg\nIn Hand: {}\nSeed: {}{}{}{}",
         player.translation,
-        vec3(
-            player.translation.x.rem_euclid(CHUNK_SIZE as f32),
-            player.translation.y,
-            player.translation.z.rem_euclid(CHUNK_SIZE as f32),
-        )
-        .as_ivec3(),
-        ivec2(
-            player.translation.x.div_euclid(CHUNK_SIZE as f32) as i32,
-            player.translation.z.div_euclid(CHUNK_SIZE as f32) as i32,
-        ),
-        // not really
-        if biome < 0.4 {
-            "Ocean"
-        } else if biome > 0.6 {
-            "Mountains"
-        } else {
-            "Plains"
-        },
+        local_pos,
+        chunk_pos.xz(),
+        Biome::from_noise(biome),
         match deg {
             x if !(22.5..337.5).contains(&x) => "N",
             x if (22.5..67.5).contains(&x) => "NE",
@@ -649,22 +1552,25 @@ fn handle_hud(
             _ => "N",
         },
         deg as i32,
-        game_info.current_block
+        in_hand,
+        game_info.seed,
+        time,
+        health,
+        debug,
     );
 
-    if !game_info.paused {
+    if !game_info.paused && !game_info.chat_open {
+        let slots = hotbar_slots(&game_info.settings);
         for ev in mouse_scroll.read() {
-            let dir = -ev.y.signum();
-            let mut next = game_info.current_block as i32 + dir as i32;
-            if next == BlockKind::Water as i32 {
-                next += dir as i32;
+            let dir = -ev.y.signum() as i32;
+            // a block that isn't in the hotbar scrolls to the first slot
+            let next = slots
+                .iter()
+                .position(|&kind| kind == game_info.current_block)
+                .map_or(0, |i| (i as i32 + dir).rem_euclid(slots.len() as i32));
+            if let Some(&kind) = slots.get(next as usize) {
+                game_info.current_block = kind;
             }
-            if next < 1 {
-                next = 10;
-            } else if next > 10 {
-                next = 1;
-            }
-            game_info.current_block = BlockKind::from_u32(next as u32);
         }
 
         for mut visibility in perf_ui {
@@ -837,7 +1743,111 @@ pub fn coords_bundle(ui: Entity) -> impl Bundle {
     )
 }
 
-pub fn hotbar_bundle(ui: Entity) -> impl Bundle {
+const CROSSHAIR_SIZE: f32 = 16.0;
+const CROSSHAIR_THICKNESS: f32 = 2.0;
+
+#[derive(Component)]
+pub struct Crosshair;
+
+// two thin bars, centered by the root ui node
+pub fn crosshair_bundle(ui: Entity) -> impl Bundle {
+    let bar = |width, height| {
+        (
+            Node {
+                position_type: PositionType::Absolute,
+                width: Val::Px(width),
+                height: Val::Px(height),
+                ..default()
+            },
+            BackgroundColor(Color::WHITE),
+        )
+    };
+    (
+        Node {
+            position_type: PositionType::Absolute,
+            width: Val::Px(CROSSHAIR_SIZE),
+            height: Val::Px(CROSSHAIR_SIZE),
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            ..default()
+        },
+        Crosshair,
+        children![
+            bar(CROSSHAIR_SIZE, CROSSHAIR_THICKNESS),
+            bar(CROSSHAIR_THICKNESS, CROSSHAIR_SIZE),
+        ],
+        ChildOf(ui),
+    )
+}
+
+// hidden while paused, tinted when there's a block in reach
+fn handle_crosshair(
+    crosshair: Single<(&mut Visibility, &Children), With<Crosshair>>,
+    mut bars: Query<&mut BackgroundColor>,
+    camera: Single<&GlobalTransform, With<Camera3d>>,
+    game_info: Res<GameInfo>,
+) {
+    let (mut visibility, children) = crosshair.into_inner();
+
+    visibility.set_if_neq(if game_info.paused {
+        Visibility::Hidden
+    } else {
+        Visibility::Inherited
+    });
+
+    let targeting = ray_cast(
+        &game_info,
+        camera.translation(),
+        camera.forward().into(),
+        game_info.settings.reach,
+    )
+    .is_some();
+    let color = if targeting {
+        Color::srgb(1.0, 0.85, 0.3)
+    } else {
+        Color::WHITE
+    };
+    let mut bars = bars.iter_many_mut(children);
+    while let Some(mut bar) = bars.fetch_next() {
+        bar.set_if_neq(BackgroundColor(color));
+    }
+}
+
+// every placeable block in order, cut off at the configured slot count
+pub fn hotbar_slots(settings: &GameSettings) -> Vec<BlockKind> {
+    BlockKind::ALL
+        .into_iter()
+        .filter(|kind| kind.is_solid())
+        .take(settings.hotbar_slots)
+        .collect()
+}
+
+pub fn spawn_hotbar(
+    commands: &mut Commands,
+    ui: Entity,
+    atlas: Handle<Image>,
+    settings: &GameSettings,
+) {
+    let slots = hotbar_slots(settings);
+    let hotbar = commands.spawn(hotbar_bundle(ui, slots.len())).id();
+    let node = ImageNode::new(atlas);
+
+    for kind in slots {
+        commands
+            .spawn(hotbar_block(hotbar, node.clone(), kind))
+            .observe(
+                |trigger: Trigger<Pointer<Released>>,
+                 slots: Query<&HotbarBlock>,
+                 mut game_info: ResMut<GameInfo>| {
+                    if let Ok(slot) = slots.get(trigger.target) {
+                        game_info.current_block = slot.0;
+                    }
+                },
+            );
+    }
+}
+
+fn hotbar_bundle(ui: Entity, slots: usize) -> impl Bundle {
     (
         Node {
             position_type: PositionType::Absolute,
@@ -845,7 +1855,8 @@ pub fn hotbar_bundle(ui: Entity) -> impl Bundle {
             align_items: AlignItems::Center,
             align_content: AlignContent::SpaceEvenly,
             justify_content: JustifyContent::SpaceEvenly,
-            width: Val::Px(464.0),
+            // 48px per block plus spacing
+            width: Val::Px(52.0 * slots as f32 - 4.0),
             height: Val::Px(56.0),
             bottom: Val::Vh(2.0),
             ..default()
@@ -855,14 +1866,10 @@ pub fn hotbar_bundle(ui: Entity) -> impl Bundle {
     )
 }
 
-pub fn hotbar_block(hotbar: Entity, node: ImageNode, idx: u8) -> impl Bundle {
+fn hotbar_block(hotbar: Entity, node: ImageNode, kind: BlockKind) -> impl Bundle {
+    let (min, max) = ATLAS.tile_rect(kind.tiles().top);
     (
-        node.with_rect(Rect::new(
-            0.0,
-            16.0 * (idx - 1) as f32,
-            16.0,
-            16.0 * idx as f32,
-        )),
+        node.with_rect(Rect::from_corners(min, max)),
         Node {
             width: Val::Px(48.0),
             height: Val::Px(48.0),
@@ -870,7 +1877,25 @@ pub fn hotbar_block(hotbar: Entity, node: ImageNode, idx: u8) -> impl Bundle {
             align_items: AlignItems::Center,
             ..default()
         },
-        HotbarBlock(idx),
+        HotbarBlock(kind),
+        children![(
+            Text::default(),
+            TextFont {
+                font_size: 14.0,
+                ..default()
+            },
+            TextColor(Color::WHITE),
+            TextShadow::default(),
+            Node {
+                position_type: PositionType::Absolute,
+                right: Val::Px(2.0),
+                bottom: Val::Px(0.0),
+                ..default()
+            },
+            // clicks go to the slot underneath
+            Pickable::IGNORE,
+            HotbarCount(kind),
+        )],
         ChildOf(hotbar),
     )
 }
@@ -1,23 +1,26 @@
 use bevy::{prelude::*, window::CursorGrabMode};
 use ferriscraft::{Block, Direction};
-use noiz::{
-    Noise, NoiseFunction, SampleableFor,
-    prelude::{
-        FractalLayers, Normed, Persistence,
-        common_noise::{Fbm, Perlin, Simplex},
-    },
-    rng::NoiseRng,
-};
 
-use crate::{CHUNK_HEIGHT, CHUNK_SIZE, GameInfo, world::utils::NoiseFunctions};
+use crate::{CHUNK_HEIGHT, CHUNK_SIZE, GameInfo, world::utils::chunk_coords};
 
 #[inline]
 pub fn vec3_to_index(pos: IVec3) -> usize {
+    // the mesher indexes with get_unchecked, out of bounds there is ub instead of a panic
+    debug_assert!(
+        (0..CHUNK_SIZE).contains(&pos.x)
+            && (0..CHUNK_HEIGHT).contains(&pos.y)
+            && (0..CHUNK_SIZE).contains(&pos.z),
+        "block position {pos} is outside the chunk"
+    );
     (pos.x + pos.y * CHUNK_SIZE + pos.z * CHUNK_SIZE * CHUNK_HEIGHT) as usize
 }
 
 #[inline]
 pub fn index_to_vec3(index: usize) -> IVec3 {
+    debug_assert!(
+        index < (CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE) as usize,
+        "block index {index} is outside the chunk"
+    );
     ivec3(
         index as i32 % CHUNK_SIZE,
         (index as i32 / CHUNK_SIZE) % CHUNK_HEIGHT,
@@ -25,6 +28,22 @@ pub fn index_to_vec3(index: usize) -> IVec3 {
     )
 }
 
+// fastest anything falls, in blocks per second
+const TERMINAL_VELOCITY: f32 = 78.4;
+
+// gravity is a positive magnitude, it only gets its direction here
+pub fn apply_gravity(velocity_y: f32, gravity: f32, delta: f32) -> f32 {
+    (velocity_y - gravity * delta).max(-TERMINAL_VELOCITY)
+}
+
+// how far below the feet the ground is, if it's within this frame's fall. the ray starts
+// `above` the feet so something sunk a little into a block still finds its top.
+// the length follows the fall, a fixed one lets fast falls skip through the floor
+pub fn ground_distance(game_info: &GameInfo, feet: Vec3, above: f32, fall: f32) -> Option<f32> {
+    ray_cast(game_info, feet + Vec3::Y * above, Vec3::NEG_Y, above + fall)
+        .map(|hit| hit.distance - above)
+}
+
 #[inline]
 pub fn aabb_collision(pos1: Vec3, size1: Vec3, pos2: Vec3, size2: Vec3) -> bool {
     let min1 = pos1;
@@ -41,12 +60,6 @@ pub fn aabb_collision(pos1: Vec3, size1: Vec3, pos2: Vec3, size2: Vec3) -> bool
         && max1.z > min2.z
 }
 
-#[inline]
-pub fn noise<T: NoiseFunction<Vec2, Output = f32>>(noise: Noise<T>, pos: Vec2) -> f32 {
-    let n: f32 = noise.sample(pos);
-    (n + 1.0) / 2.0
-}
-
 #[inline]
 pub fn set_cursor_grab(window: &mut Window, val: bool) {
     if val {
@@ -58,58 +71,29 @@ pub fn set_cursor_grab(window: &mut Window, val: bool) {
     }
 }
 
-#[inline]
-pub fn get_noise_functions(seed: u32) -> NoiseFunctions {
-    NoiseFunctions {
-        terrain: Noise {
-            noise: Fbm::<Simplex>::new(
-                Normed::default(),
-                Persistence(0.5),
-                FractalLayers {
-                    amount: 4,
-                    lacunarity: 2.0,
-                    ..Default::default()
-                },
-            ),
-            frequency: 0.00200,
-            seed: NoiseRng(seed),
-        },
-        biome: Noise {
-            noise: Fbm::<Simplex>::new(
-                Normed::default(),
-                Persistence(0.6),
-                FractalLayers {
-                    amount: 3,
-                    lacunarity: 2.0,
-                    ..Default::default()
-                },
-            ),
-            frequency: 0.0001,
-            seed: NoiseRng(seed + 1),
-        },
-        tree: Noise {
-            noise: Perlin::default(),
-            frequency: 0.069,
-            seed: NoiseRng(seed),
-        },
-        ferris: Noise {
-            noise: Perlin::default(),
-            frequency: 0.42,
-            seed: NoiseRng(seed),
-        },
-    }
-}
-
 #[derive(Debug)]
 pub struct RayHit {
     pub global_position: IVec3,
-    pub chunk_pos: IVec3,
-    pub local_pos: IVec3,
     pub normal: Direction,
-    pub _block: Block,
+    pub block: Block,
     pub distance: f32,
 }
 
+// None if the chunk isn't loaded
+pub fn get_block(game_info: &GameInfo, pos: Vec3) -> Option<Block> {
+    let pos = pos.floor().as_ivec3();
+    if !(0..CHUNK_HEIGHT).contains(&pos.y) {
+        return None;
+    }
+    let (chunk_pos, local_pos) = chunk_coords(pos);
+    game_info
+        .chunks
+        .read()
+        .unwrap()
+        .get(&chunk_pos)
+        .map(|chunk| chunk.blocks[vec3_to_index(local_pos)])
+}
+
 pub fn ray_cast(
     game_info: &GameInfo,
     ray_origin: Vec3,
@@ -204,36 +188,16 @@ pub fn ray_cast(
             break;
         }
 
-        let chunk_pos = ivec3(
-            current_block_pos.x.div_euclid(CHUNK_SIZE as f32) as i32,
-            0,
-            current_block_pos.z.div_euclid(CHUNK_SIZE as f32) as i32,
-        );
-
-        let local_block_pos = vec3(
-            current_block_pos.x.rem_euclid(CHUNK_SIZE as f32),
-            current_block_pos.y,
-            current_block_pos.z.rem_euclid(CHUNK_SIZE as f32),
-        )
-        .as_ivec3();
-
-        if let Some(chunk) = game_info.chunks.read().unwrap().get(&chunk_pos) {
-            let block_index = vec3_to_index(local_block_pos);
-
-            if block_index < chunk.blocks.len() && (0..CHUNK_HEIGHT).contains(&local_block_pos.y) {
-                let block = chunk.blocks[block_index];
-
-                if block.kind.is_solid() {
-                    return Some(RayHit {
-                        global_position: current_block_pos.as_ivec3(),
-                        chunk_pos,
-                        local_pos: local_block_pos,
-                        normal,
-                        _block: block,
-                        distance: current_distance,
-                    });
-                }
-            }
+        // unloaded chunks and above or below the world are air, there's nothing to hit
+        let global_position = current_block_pos.as_ivec3();
+        let block = game_info.block_at(global_position);
+        if block.kind.is_solid() {
+            return Some(RayHit {
+                global_position,
+                normal,
+                block,
+                distance: current_distance,
+            });
         }
     }
 
@@ -388,3 +352,479 @@ pub const TREE_OBJECT: [[[Block; 5]; 5]; 7] = [
         [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
     ],
 ];
+
+// mountain tree, narrows towards the top
+pub const SPRUCE_OBJECT: [[[Block; 5]; 5]; 9] = [
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::WOOD, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::WOOD, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::WOOD, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+        ],
+        [
+            Block::LEAF,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::LEAF,
+        ],
+        [
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+        ],
+        [
+            Block::LEAF,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::LEAF,
+        ],
+        [
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::LEAF, Block::AIR, Block::AIR],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [Block::AIR, Block::AIR, Block::LEAF, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::LEAF, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+];
+
+// spruce above the snow line, snow wherever the leaves face the sky
+pub const SNOWY_SPRUCE_OBJECT: [[[Block; 5]; 5]; 9] = [
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::WOOD, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::WOOD, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::WOOD, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::SNOW,
+            Block::SNOW,
+            Block::AIR,
+        ],
+        [
+            Block::SNOW,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::SNOW,
+        ],
+        [
+            Block::SNOW,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::SNOW,
+        ],
+        [
+            Block::SNOW,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::SNOW,
+        ],
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::SNOW,
+            Block::SNOW,
+            Block::AIR,
+        ],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::SNOW,
+            Block::SNOW,
+            Block::AIR,
+        ],
+        [
+            Block::SNOW,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::SNOW,
+        ],
+        [
+            Block::SNOW,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::SNOW,
+        ],
+        [
+            Block::SNOW,
+            Block::LEAF,
+            Block::LEAF,
+            Block::LEAF,
+            Block::SNOW,
+        ],
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::SNOW,
+            Block::SNOW,
+            Block::AIR,
+        ],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::LEAF,
+            Block::SNOW,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::LEAF,
+            Block::WOOD,
+            Block::LEAF,
+            Block::AIR,
+        ],
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::LEAF,
+            Block::SNOW,
+            Block::AIR,
+        ],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::SNOW, Block::AIR, Block::AIR],
+        [
+            Block::AIR,
+            Block::SNOW,
+            Block::LEAF,
+            Block::SNOW,
+            Block::AIR,
+        ],
+        [Block::AIR, Block::AIR, Block::SNOW, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+    [
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::SNOW, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+        [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    ],
+];
+
+// decoration on plain grass
+pub const BUSH_OBJECT: [[[Block; 5]; 5]; 1] = [[
+    [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    [Block::AIR, Block::AIR, Block::LEAF, Block::AIR, Block::AIR],
+    [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+    [Block::AIR, Block::AIR, Block::AIR, Block::AIR, Block::AIR],
+]];
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, RwLock},
+    };
+
+    use ferriscraft::Persistent;
+
+    use super::*;
+    use crate::{GameSettings, world::Chunk};
+
+    // one loaded chunk at the origin with stone at `stone`, everything around it unloaded.
+    // the settings file goes to the temp dir so the real saves aren't touched
+    fn game_info(name: &str, stone: &[IVec3]) -> GameInfo {
+        let mut chunk = Chunk::new(IVec3::ZERO);
+        for &pos in stone {
+            chunk.blocks[vec3_to_index(pos)] = Block::STONE;
+        }
+        let settings = Persistent::new(
+            std::env::temp_dir().join(format!("ferriscraft-{name}-settings.toml")),
+            GameSettings::default(),
+            true,
+        );
+        std::fs::remove_file(&settings.path).ok();
+
+        GameInfo {
+            chunks: Arc::new(RwLock::new(HashMap::from([(IVec3::ZERO, Arc::new(chunk))]))),
+            loading_chunks: Default::default(),
+            saved_chunks: Default::default(),
+            unsaved_chunks: Default::default(),
+            materials: Default::default(),
+            voxel_material: Default::default(),
+            models: Default::default(),
+            skybox: Default::default(),
+            noises: Default::default(),
+            seed: Default::default(),
+            current_block: Default::default(),
+            placement_direction: Default::default(),
+            settings,
+            ui_err: Default::default(),
+            connection_addr: Default::default(),
+            paused: Default::default(),
+            chat_open: Default::default(),
+            time_of_day: Default::default(),
+            spawn: Default::default(),
+            creative: Default::default(),
+        }
+    }
+
+    fn assert_hit(hit: Option<RayHit>, pos: IVec3, normal: Direction, distance: f32) {
+        let hit = hit.expect("ray should hit");
+        assert_eq!(hit.global_position, pos);
+        assert_eq!(hit.normal, normal);
+        assert_eq!(hit.block, Block::STONE);
+        assert!(
+            (hit.distance - distance).abs() < 1e-4,
+            "distance {} instead of {distance}",
+            hit.distance
+        );
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let volume = (CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE) as usize;
+        for index in 0..volume {
+            let pos = index_to_vec3(index);
+            assert!(
+                (0..CHUNK_SIZE).contains(&pos.x)
+                    && (0..CHUNK_HEIGHT).contains(&pos.y)
+                    && (0..CHUNK_SIZE).contains(&pos.z),
+                "index {index} gave {pos}"
+            );
+            assert_eq!(vec3_to_index(pos), index);
+        }
+
+        let max = ivec3(CHUNK_SIZE - 1, CHUNK_HEIGHT - 1, CHUNK_SIZE - 1);
+        for x in [0, max.x] {
+            for y in [0, max.y] {
+                for z in [0, max.z] {
+                    let pos = ivec3(x, y, z);
+                    assert_eq!(index_to_vec3(vec3_to_index(pos)), pos);
+                }
+            }
+        }
+        assert_eq!(vec3_to_index(IVec3::ZERO), 0);
+        assert_eq!(vec3_to_index(max), volume - 1);
+    }
+
+    #[test]
+    fn ray_cast_along_an_axis() {
+        let game_info = game_info("ray-axis", &[ivec3(5, 10, 5)]);
+        let hit = ray_cast(&game_info, vec3(5.5, 10.5, 1.5), Vec3::Z, 10.0);
+        assert_hit(hit, ivec3(5, 10, 5), Direction::Back, 3.5);
+
+        let hit = ray_cast(&game_info, vec3(5.5, 14.5, 5.5), Vec3::NEG_Y, 10.0);
+        assert_hit(hit, ivec3(5, 10, 5), Direction::Top, 3.5);
+    }
+
+    #[test]
+    fn ray_cast_diagonal() {
+        let game_info = game_info("ray-diagonal", &[ivec3(5, 10, 5)]);
+        // crosses z = 4 and z = 5 before x = 5, so it enters through the left face
+        let direction = vec3(1.0, 0.0, 0.5);
+        let hit = ray_cast(&game_info, vec3(2.2, 10.5, 3.7), direction, 10.0);
+        let distance = 2.8 * direction.length() / direction.x;
+        assert_hit(hit, ivec3(5, 10, 5), Direction::Left, distance);
+    }
+
+    #[test]
+    fn ray_cast_from_inside_a_block() {
+        // the block the ray starts in is never hit, only the next one along
+        let game_info = game_info("ray-inside", &[ivec3(5, 10, 5), ivec3(7, 10, 5)]);
+        let hit = ray_cast(&game_info, vec3(5.5, 10.5, 5.5), Vec3::X, 10.0);
+        assert_hit(hit, ivec3(7, 10, 5), Direction::Left, 1.5);
+    }
+
+    #[test]
+    fn ray_cast_miss() {
+        let game_info = game_info("ray-miss", &[ivec3(5, 10, 5)]);
+        // away from the block, out into unloaded chunks
+        assert!(ray_cast(&game_info, vec3(5.5, 10.5, 1.5), Vec3::NEG_Z, 10.0).is_none());
+        // towards it, but stopping short
+        assert!(ray_cast(&game_info, vec3(5.5, 10.5, 1.5), Vec3::Z, 3.0).is_none());
+        // straight up out of the world
+        assert!(ray_cast(&game_info, vec3(5.5, 250.5, 5.5), Vec3::Y, 10.0).is_none());
+    }
+}
@@ -0,0 +1,94 @@
+use std::f32::consts::{FRAC_PI_4, PI};
+
+use bevy::{pbr::NotShadowCaster, prelude::*};
+use ferriscraft::{Block, BlockKind, Direction};
+
+use crate::{GameInfo, player::Player, ui::GameState, world::mesher::block_mesh};
+
+// camera space, bottom right of the view
+const HELD_OFFSET: Vec3 = vec3(0.45, -0.4, -0.75);
+const HELD_SIZE: f32 = 0.3;
+const SWING_TIME: f32 = 0.25; // seconds
+const BOB_AMOUNT: f32 = 0.02;
+
+pub struct ViewmodelPlugin;
+
+impl Plugin for ViewmodelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            update_held_block.run_if(not(in_state(GameState::Menu))),
+        );
+    }
+}
+
+// block drawn in hand, a child of the camera
+#[derive(Component)]
+pub struct HeldBlock {
+    kind: BlockKind,
+    swing: f32, // seconds left
+    bob: f32,   // walk cycle phase
+}
+
+impl HeldBlock {
+    pub fn swing(&mut self) {
+        self.swing = SWING_TIME;
+    }
+}
+
+fn update_held_block(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    held: Option<Single<(&mut HeldBlock, &mut Mesh3d, &mut Transform)>>,
+    camera: Single<Entity, With<Camera3d>>,
+    player: Single<&Player>,
+    game_info: Res<GameInfo>,
+    game_state: Res<State<GameState>>,
+    time: Res<Time>,
+) {
+    let block = Block {
+        kind: game_info.current_block,
+        direction: Direction::Top,
+    };
+    let Some(held) = held else {
+        commands.spawn((
+            HeldBlock {
+                kind: block.kind,
+                swing: 0.0,
+                bob: 0.0,
+            },
+            Mesh3d(meshes.add(block_mesh(block))),
+            MeshMaterial3d(game_info.materials[0].clone()),
+            Transform::from_translation(HELD_OFFSET),
+            NotShadowCaster,
+            ChildOf(*camera),
+            StateScoped(*game_state.get()),
+        ));
+        return;
+    };
+    let (mut held, mut mesh, mut transform) = held.into_inner();
+
+    // scrolling or the number keys changed the selection
+    if held.kind != block.kind {
+        held.kind = block.kind;
+        mesh.0 = meshes.add(block_mesh(block));
+    }
+
+    let delta = time.delta_secs();
+    let speed = player.velocity.xz().length();
+    if speed > 0.1 && !game_info.paused {
+        held.bob = (held.bob + speed * delta * 2.0) % (2.0 * PI);
+    } else {
+        // settle back to rest instead of freezing mid step
+        held.bob = held.bob.lerp(0.0, (delta * 8.0).min(1.0));
+    }
+    held.swing = (held.swing - delta).max(0.0);
+
+    // a quick dip towards the center of the screen and back
+    let swing = (held.swing / SWING_TIME * PI).sin();
+    let bob = vec3(held.bob.cos() * 0.5, -held.bob.sin().abs(), 0.0) * BOB_AMOUNT;
+    transform.translation = HELD_OFFSET + bob + vec3(-0.15, -0.1, -0.1) * swing;
+    transform.rotation =
+        Quat::from_rotation_y(FRAC_PI_4) * Quat::from_rotation_x(-swing * FRAC_PI_4);
+    transform.scale = Vec3::splat(HELD_SIZE);
+}
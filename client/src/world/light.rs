@@ -0,0 +1,218 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+
+use bevy::prelude::*;
+use ferriscraft::{
+    BlockKind,
+    terrain::{NoiseFunctions, terrain_noise},
+};
+
+use crate::{
+    CHUNK_HEIGHT, CHUNK_SIZE,
+    utils::vec3_to_index,
+    world::{Chunk, utils::chunk_coords},
+};
+
+// open sky and the brightest glowing blocks, every block travelled takes one off
+pub const MAX_LIGHT: u8 = 15;
+// blocks further than this can't change a block's light
+pub const LIGHT_REACH: i32 = MAX_LIGHT as i32;
+// brightness kept for every level below MAX_LIGHT
+const LIGHT_FALLOFF: f32 = 0.8;
+
+// the chunk and everything around it that can light it
+const REGION_SIZE: i32 = CHUNK_SIZE + 2 * LIGHT_REACH;
+// LIGHT_REACH rounded up to whole chunks
+const CHUNK_REACH: i32 = (LIGHT_REACH + CHUNK_SIZE - 1) / CHUNK_SIZE;
+
+// the chunk at `pos` and every chunk its light can reach, or that can light it
+pub fn chunks_in_reach(pos: IVec3) -> impl Iterator<Item = IVec3> {
+    (-CHUNK_REACH..=CHUNK_REACH)
+        .flat_map(move |z| (-CHUNK_REACH..=CHUNK_REACH).map(move |x| pos + ivec3(x, 0, z)))
+}
+
+// whether light changing at `local` in a chunk can change the chunk at offset `side` from it,
+// x and z of the offset in -1..=1
+pub fn reaches_neighbour(local: IVec3, side: IVec3) -> bool {
+    let distance = |side: i32, local: i32| match side {
+        -1 => local + 1,
+        1 => CHUNK_SIZE - local,
+        _ => 0,
+    };
+    distance(side.x, local.x) + distance(side.z, local.z) <= LIGHT_REACH
+}
+
+// columns of a chunk that don't light their surroundings the way the fallback in
+// ChunkLight::compute does, solid up to the terrain height with nothing glowing.
+// neighbours meshed while the chunk was missing only need redoing if these reach them
+pub fn unexpected_light(chunk: &Chunk, noises: &NoiseFunctions) -> Vec<IVec3> {
+    let mut columns = Vec::new();
+    for z in 0..CHUNK_SIZE {
+        for x in 0..CHUNK_SIZE {
+            let local = ivec3(x, 0, z);
+            let world = chunk.pos * CHUNK_SIZE + local;
+            let (height, _) = terrain_noise(world.xz().as_vec2(), noises);
+
+            let mut floor = CHUNK_HEIGHT;
+            let mut glowing = false;
+            for y in (0..CHUNK_HEIGHT).rev() {
+                let kind = chunk.blocks[vec3_to_index(local.with_y(y))].kind;
+                glowing |= kind.light_emission() > 0;
+                if floor == y + 1 && kind.is_transparent() {
+                    floor = y;
+                }
+            }
+            if glowing || floor != height.clamp(0, CHUNK_HEIGHT) {
+                columns.push(local);
+            }
+        }
+    }
+    columns
+}
+
+// sky and block light of a chunk, worked out from it and its neighbours every time it's meshed.
+// covers LIGHT_REACH blocks past the chunk's edges as well
+pub struct ChunkLight {
+    sky: Vec<u8>,
+    block: Vec<u8>,
+}
+
+impl ChunkLight {
+    pub fn compute(
+        chunk: &Chunk,
+        chunks: &HashMap<IVec3, Arc<Chunk>>,
+        noises: &NoiseFunctions,
+    ) -> Self {
+        // world pos of the region's corner
+        let origin = chunk.pos * CHUNK_SIZE - ivec3(LIGHT_REACH, 0, LIGHT_REACH);
+        let len = (REGION_SIZE * CHUNK_HEIGHT * REGION_SIZE) as usize;
+
+        // light passes through these, glowing blocks give off their own
+        let mut transparent = vec![false; len];
+        let mut sky = vec![0; len];
+        let mut block = vec![0; len];
+        let mut sky_queue = VecDeque::new();
+        let mut block_queue = VecDeque::new();
+        // lowest block of each column the sky reaches
+        let mut sky_floor = vec![0; (REGION_SIZE * REGION_SIZE) as usize];
+
+        for z in 0..REGION_SIZE {
+            for x in 0..REGION_SIZE {
+                let world = origin + ivec3(x, 0, z);
+                let (chunk_pos, local) = chunk_coords(world);
+                let neighbour = if chunk_pos == chunk.pos {
+                    Some(chunk)
+                } else {
+                    chunks.get(&chunk_pos).map(Arc::as_ref)
+                };
+                // unloaded neighbours are solid up to the terrain height, without caves
+                let height = neighbour
+                    .is_none()
+                    .then(|| terrain_noise(world.xz().as_vec2(), noises).0);
+
+                let mut lit = true;
+                let mut floor = CHUNK_HEIGHT;
+                for y in (0..CHUNK_HEIGHT).rev() {
+                    let i = region_index(ivec3(x, y, z));
+                    let kind = match neighbour {
+                        Some(neighbour) => neighbour.blocks[vec3_to_index(local.with_y(y))].kind,
+                        None if height.is_some_and(|height| y < height) => BlockKind::Stone,
+                        None => BlockKind::Air,
+                    };
+                    transparent[i] = kind.is_transparent();
+                    block[i] = kind.light_emission();
+                    if block[i] > 0 {
+                        block_queue.push_back(ivec3(x, y, z));
+                    }
+                    lit &= transparent[i];
+                    if lit {
+                        sky[i] = MAX_LIGHT;
+                        floor = y;
+                    }
+                }
+                sky_floor[(z * REGION_SIZE + x) as usize] = floor;
+            }
+        }
+
+        // only the sky next to a taller column has anywhere to spread, the rest is lit already
+        for z in 0..REGION_SIZE {
+            for x in 0..REGION_SIZE {
+                let floor = sky_floor[(z * REGION_SIZE + x) as usize];
+                let highest_neighbour = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                    .into_iter()
+                    .map(|(dx, dz)| ivec2(x + dx, z + dz))
+                    .filter(|pos| {
+                        (0..REGION_SIZE).contains(&pos.x) && (0..REGION_SIZE).contains(&pos.y)
+                    })
+                    .map(|pos| sky_floor[(pos.y * REGION_SIZE + pos.x) as usize])
+                    .max()
+                    .unwrap_or(floor);
+                for y in floor..highest_neighbour {
+                    sky_queue.push_back(ivec3(x, y, z));
+                }
+            }
+        }
+
+        spread(&mut sky, sky_queue, &transparent);
+        spread(&mut block, block_queue, &transparent);
+
+        ChunkLight { sky, block }
+    }
+
+    // 0 - MAX_LIGHT, whichever of sky and block light is brighter.
+    // takes a position local to the chunk, up to LIGHT_REACH blocks outside it
+    pub fn level(&self, pos: IVec3) -> u8 {
+        let pos = pos + ivec3(LIGHT_REACH, 0, LIGHT_REACH);
+        if !(0..CHUNK_HEIGHT).contains(&pos.y)
+            || !(0..REGION_SIZE).contains(&pos.x)
+            || !(0..REGION_SIZE).contains(&pos.z)
+        {
+            return MAX_LIGHT;
+        }
+        let i = region_index(pos);
+        self.sky[i].max(self.block[i])
+    }
+}
+
+// what a light level multiplies the block's color by
+pub fn brightness(level: u8) -> f32 {
+    LIGHT_FALLOFF.powi((MAX_LIGHT - level.min(MAX_LIGHT)) as i32)
+}
+
+// flood fill out from the queued blocks, one level darker every step
+fn spread(light: &mut [u8], mut queue: VecDeque<IVec3>, transparent: &[bool]) {
+    while let Some(pos) = queue.pop_front() {
+        let level = light[region_index(pos)];
+        if level <= 1 {
+            continue;
+        }
+        for offset in [
+            IVec3::X,
+            IVec3::NEG_X,
+            IVec3::Y,
+            IVec3::NEG_Y,
+            IVec3::Z,
+            IVec3::NEG_Z,
+        ] {
+            let next = pos + offset;
+            if !(0..REGION_SIZE).contains(&next.x)
+                || !(0..CHUNK_HEIGHT).contains(&next.y)
+                || !(0..REGION_SIZE).contains(&next.z)
+            {
+                continue;
+            }
+            let i = region_index(next);
+            if transparent[i] && light[i] < level - 1 {
+                light[i] = level - 1;
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+#[inline(always)]
+fn region_index(pos: IVec3) -> usize {
+    (pos.x + pos.y * REGION_SIZE + pos.z * REGION_SIZE * CHUNK_HEIGHT) as usize
+}
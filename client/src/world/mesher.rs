@@ -1,15 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
-use bevy::prelude::*;
-use ferriscraft::Direction;
+use bevy::{
+    asset::RenderAssetUsages,
+    prelude::*,
+    render::mesh::{Indices, PrimitiveTopology},
+};
+use ferriscraft::{
+    Block, Direction,
+    terrain::{NoiseFunctions, terrain_noise},
+};
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use crate::{
     CHUNK_HEIGHT, CHUNK_SIZE,
     utils::{index_to_vec3, vec3_to_index},
     world::{
-        Block, Chunk,
-        utils::{NoiseFunctions, Quad, generate_block_at, terrain_noise},
+        Chunk, SECTIONS,
+        light::{ChunkLight, MAX_LIGHT, brightness},
+        utils::{Quad, generate_block_at},
     },
 };
 
@@ -17,6 +25,7 @@ use crate::{
 pub struct ChunkMesh {
     pub vertices: Vec<Vertex>,
     pub indices: Vec<u32>,
+    pub greedy: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -24,76 +33,116 @@ pub struct Vertex {
     pub pos: Vec3,
     pub normal: Direction,
     pub uv: Vec2,
+    pub tile: Vec2, // atlas tile origin, only used by greedy meshes
+    pub light: f32, // 0.0 - 1.0, multiplies the block's color
 }
 
 impl ChunkMesh {
     pub fn build(
         mut self,
         chunk: &Chunk,
-        chunks: &HashMap<IVec3, Chunk>,
+        chunks: &HashMap<IVec3, Arc<Chunk>>,
         noises: &NoiseFunctions,
+        greedy: bool,
     ) -> Option<Self> {
         let chunk_pos = chunk.pos;
 
-        let left_chunk = chunks.get(&(chunk_pos + IVec3::new(-1, 0, 0)));
-        let back_chunk = chunks.get(&(chunk_pos + IVec3::new(0, 0, -1)));
-
-        // parallelized (thanks rayon)
-        let mesh_parts: Vec<ChunkMesh> = (0..CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE)
-            .into_par_iter()
-            .filter_map(|i| {
-                let mut local_mesh = ChunkMesh::default();
+        let left_chunk = chunks
+            .get(&(chunk_pos + IVec3::new(-1, 0, 0)))
+            .map(Arc::as_ref);
+        let back_chunk = chunks
+            .get(&(chunk_pos + IVec3::new(0, 0, -1)))
+            .map(Arc::as_ref);
+
+        let light = ChunkLight::compute(chunk, chunks, noises);
+
+        let block_faces = |i: i32| {
+            let pos = index_to_vec3(i as usize);
+            // inside a uniform section every neighbour is the same block, only the chunk edges
+            // and the bottom layer of the section can have faces
+            if chunk.uniform[(pos.y / CHUNK_SIZE) as usize].is_some()
+                && pos.x != 0
+                && pos.z != 0
+                && pos.y % CHUNK_SIZE != 0
+            {
+                return [None; 3];
+            }
 
-                let pos = index_to_vec3(i as usize);
-                let local = pos.as_vec3();
+            let current = *unsafe { chunk.blocks.get_unchecked(i as usize) };
+
+            let (back, left, down) = chunk.get_adjacent_blocks(pos, left_chunk, back_chunk, noises);
+
+            // faces are as bright as the air in front of them
+            let face = |neighbor: Block, offset: IVec3, dir: Direction| {
+                visible_face(current, neighbor, dir).map(|(dir, block)| {
+                    let air = if current.kind.is_air() {
+                        pos
+                    } else {
+                        pos + offset
+                    };
+                    (dir, block, light.level(air))
+                })
+            };
+            [
+                face(left, IVec3::NEG_X, Direction::Left),
+                face(back, IVec3::NEG_Z, Direction::Back),
+                face(down, IVec3::NEG_Y, Direction::Bottom),
+            ]
+        };
 
-                let current = *unsafe { chunk.blocks.get_unchecked(i as usize) };
+        // parallelized (thanks rayon)
+        let mesh_parts: Vec<ChunkMesh> = if greedy {
+            let faces: Vec<[Option<(Direction, Block, u8)>; 3]> =
+                (0..CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE)
+                    .into_par_iter()
+                    .map(block_faces)
+                    .collect();
+
+            (0..CHUNK_SIZE)
+                .into_par_iter()
+                .map(|x| greedy_layer(&faces, 0, x))
+                .chain(
+                    (0..CHUNK_SIZE)
+                        .into_par_iter()
+                        .map(|z| greedy_layer(&faces, 1, z)),
+                )
+                .chain(
+                    (0..CHUNK_HEIGHT)
+                        .into_par_iter()
+                        .map(|y| greedy_layer(&faces, 2, y)),
+                )
+                .filter(|part| !part.vertices.is_empty())
+                .collect()
+        } else {
+            (0..CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE)
+                .into_par_iter()
+                .filter_map(|i| {
+                    let mut local_mesh = ChunkMesh::default();
 
-                let (back, left, down) =
-                    chunk.get_adjacent_blocks(pos, left_chunk, back_chunk, noises);
+                    let local = index_to_vec3(i as usize).as_vec3();
 
-                if !current.kind.is_air() {
-                    if left.kind.is_air() {
-                        local_mesh.push_face(Direction::Left, local, current);
-                    }
-                    if back.kind.is_air() {
-                        local_mesh.push_face(Direction::Back, local, current);
-                    }
-                    if down.kind.is_air() {
-                        local_mesh.push_face(Direction::Bottom, local, current);
+                    for (dir, block, light) in block_faces(i).into_iter().flatten() {
+                        local_mesh.push_face(dir, local, block, light);
                     }
-                } else {
-                    if !left.kind.is_air() {
-                        local_mesh.push_face(Direction::Right, local, left);
-                    }
-                    if !back.kind.is_air() {
-                        local_mesh.push_face(Direction::Front, local, back);
-                    }
-                    if !down.kind.is_air() {
-                        local_mesh.push_face(Direction::Top, local, down);
-                    }
-                }
 
-                if local_mesh.vertices.is_empty() {
-                    None
-                } else {
-                    Some(local_mesh)
-                }
-            })
-            .collect();
+                    if local_mesh.vertices.is_empty() {
+                        None
+                    } else {
+                        Some(local_mesh)
+                    }
+                })
+                .collect()
+        };
 
+        // parts only carry quads, indices are generated once below
         for part in mesh_parts {
-            for v in part.vertices {
-                self.vertices.push(v);
-            }
-            for i in part.indices {
-                self.indices.push(i + self.vertices.len() as u32);
-            }
+            self.vertices.extend(part.vertices);
         }
 
         if self.vertices.is_empty() {
             None
         } else {
+            self.greedy = greedy;
             self.vertices.shrink_to_fit();
             self.indices
                 .extend((0..self.vertices.len() / 4).flat_map(|i| {
@@ -105,7 +154,7 @@ impl ChunkMesh {
     }
 
     #[inline(always)]
-    pub fn push_face(&mut self, dir: Direction, pos: Vec3, block: Block) {
+    pub fn push_face(&mut self, dir: Direction, pos: Vec3, block: Block, light: u8) {
         let uvs = dir.get_uvs(block);
         for (i, corner) in Quad::from_direction(dir, pos, Vec3::ONE)
             .corners
@@ -116,11 +165,166 @@ impl ChunkMesh {
                 pos: Vec3::from_array(corner),
                 normal: dir,
                 uv: uvs[i],
+                tile: Vec2::ZERO,
+                light: brightness(light),
+            });
+        }
+    }
+
+    // uvs are in tile space (0..size) and get wrapped back into the atlas tile by the voxel shader
+    #[inline(always)]
+    pub fn push_greedy_face(
+        &mut self,
+        dir: Direction,
+        pos: Vec3,
+        size: Vec3,
+        block: Block,
+        light: u8,
+    ) {
+        let uvs = dir.get_uvs(block);
+        let tile_min = uvs.iter().fold(Vec2::INFINITY, |acc, &uv| acc.min(uv));
+        let tile_max = uvs.iter().fold(Vec2::NEG_INFINITY, |acc, &uv| acc.max(uv));
+        let local = uvs.map(|uv| (uv - tile_min) / (tile_max - tile_min));
+
+        let corners = Quad::from_direction(dir, pos, size)
+            .corners
+            .map(Vec3::from_array);
+        let edge_a = corners[0].distance(corners[1]);
+        let edge_b = corners[1].distance(corners[2]);
+
+        // uvs can be rotated, so check which tile axis runs along the first edge
+        let scale = if local[0].x != local[1].x {
+            vec2(edge_a, edge_b)
+        } else {
+            vec2(edge_b, edge_a)
+        };
+
+        for (i, corner) in corners.into_iter().enumerate() {
+            self.vertices.push(Vertex {
+                pos: corner,
+                normal: dir,
+                uv: local[i] * scale,
+                tile: tile_min,
+                light: brightness(light),
             });
         }
     }
 }
 
+// unit cube around the origin textured with the block's tiles, for things held or thrown around
+pub fn block_mesh(block: Block) -> Mesh {
+    let mut cube = ChunkMesh::default();
+    let min = Vec3::splat(-0.5);
+    for dir in [Direction::Left, Direction::Bottom, Direction::Back] {
+        cube.push_face(dir, min, block, MAX_LIGHT);
+    }
+    for dir in [Direction::Right, Direction::Top, Direction::Front] {
+        cube.push_face(dir, min + dir.as_vec3(), block, MAX_LIGHT);
+    }
+
+    let (positions, normals, uvs): (Vec<_>, Vec<_>, Vec<_>) = cube
+        .vertices
+        .iter()
+        .map(|v| (v.pos, v.normal.as_vec3(), v.uv))
+        .collect();
+    let indices = (0..cube.vertices.len() as u32 / 4)
+        .flat_map(|i| {
+            let idx = i * 4;
+            [idx, idx + 1, idx + 2, idx, idx + 2, idx + 3]
+        })
+        .collect();
+
+    Mesh::new(
+        PrimitiveTopology::TriangleList,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+    .with_inserted_indices(Indices::U32(indices))
+}
+
+#[inline(always)]
+fn visible_face(current: Block, neighbor: Block, dir: Direction) -> Option<(Direction, Block)> {
+    match (current.kind.is_air(), neighbor.kind.is_air()) {
+        (false, true) => Some((dir, current)),
+        (true, false) => Some((dir.get_opposite(), neighbor)),
+        _ => None,
+    }
+}
+
+// axis: 0 - x (left/right), 1 - z (back/front), 2 - y (bottom/top)
+fn greedy_layer(
+    faces: &[[Option<(Direction, Block, u8)>; 3]],
+    axis: usize,
+    layer: i32,
+) -> ChunkMesh {
+    let (u_len, v_len) = if axis == 2 {
+        (CHUNK_SIZE, CHUNK_SIZE)
+    } else {
+        (CHUNK_SIZE, CHUNK_HEIGHT)
+    };
+    let to_local = |u: i32, v: i32| match axis {
+        0 => ivec3(layer, v, u),
+        1 => ivec3(u, v, layer),
+        _ => ivec3(u, layer, v),
+    };
+    let to_size = |w: i32, h: i32| match axis {
+        0 => vec3(0.0, h as f32, w as f32),
+        1 => vec3(w as f32, h as f32, 0.0),
+        _ => vec3(w as f32, 0.0, h as f32),
+    };
+    let idx = |u: i32, v: i32| (u + v * u_len) as usize;
+
+    let mut mask = Vec::with_capacity((u_len * v_len) as usize);
+    for v in 0..v_len {
+        for u in 0..u_len {
+            mask.push(faces[vec3_to_index(to_local(u, v))][axis]);
+        }
+    }
+
+    let mut mesh = ChunkMesh::default();
+
+    for v in 0..v_len {
+        let mut u = 0;
+        while u < u_len {
+            let Some(face) = mask[idx(u, v)] else {
+                u += 1;
+                continue;
+            };
+
+            let mut w = 1;
+            while u + w < u_len && mask[idx(u + w, v)] == Some(face) {
+                w += 1;
+            }
+
+            let mut h = 1;
+            'grow: while v + h < v_len {
+                for du in 0..w {
+                    if mask[idx(u + du, v + h)] != Some(face) {
+                        break 'grow;
+                    }
+                }
+                h += 1;
+            }
+
+            for dv in 0..h {
+                for du in 0..w {
+                    mask[idx(u + du, v + dv)] = None;
+                }
+            }
+
+            // differently lit faces don't merge, so one light covers the whole quad
+            let (dir, block, light) = face;
+            mesh.push_greedy_face(dir, to_local(u, v).as_vec3(), to_size(w, h), block, light);
+
+            u += w;
+        }
+    }
+
+    mesh
+}
+
 impl Chunk {
     #[inline]
     pub fn new(pos: IVec3) -> Self {
@@ -128,7 +332,27 @@ impl Chunk {
             pos,
             entities: Vec::new(),
             blocks: vec![Block::DEFAULT; (CHUNK_SIZE * CHUNK_HEIGHT * CHUNK_SIZE) as usize],
+            uniform: [Some(Block::DEFAULT); SECTIONS],
+        }
+    }
+
+    // the block filling each section, None where they're mixed
+    pub fn uniform_sections(&self) -> [Option<Block>; SECTIONS] {
+        let mut sections = [None; SECTIONS];
+        let mut mixed = 0u32;
+        for (row, blocks) in self.blocks.chunks(CHUNK_SIZE as usize).enumerate() {
+            let section = (row as i32 % CHUNK_HEIGHT / CHUNK_SIZE) as usize;
+            let first = *sections[section].get_or_insert(blocks[0]);
+            if blocks.iter().any(|&block| block != first) {
+                mixed |= 1 << section;
+            }
         }
+        for (section, block) in sections.iter_mut().enumerate() {
+            if mixed & (1 << section) != 0 {
+                *block = None;
+            }
+        }
+        sections
     }
 
     // pub fn get_block(&self, pos: IVec3) -> &Block {
@@ -140,29 +364,6 @@ impl Chunk {
     //     }
     // }
 
-    #[inline]
-    pub fn get_relative_chunk(&self, pos: IVec3) -> Option<IVec3> {
-        if !(0..CHUNK_HEIGHT).contains(&pos.y) {
-            return None;
-        }
-
-        let mut chunk_pos = self.pos;
-
-        if pos.x < 0 {
-            chunk_pos.x -= 1;
-        } else if pos.x >= CHUNK_SIZE {
-            chunk_pos.x += 1;
-        }
-
-        if pos.z < 0 {
-            chunk_pos.z -= 1;
-        } else if pos.z >= CHUNK_SIZE {
-            chunk_pos.z += 1;
-        }
-
-        Some(chunk_pos)
-    }
-
     #[inline(always)]
     pub fn get_adjacent_blocks(
         &self,
@@ -225,7 +426,11 @@ impl Chunk {
             }
 
             let world_pos = IVec3::new(chunk_x * CHUNK_SIZE + lx, ny, chunk_z * CHUNK_SIZE + lz);
-            generate_block_at(world_pos, terrain_noise(world_pos.xz().as_vec2(), noises).0)
+            generate_block_at(
+                world_pos,
+                terrain_noise(world_pos.xz().as_vec2(), noises).0,
+                noises,
+            )
         };
 
         let back = get_block(0, 0, -1, back_chunk);
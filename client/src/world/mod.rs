@@ -1,39 +1,57 @@
+use std::collections::HashSet;
+
 use bevy::{pbr::wireframe::WireframePlugin, prelude::*, tasks::Task};
-use ferriscraft::{Block, GameEntity};
+use ferriscraft::{Block, GameEntity, SavedWorld};
 
 use crate::{
-    GameInfo,
+    CHUNK_HEIGHT, CHUNK_SIZE, GameInfo, PausableSystems,
     ui::GameState,
     world::{
         mesher::ChunkMesh,
         systems::{
-            autosave_and_exit, handle_chunk_despawn, handle_chunk_gen, handle_mesh_gen,
-            process_tasks,
+            apply_block_edits, apply_entity_gravity, autosave_and_exit, finish_background_save,
+            handle_chunk_despawn, handle_chunk_gen, handle_mesh_gen, process_tasks,
+            rebuild_dirty_chunks, sync_chunk_entities, wander_entities,
         },
     },
 };
 
+pub mod light;
 pub mod mesher;
 pub mod systems;
 pub mod utils;
 
+// CHUNK_SIZE tall slices of a chunk column
+pub const SECTIONS: usize = (CHUNK_HEIGHT / CHUNK_SIZE) as usize;
+
 pub struct WorldPlugin;
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(WireframePlugin::default())
-            .add_systems(Update, autosave_and_exit)
+            .init_resource::<DirtyChunks>()
+            .init_resource::<SaveTask>()
+            .add_event::<EditBlock>()
+            .add_systems(Update, (finish_background_save, autosave_and_exit).chain())
             .add_systems(
                 Update,
                 (
                     handle_chunk_gen,
+                    apply_block_edits.before(rebuild_dirty_chunks),
+                    rebuild_dirty_chunks,
                     handle_mesh_gen,
-                    handle_chunk_despawn
-                        .run_if(|game_info: Res<GameInfo>| game_info.settings.despawn_chunks),
+                    handle_chunk_despawn,
                     process_tasks,
                 )
                     .run_if(not(in_state(GameState::Menu))),
             )
+            .add_systems(
+                FixedUpdate,
+                (wander_entities, apply_entity_gravity, sync_chunk_entities)
+                    .chain()
+                    .run_if(not(in_state(GameState::Menu)))
+                    .in_set(PausableSystems),
+            )
             .add_systems(
                 Update,
                 |mut commands: Commands,
@@ -61,15 +79,45 @@ impl Plugin for WorldPlugin {
 #[derive(Component)]
 pub struct ChunkMarker;
 
+// edited chunks, remeshed once per frame no matter how many blocks changed
+#[derive(Resource, Default)]
+pub struct DirtyChunks(pub HashSet<IVec3>);
+
+// changes a block from any system, the same way a player edit does: saved, remeshed
+// and sent to the server
+#[derive(Event)]
+pub struct EditBlock(pub IVec3, pub Block); // world pos, block
+
+#[derive(Component, Default)]
+pub struct EntityVelocity(pub Vec3);
+
+#[derive(Component, Default)]
+pub struct Wander {
+    pub timer: f32,
+    pub walking: bool,
+}
+
+// chunk the entity was loaded from
+#[derive(Component)]
+pub struct ChunkEntity(pub IVec3);
+
+// TODO: split columns into CHUNK_SIZE tall chunks along pos.y, it's always 0 for now
 #[derive(Clone)]
 pub struct Chunk {
     pub pos: IVec3,
     pub entities: Vec<(Entity, GameEntity)>,
     pub blocks: Vec<Block>,
+    pub uniform: [Option<Block>; SECTIONS], // every block in the section is this one, cleared by edits
 }
 
 #[derive(Component)]
-pub struct ComputeChunk(pub Task<Chunk>, pub IVec3);
+// the chunk with its unexpected_light columns
+pub struct ComputeChunk(pub Task<(Chunk, Vec<IVec3>)>, pub IVec3);
 
 #[derive(Component)]
 pub struct ComputeChunkMesh(pub Task<Option<ChunkMesh>>, pub IVec3);
+
+// a save being written off the main thread, the saved world is moved into the task and
+// comes back once it's on disk
+#[derive(Resource, Default)]
+pub struct SaveTask(pub Option<Task<(SavedWorld, Result<(), String>)>>);
@@ -8,35 +8,64 @@ use bevy::{
     tasks::{AsyncComputeTaskPool, futures_lite::future},
     window::PrimaryWindow,
 };
+use std::{collections::HashMap, f32::consts::TAU, sync::Arc};
+
 use bevy_renet::renet::RenetClient;
-use ferriscraft::{ClientPacket, GameEntity, GameEntityKind, Persistent, SEA_LEVEL, SavedWorld};
-use rayon::slice::ParallelSliceMut;
+use ferriscraft::{ClientPacket, GameEntity, GameEntityKind, Persistent, SavedWorld};
 
 use crate::{
-    CHUNK_HEIGHT, CHUNK_SIZE, GameInfo,
-    player::Player,
-    utils::{TREE_OBJECT, noise, vec3_to_index},
+    CHUNK_HEIGHT, CHUNK_SIZE, GameInfo, MAX_AUTOSAVE_INTERVAL,
+    player::{Health, Inventory, Player},
+    render_pipeline::VoxelMaterial,
+    ui::toast,
+    utils::{apply_gravity, ground_distance, ray_cast, vec3_to_index},
     world::{
-        Chunk, ChunkMarker, ComputeChunk, ComputeChunkMesh,
+        ChunkEntity, ChunkMarker, ComputeChunk, ComputeChunkMesh, DirtyChunks, EditBlock,
+        EntityVelocity, SaveTask, Wander,
+        light::{chunks_in_reach, reaches_neighbour, unexpected_light},
         mesher::ChunkMesh,
-        utils::{generate_block_at, terrain_noise},
+        utils::{chunk_coords, chunk_priority, generate_chunk, in_render_distance, store_entities},
     },
 };
 
+const WANDER_SPEED: f32 = 1.5;
+// frame time process_tasks tries to stay under
+const TARGET_FRAME_TIME: f32 = 1.0 / 60.0;
+// how far the camera turns before handle_chunk_gen sorts its queue again, cos of ~15 degrees
+const RESORT_TURN_COS: f32 = 0.966;
+
+// chunks in render distance, nearest and most in view first. only sorted again when the
+// player moves to another chunk, the render distance changes or the camera turns enough
+#[derive(Default)]
+pub struct LoadOrder {
+    positions: Vec<IVec3>,
+    player_chunk: IVec3,
+    forward: Vec2,
+    render_distance: i32,
+}
+
 pub fn autosave_and_exit(
     mut app_exit: EventWriter<AppExit>,
     mut last_save: Local<f32>,
+    mut save_task: ResMut<SaveTask>,
     persistent_world: Option<ResMut<Persistent<SavedWorld>>>,
     client: Option<ResMut<RenetClient>>,
     window: Query<&Window, With<PrimaryWindow>>,
-    player: Query<(&Transform, &Player)>,
+    player: Query<(&Transform, &Player, &Health, &Inventory)>,
     camera: Query<&Transform, With<Camera3d>>,
     game_info: Res<GameInfo>,
     time: Res<Time>,
 ) {
     if window.is_empty() {
         info!("saving and exiting");
-        save_game(persistent_world, player, camera.single().ok(), &game_info);
+        save_game(
+            persistent_world,
+            &mut save_task,
+            player,
+            camera.single().ok(),
+            &game_info,
+        );
+        game_info.settings.write().ok();
         if let Some(mut client) = client {
             client.disconnect();
         }
@@ -46,9 +75,22 @@ pub fn autosave_and_exit(
 
     let elapsed = time.elapsed_secs_wrapped();
 
-    // 10 minute autosave
-    if game_info.settings.autosave && elapsed > *last_save + 600.0 {
-        save_game(persistent_world, player, camera.single().ok(), &game_info);
+    // elapsed wraps around every hour, the interval has to stay below that or it never fires
+    let interval = game_info
+        .settings
+        .autosave_interval
+        .min(MAX_AUTOSAVE_INTERVAL) as f32;
+    if game_info.settings.autosave && elapsed > *last_save + interval {
+        // the server saves multiplayer worlds
+        if let Some(mut persistent_world) = persistent_world {
+            save_game_in_background(
+                &mut persistent_world,
+                &mut save_task,
+                &player,
+                camera.single().ok(),
+                &game_info,
+            );
+        }
         *last_save = elapsed;
     }
 
@@ -57,38 +99,125 @@ pub fn autosave_and_exit(
     }
 }
 
+// blocks until the world is on disk, for leaving it and exiting
 pub fn save_game(
     persistent_world: Option<ResMut<Persistent<SavedWorld>>>,
-    player: Query<(&Transform, &Player)>,
+    save_task: &mut SaveTask,
+    player: Query<(&Transform, &Player, &Health, &Inventory)>,
     camera: Option<&Transform>,
     game_info: &GameInfo,
 ) {
     if let Some(mut persistent_world) = persistent_world {
+        // don't write over the world while it's still out
+        if let Some(task) = save_task.0.take() {
+            let (saved_world, _) = future::block_on(task);
+            persistent_world.data = saved_world;
+        }
         persistent_world
-            .update(|saved_world| {
-                if let Ok(player) = player.single()
-                    && let Some(camera) = camera
-                {
-                    let (_, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
-                    let (yaw, _, _) = player.0.rotation.to_euler(EulerRot::YXZ);
-                    saved_world.players.insert(
-                        game_info.settings.player_name.clone(),
-                        (player.0.translation, player.1.velocity, yaw, pitch),
-                    );
-                }
-                if let Some(saved_chunks) = &game_info.saved_chunks {
-                    saved_world.chunks = saved_chunks.read().unwrap().clone();
-                }
-            })
+            .update(|saved_world| update_saved_world(saved_world, &player, camera, game_info))
             .unwrap();
     }
 }
 
+// false while the last one is still being written, whatever changed since gets picked up
+// by the next save
+pub fn save_game_in_background(
+    persistent_world: &mut Persistent<SavedWorld>,
+    save_task: &mut SaveTask,
+    player: &Query<(&Transform, &Player, &Health, &Inventory)>,
+    camera: Option<&Transform>,
+    game_info: &GameInfo,
+) -> bool {
+    if save_task.0.is_some() {
+        return false;
+    }
+    update_saved_world(&mut persistent_world.data, player, camera, game_info);
+
+    let path = persistent_world.path.clone();
+    let human = persistent_world.human();
+    let saved_world = std::mem::take(&mut persistent_world.data);
+    save_task.0 = Some(AsyncComputeTaskPool::get().spawn(async move {
+        let result = Persistent::write_to(&path, &saved_world, human);
+        (saved_world, result)
+    }));
+    true
+}
+
+pub fn finish_background_save(
+    mut commands: Commands,
+    mut save_task: ResMut<SaveTask>,
+    persistent_world: Option<ResMut<Persistent<SavedWorld>>>,
+) {
+    let Some(task) = &mut save_task.0 else {
+        return;
+    };
+    let Some((saved_world, result)) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+    save_task.0 = None;
+
+    if let Some(mut persistent_world) = persistent_world {
+        persistent_world.data = saved_world;
+    }
+    match result {
+        Ok(()) => {
+            info!("world saved");
+            toast(&mut commands, "World saved");
+        }
+        Err(error) => {
+            error!("failed to save world: {error}");
+            toast(&mut commands, format!("Failed to save world - {error}"));
+        }
+    }
+}
+
+fn update_saved_world(
+    saved_world: &mut SavedWorld,
+    player: &Query<(&Transform, &Player, &Health, &Inventory)>,
+    camera: Option<&Transform>,
+    game_info: &GameInfo,
+) {
+    if let Ok(player) = player.single()
+        && let Some(camera) = camera
+    {
+        let (_, pitch, _) = camera.rotation.to_euler(EulerRot::YXZ);
+        let (yaw, _, _) = player.0.rotation.to_euler(EulerRot::YXZ);
+        saved_world.players.insert(
+            game_info.settings.player_name.clone(),
+            (
+                player.0.translation,
+                player.1.velocity,
+                yaw,
+                pitch,
+                player.2.0,
+                player.3.counts(),
+            ),
+        );
+    }
+    saved_world.spawn = game_info.spawn;
+    if let Some(saved_chunks) = &game_info.saved_chunks {
+        let chunks = game_info.chunks.read().unwrap();
+        let mut saved_chunks = saved_chunks.write().unwrap();
+        let mut unsaved_chunks = game_info.unsaved_chunks.write().unwrap();
+        for chunk in chunks.values() {
+            store_entities(chunk, &mut saved_chunks, &mut unsaved_chunks);
+        }
+        // the rest is already in the saved world from the last save or the load
+        for pos in unsaved_chunks.drain() {
+            if let Some(saved_chunk) = saved_chunks.get(&pos) {
+                saved_world.chunks.insert(pos, saved_chunk.clone());
+            }
+        }
+    }
+}
+
 pub fn handle_chunk_gen(
     mut commands: Commands,
     game_info: Res<GameInfo>,
     player: Single<&Transform, With<Player>>,
+    camera: Single<&GlobalTransform, With<Camera3d>>,
     client: Option<ResMut<RenetClient>>,
+    mut load_order: Local<LoadOrder>,
 ) {
     let pt = player.translation;
     let thread_pool = AsyncComputeTaskPool::get();
@@ -97,126 +226,128 @@ pub fn handle_chunk_gen(
 
     let mut chunks_to_load = Vec::new();
 
-    for chunk_z in
-        (pt.z as i32 / CHUNK_SIZE - render_distance)..(pt.z as i32 / CHUNK_SIZE + render_distance)
+    let (player_chunk, _) = chunk_coords(pt.floor().as_ivec3());
+    let forward = camera.forward().as_vec3().xz().normalize_or_zero();
+    if load_order.positions.is_empty()
+        || load_order.player_chunk != player_chunk
+        || load_order.render_distance != render_distance
+        || load_order.forward.dot(forward) < RESORT_TURN_COS
     {
-        for chunk_x in (pt.x as i32 / CHUNK_SIZE - render_distance)
-            ..(pt.x as i32 / CHUNK_SIZE + render_distance)
-        {
-            let pos = ivec3(chunk_x, 0, chunk_z);
-
-            if let Ok(guard) = game_info.chunks.read() {
-                if guard.contains_key(&pos) {
-                    continue;
+        let mut positions = Vec::new();
+        for z in -render_distance..=render_distance {
+            for x in -render_distance..=render_distance {
+                let pos = player_chunk + ivec3(x, 0, z);
+                if in_render_distance(pos, pt, render_distance) {
+                    positions.push(pos);
                 }
-            } else {
-                continue;
-            };
+            }
+        }
+        // the pool picks tasks up roughly in spawn order
+        positions
+            .sort_by_cached_key(|&pos| chunk_priority(pos, pt, vec3(forward.x, 0.0, forward.y)));
+        *load_order = LoadOrder {
+            positions,
+            player_chunk,
+            forward,
+            render_distance,
+        };
+    }
 
-            if let Ok(guard) = game_info.loading_chunks.read() {
-                if guard.contains(&pos) {
-                    continue;
-                }
-            } else {
+    for &pos in &load_order.positions {
+        if let Ok(guard) = game_info.chunks.read() {
+            if guard.contains_key(&pos) {
                 continue;
-            };
+            }
+        } else {
+            continue;
+        };
 
-            {
-                game_info.loading_chunks.write().unwrap().insert(pos);
+        if let Ok(guard) = game_info.loading_chunks.read() {
+            if guard.contains(&pos) {
+                continue;
             }
+        } else {
+            continue;
+        };
 
-            chunks_to_load.push(pos);
+        {
+            game_info.loading_chunks.write().unwrap().insert(pos);
+        }
 
-            let chunks = game_info.chunks.clone();
-            let saved_chunks = game_info.saved_chunks.clone();
+        chunks_to_load.push(pos);
 
-            let task = thread_pool.spawn(async move {
-                let mut chunk = Chunk::new(pos);
+        let saved_chunks = game_info.saved_chunks.clone();
+        // the server owns the mobs in multiplayer
+        let spawn_entities = client.is_none();
 
-                for rela_z in 0..CHUNK_SIZE {
-                    for rela_x in 0..CHUNK_SIZE {
-                        let pos = vec2(
-                            (rela_x + pos.x * CHUNK_SIZE) as f32,
-                            (rela_z + pos.z * CHUNK_SIZE) as f32,
-                        );
-                        let (max_y, biome) = terrain_noise(pos, &noises);
-
-                        for y in 0..CHUNK_HEIGHT {
-                            chunk.blocks[vec3_to_index(ivec3(rela_x, y, rela_z))] =
-                                generate_block_at(ivec3(pos.x as i32, y, pos.y as i32), max_y);
-
-                            if y == max_y
-                                && max_y > SEA_LEVEL
-                                && biome < 0.4
-                                && noise(noises.ferris, pos) > 0.85
-                            {
-                                chunk.entities.push((
-                                    Entity::PLACEHOLDER,
-                                    GameEntity {
-                                        kind: GameEntityKind::Ferris,
-                                        pos: vec3(pos.x, y as f32, pos.y),
-                                        rot: rand::random_range(0..360) as f32,
-                                    },
-                                ));
-                            }
-                        }
+        let task = thread_pool.spawn(async move {
+            #[cfg(feature = "profile")]
+            let instant = std::time::Instant::now();
+            let mut chunk = generate_chunk(pos, &noises, spawn_entities);
 
-                        let tree_probabilty = noise(noises.tree, pos);
-
-                        // TODO: clean up
-                        if tree_probabilty > 0.85 && max_y < 90 && max_y > SEA_LEVEL + 2 {
-                            for (y, tree_layer) in TREE_OBJECT.iter().enumerate() {
-                                for (z, tree_row) in tree_layer.iter().enumerate() {
-                                    for (x, &block) in tree_row.iter().enumerate() {
-                                        let mut pos = ivec3(3 + x as i32, y as i32, 3 + z as i32);
-                                        let (local_max_y, _) = terrain_noise(
-                                            (chunk.pos * CHUNK_SIZE + pos).as_vec3().xz(),
-                                            &noises,
-                                        );
-
-                                        pos.y += local_max_y;
-
-                                        if (0..CHUNK_SIZE).contains(&pos.x)
-                                            && (0..CHUNK_HEIGHT).contains(&pos.y)
-                                            && (0..CHUNK_SIZE).contains(&pos.z)
-                                        {
-                                            chunk.blocks[vec3_to_index(pos)] = block;
-                                        } else if let Some(relative_chunk) =
-                                            chunk.get_relative_chunk(pos)
-                                            && let Some(target) =
-                                                chunks.write().unwrap().get_mut(&relative_chunk)
-                                        {
-                                            let block_index =
-                                                vec3_to_index(pos - relative_chunk * CHUNK_SIZE);
-                                            if block_index < target.blocks.len() {
-                                                target.blocks[block_index] = block;
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
+            if let Some(saved_chunks) = &saved_chunks
+                && let Some(saved_chunk) = saved_chunks.read().unwrap().get(&pos)
+            {
+                for (pos, block) in saved_chunk.blocks.iter() {
+                    chunk.blocks[vec3_to_index(pos)] = block;
                 }
-
-                if let Some(saved_chunks) = &saved_chunks
-                    && let Some(saved_chunk) = saved_chunks.read().unwrap().get(&pos)
-                {
-                    for (&pos, &block) in &saved_chunk.blocks {
-                        chunk.blocks[vec3_to_index(pos)] = block;
-                    }
-                    // chunk.entities = saved_chunk.entities.clone();
+                if !saved_chunk.entities.is_empty() {
+                    chunk.entities = saved_chunk
+                        .entities
+                        .iter()
+                        .map(|&entity| (Entity::PLACEHOLDER, entity))
+                        .collect();
                 }
-                chunk
-            });
-            commands.spawn(ComputeChunk(task, pos));
-        }
+            }
+            chunk.uniform = chunk.uniform_sections();
+            let unexpected = unexpected_light(&chunk, &noises);
+            #[cfg(feature = "profile")]
+            println!("Generated chunk terrain in {:?}", instant.elapsed());
+            (chunk, unexpected)
+        });
+        commands.spawn(ComputeChunk(task, pos));
     }
     if !chunks_to_load.is_empty() {
         ClientPacket::LoadChunks(chunks_to_load).send(client);
     }
 }
 
+pub fn apply_block_edits(
+    mut edits: EventReader<EditBlock>,
+    game_info: Res<GameInfo>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    mut client: Option<ResMut<RenetClient>>,
+) {
+    for &EditBlock(pos, block) in edits.read() {
+        let client = client.as_mut().map(|client| client.reborrow());
+        if !game_info.set_block(pos, block, client, Some(&mut dirty_chunks)) {
+            warn!("editing a block in a chunk that isn't loaded {pos}");
+        }
+    }
+}
+
+// re-adding the marker is what handle_mesh_gen picks up
+pub fn rebuild_dirty_chunks(
+    mut commands: Commands,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    query: Query<(Entity, &Transform), With<ChunkMarker>>,
+) {
+    if dirty_chunks.0.is_empty() {
+        return;
+    }
+    for (entity, transform) in query {
+        let (pos, _) = chunk_coords(transform.translation.as_ivec3());
+        if dirty_chunks.0.contains(&pos) {
+            commands
+                .entity(entity)
+                .try_remove::<ChunkMarker>()
+                .try_insert(ChunkMarker);
+        }
+    }
+    // chunks that aren't loaded get meshed when they are
+    dirty_chunks.0.clear();
+}
+
 pub fn handle_mesh_gen(
     mut commands: Commands,
     game_info: Res<GameInfo>,
@@ -225,16 +356,24 @@ pub fn handle_mesh_gen(
     let thread_pool = AsyncComputeTaskPool::get();
 
     for (entity, transform) in query {
-        let pos = transform.translation.as_ivec3() / CHUNK_SIZE;
+        let (pos, _) = chunk_coords(transform.translation.as_ivec3());
 
         let chunks = game_info.chunks.clone();
         let noises = game_info.noises;
+        let greedy = game_info.settings.greedy_meshing;
 
         let task = thread_pool.spawn(async move {
-            let guard = chunks.read().unwrap();
+            // taken out so the lock isn't held through the flood fill, that would hold up
+            // process_tasks and block edits. edits meanwhile copy the chunk instead
+            let nearby = {
+                let guard = chunks.read().unwrap();
+                chunks_in_reach(pos)
+                    .filter_map(|pos| Some((pos, Arc::clone(guard.get(&pos)?))))
+                    .collect::<HashMap<_, _>>()
+            };
             #[cfg(feature = "profile")]
             let instant = std::time::Instant::now();
-            let mesh = ChunkMesh::default().build(guard.get(&pos)?, &guard, &noises);
+            let mesh = ChunkMesh::default().build(nearby.get(&pos)?, &nearby, &noises, greedy);
             #[cfg(feature = "profile")]
             println!("Generated chunk in {:?}", instant.elapsed());
             mesh
@@ -258,21 +397,33 @@ pub fn handle_chunk_despawn(
         )>,
     >,
     player: Single<&Transform, With<Player>>,
+    client: Option<ResMut<RenetClient>>,
+    mut last_render_distance: Local<i32>,
+    mut trimming: Local<bool>,
 ) {
     let pt = player.translation;
     let render_distance = game_info.settings.render_distance;
 
+    // lowering the render distance always drops the far chunks, even with despawning turned off.
+    // keeps going until the chunks that were already loading are done
+    if render_distance < *last_render_distance {
+        *trimming = true;
+    }
+    *last_render_distance = render_distance;
+    if !game_info.settings.despawn_chunks && !*trimming {
+        return;
+    }
+
     let mut chunks = game_info.chunks.write().unwrap();
     let mut loading_chunks = game_info.loading_chunks.write().unwrap();
+    // the server stops sending their entities
+    let mut unloaded = Vec::new();
 
     for (entity, transform) in query {
-        let pos = transform.translation.as_ivec3() / CHUNK_SIZE;
+        let (pos, _) = chunk_coords(transform.translation.as_ivec3());
 
-        if (pos.x + render_distance < pt.x as i32 / CHUNK_SIZE)
-            || (pos.x - render_distance > pt.x as i32 / CHUNK_SIZE)
-            || (pos.z + render_distance < pt.z as i32 / CHUNK_SIZE)
-            || (pos.z - render_distance > pt.z as i32 / CHUNK_SIZE)
-        {
+        // same bounds as handle_chunk_gen
+        if !in_render_distance(pos, pt, render_distance) {
             {
                 if let Some(chunk_entities) = chunks.get(&pos) {
                     for (entity, _) in &chunk_entities.entities {
@@ -280,29 +431,63 @@ pub fn handle_chunk_despawn(
                             commands.entity(*entity).try_despawn();
                         }
                     }
+                    if let Some(saved_chunks) = &game_info.saved_chunks {
+                        store_entities(
+                            chunk_entities,
+                            &mut saved_chunks.write().unwrap(),
+                            &mut game_info.unsaved_chunks.write().unwrap(),
+                        );
+                    }
                 }
             }
             commands.entity(entity).try_despawn();
 
-            chunks.remove(&pos);
-            loading_chunks.remove(&pos);
+            let removed = chunks.remove(&pos).is_some();
+            if loading_chunks.remove(&pos) || removed {
+                unloaded.push(pos);
+            }
         }
     }
+
+    if loading_chunks.is_empty() {
+        *trimming = false;
+    }
+    if !unloaded.is_empty() {
+        ClientPacket::UnloadChunks(unloaded).send(client);
+    }
 }
 
 pub fn process_tasks(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     player: Single<&Transform, With<Player>>,
+    camera: Single<&GlobalTransform, With<Camera3d>>,
     mesh_tasks: Query<(Entity, &mut ComputeChunkMesh)>,
     spawn_tasks: Query<(Entity, &mut ComputeChunk)>,
     game_info: Res<GameInfo>,
+    mut dirty_chunks: ResMut<DirtyChunks>,
+    time: Res<Time>,
+    mut budget: Local<usize>,
 ) {
+    // back off quickly when a frame runs long, grow slowly while there's headroom
+    let frame_time = time.delta_secs();
+    if frame_time > TARGET_FRAME_TIME {
+        *budget = *budget * 3 / 4;
+    } else if frame_time < TARGET_FRAME_TIME * 0.8 {
+        *budget += 1;
+    }
+    let (min, max) = (
+        game_info.settings.min_chunk_budget,
+        game_info.settings.max_chunk_budget,
+    );
+    *budget = (*budget).clamp(min, max.max(min));
+
     // GENERATING CHUNKS
-    let pt = player.translation.as_ivec3().with_y(0) / CHUNK_SIZE;
+    let pt = player.translation;
+    let forward = camera.forward().as_vec3();
 
     let mut tasks = spawn_tasks.into_iter().collect::<Vec<_>>();
-    tasks.par_sort_by_cached_key(|(_, x)| x.1.distance_squared(pt));
+    tasks.par_sort_by_cached_key(|(_, x)| chunk_priority(x.1, pt, forward));
 
     let mut chunks = game_info.chunks.write().unwrap();
     // let mut saved_chunks = game_info
@@ -313,10 +498,12 @@ pub fn process_tasks(
 
     let mut processed_this_frame = 0;
     for (entity, mut compute_task) in tasks {
-        if processed_this_frame >= 15 {
+        if processed_this_frame >= *budget {
             break;
         }
-        if let Some(mut chunk) = future::block_on(future::poll_once(&mut compute_task.0)) {
+        if let Some((mut chunk, unexpected)) =
+            future::block_on(future::poll_once(&mut compute_task.0))
+        {
             // if let Some(saved_chunks) = &mut saved_chunks {
             //     saved_chunks
             //         .entry(chunk.pos)
@@ -335,6 +522,9 @@ pub fn process_tasks(
                 *e = commands
                     .spawn((
                         *game_entity,
+                        EntityVelocity::default(),
+                        Wander::default(),
+                        ChunkEntity(chunk.pos),
                         SceneRoot(game_info.models[game_entity.kind as usize].clone()),
                         Transform::from_translation(game_entity.pos + vec3(0.5, 0.0, 0.5))
                             .with_scale(Vec3::splat(2.0))
@@ -346,6 +536,7 @@ pub fn process_tasks(
                 .entity(entity)
                 .try_insert((
                     ChunkMarker,
+                    // replaced by the mesh bounds once it's built
                     Aabb::from_min_max(
                         vec3(0.0, 0.0, 0.0),
                         vec3(CHUNK_SIZE as f32, CHUNK_HEIGHT as f32, CHUNK_SIZE as f32),
@@ -354,8 +545,21 @@ pub fn process_tasks(
                 ))
                 .try_remove::<ComputeChunk>();
 
+            // the loaded chunks around it were lit as if it was plain terrain, only the ones
+            // it lights differently need remeshing
+            for pos in chunks_in_reach(chunk.pos) {
+                let side = pos - chunk.pos;
+                if side != IVec3::ZERO
+                    && chunks.contains_key(&pos)
+                    && unexpected
+                        .iter()
+                        .any(|&column| reaches_neighbour(column, side))
+                {
+                    dirty_chunks.0.insert(pos);
+                }
+            }
             loading_chunks.remove(&chunk.pos);
-            chunks.insert(chunk.pos, chunk);
+            chunks.insert(chunk.pos, Arc::new(chunk));
 
             processed_this_frame += 1;
         }
@@ -364,11 +568,11 @@ pub fn process_tasks(
     // GENERATING MESHES
 
     let mut tasks = mesh_tasks.into_iter().collect::<Vec<_>>();
-    tasks.par_sort_by_cached_key(|(_, x)| x.1.distance_squared(pt));
+    tasks.par_sort_by_cached_key(|(_, x)| chunk_priority(x.1, pt, forward));
 
     let mut processed_this_frame = 0;
     for (entity, mut compute_task) in tasks {
-        if processed_this_frame >= 15 {
+        if processed_this_frame >= *budget {
             break;
         }
 
@@ -376,28 +580,63 @@ pub fn process_tasks(
             commands.entity(entity).try_remove::<ComputeChunkMesh>();
 
             if let Some(mesh_data) = result {
+                // bevy only computes bounds for meshes without an Aabb, so keep it tight here
+                if let Some(aabb) = mesh_aabb(&mesh_data) {
+                    commands.entity(entity).try_insert(aabb);
+                }
+
                 let (positions, normals, uvs): (Vec<_>, Vec<_>, Vec<_>) = mesh_data
                     .vertices
                     .iter()
                     .map(|v| (v.pos, v.normal.as_vec3(), v.uv))
                     .collect();
-
-                commands.entity(entity).try_insert((
-                    Mesh3d(
-                        meshes.add(
-                            Mesh::new(
-                                PrimitiveTopology::TriangleList,
-                                RenderAssetUsages::RENDER_WORLD,
-                            )
-                            .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
-                            .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
-                            .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
-                            .with_inserted_indices(Indices::U32(mesh_data.indices)),
-                        ),
-                    ),
-                    MeshMaterial3d(game_info.materials[0].clone()),
-                    Visibility::Visible,
-                ));
+                // baked light goes in as the vertex color, the material multiplies it in
+                let colors = mesh_data
+                    .vertices
+                    .iter()
+                    .map(|v| [v.light, v.light, v.light, 1.0])
+                    .collect::<Vec<_>>();
+
+                let mut mesh = Mesh::new(
+                    PrimitiveTopology::TriangleList,
+                    RenderAssetUsages::RENDER_WORLD,
+                )
+                .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_NORMAL, normals)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_UV_0, uvs)
+                .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+
+                if mesh_data.greedy {
+                    mesh.insert_attribute(
+                        Mesh::ATTRIBUTE_UV_1,
+                        mesh_data
+                            .vertices
+                            .iter()
+                            .map(|v| v.tile)
+                            .collect::<Vec<_>>(),
+                    );
+                    mesh.insert_indices(Indices::U32(mesh_data.indices));
+
+                    commands
+                        .entity(entity)
+                        .try_insert((
+                            Mesh3d(meshes.add(mesh)),
+                            MeshMaterial3d(game_info.voxel_material.clone()),
+                            Visibility::Visible,
+                        ))
+                        .try_remove::<MeshMaterial3d<StandardMaterial>>();
+                } else {
+                    mesh.insert_indices(Indices::U32(mesh_data.indices));
+
+                    commands
+                        .entity(entity)
+                        .try_insert((
+                            Mesh3d(meshes.add(mesh)),
+                            MeshMaterial3d(game_info.materials[0].clone()),
+                            Visibility::Visible,
+                        ))
+                        .try_remove::<MeshMaterial3d<VoxelMaterial>>();
+                }
             } else {
                 error!("Error building chunk mesh for entity {:?}", entity);
             }
@@ -405,3 +644,122 @@ pub fn process_tasks(
         }
     }
 }
+
+// chunk-local bounds of the emitted vertices
+fn mesh_aabb(mesh: &ChunkMesh) -> Option<Aabb> {
+    let (min, max) = mesh.vertices.iter().fold(
+        (Vec3::INFINITY, Vec3::NEG_INFINITY),
+        |(min, max), vertex| (min.min(vertex.pos), max.max(vertex.pos)),
+    );
+    min.cmple(max).all().then(|| Aabb::from_min_max(min, max))
+}
+
+pub fn apply_entity_gravity(
+    mut entities: Query<(&mut GameEntity, &mut EntityVelocity, &mut Transform)>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+
+    for (mut game_entity, mut velocity, mut transform) in &mut entities {
+        if game_entity.kind != GameEntityKind::Ferris {
+            continue;
+        }
+
+        let center = game_entity.pos + vec3(0.5, 0.0, 0.5);
+        let (chunk_pos, _) = chunk_coords(center.floor().as_ivec3());
+        // don't fall through chunks that aren't there anymore
+        if !game_info.chunks.read().unwrap().contains_key(&chunk_pos) {
+            continue;
+        }
+
+        velocity.0.y = apply_gravity(velocity.0.y, game_info.settings.gravity, delta);
+        let next_y = game_entity.pos.y + velocity.0.y * delta;
+
+        // cast from a block above the feet so entities stuck inside a block get pushed on top of it
+        let ground = ground_distance(
+            &game_info,
+            center.with_y(game_entity.pos.y),
+            1.0,
+            (game_entity.pos.y - next_y).max(0.0),
+        )
+        .map(|distance| game_entity.pos.y - distance);
+
+        if let Some(ground) = ground
+            && next_y <= ground
+        {
+            game_entity.pos.y = ground;
+            velocity.0.y = 0.0;
+        } else {
+            game_entity.pos.y = next_y;
+        }
+
+        transform.translation = game_entity.pos + vec3(0.5, 0.0, 0.5);
+    }
+}
+
+pub fn wander_entities(
+    mut entities: Query<(&mut GameEntity, &mut Wander, &mut Transform)>,
+    game_info: Res<GameInfo>,
+    time: Res<Time>,
+) {
+    let delta = time.delta_secs();
+
+    for (mut game_entity, mut wander, mut transform) in &mut entities {
+        if game_entity.kind != GameEntityKind::Ferris {
+            continue;
+        }
+
+        wander.timer -= delta;
+        if wander.timer <= 0.0 {
+            wander.walking = !wander.walking;
+            if wander.walking {
+                game_entity.rot = rand::random_range(0.0..TAU);
+                wander.timer = rand::random_range(2.0..5.0);
+            } else {
+                wander.timer = rand::random_range(1.0..4.0);
+            }
+        }
+
+        if !wander.walking {
+            continue;
+        }
+
+        let forward = Quat::from_rotation_y(game_entity.rot) * Vec3::Z;
+        let step = forward * WANDER_SPEED * delta;
+        let center = game_entity.pos + vec3(0.5, 0.5, 0.5);
+
+        // walls
+        let blocked = ray_cast(&game_info, center, forward, 0.5 + step.length()).is_some()
+            // cliffs, only allow dropping down a single block
+            || ray_cast(&game_info, center + step, Vec3::NEG_Y, 1.6).is_none();
+
+        if blocked {
+            wander.walking = false;
+            wander.timer = rand::random_range(1.0..4.0);
+            continue;
+        }
+
+        game_entity.pos += step;
+        transform.translation = game_entity.pos + vec3(0.5, 0.0, 0.5);
+        transform.rotation = Quat::from_rotation_y(game_entity.rot);
+    }
+}
+
+pub fn sync_chunk_entities(
+    entities: Query<(Entity, &GameEntity, &ChunkEntity), Changed<GameEntity>>,
+    game_info: Res<GameInfo>,
+) {
+    if entities.is_empty() {
+        return;
+    }
+
+    let mut chunks = game_info.chunks.write().unwrap();
+    for (entity, game_entity, chunk_entity) in entities {
+        if let Some(chunk) = chunks.get_mut(&chunk_entity.0).map(Arc::make_mut)
+            && let Some((_, stored)) = chunk.entities.iter_mut().find(|(e, _)| *e == entity)
+        {
+            *stored = *game_entity;
+        }
+    }
+}
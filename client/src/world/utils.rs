@@ -1,32 +1,57 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use bevy::prelude::*;
 use bevy_renet::renet::RenetClient;
-use ferriscraft::{Block, ClientPacket, Direction, SEA_LEVEL, SavedChunk};
-use noiz::{
-    Noise,
-    prelude::common_noise::{Fbm, Perlin, Simplex},
+use ferriscraft::{
+    Block, ClientPacket, Direction, GameEntity, GameEntityKind, SEA_LEVEL, SavedChunk, hash,
+    terrain::{
+        NoiseFunctions, OCEAN_PLAINS_THRESHOLD, PLAINS_MOUNTAIN_THRESHOLD, noise, noise_3d,
+        terrain_noise,
+    },
+};
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
 };
 
 use crate::{
-    CHUNK_SIZE,
-    utils::{noise, vec3_to_index},
-    world::{Chunk, ChunkMarker},
+    CHUNK_HEIGHT, CHUNK_SIZE, GameInfo,
+    utils::{BUSH_OBJECT, SNOWY_SPRUCE_OBJECT, SPRUCE_OBJECT, TREE_OBJECT, vec3_to_index},
+    world::{Chunk, DirtyChunks, light::reaches_neighbour},
 };
 
-pub fn update_chunks(
-    commands: &mut Commands,
-    chunks: Vec<(Entity, &Transform)>,
-    positions: Vec<IVec3>,
+// circular so the horizon is just as far in every direction.
+// loading and despawning both go through this so chunks on the edge don't churn
+pub fn in_render_distance(chunk_pos: IVec3, player_pos: Vec3, render_distance: i32) -> bool {
+    let (player_chunk, _) = chunk_coords(player_pos.floor().as_ivec3());
+    (chunk_pos.xz() - player_chunk.xz()).length_squared() <= render_distance * render_distance
+}
+
+// sort key for loading, lower goes first. a chunk straight ahead counts as half as far
+// as one at the same distance behind the camera
+pub fn chunk_priority(chunk_pos: IVec3, player_pos: Vec3, forward: Vec3) -> i32 {
+    let center = (chunk_pos.xz().as_vec2() + 0.5) * CHUNK_SIZE as f32;
+    let offset = center - player_pos.xz();
+    let facing = offset
+        .normalize_or_zero()
+        .dot(forward.xz().normalize_or_zero());
+    (offset.length() * (1.5 - 0.5 * facing)) as i32
+}
+
+pub fn store_entities(
+    chunk: &Chunk,
+    saved_chunks: &mut HashMap<IVec3, SavedChunk>,
+    unsaved_chunks: &mut HashSet<IVec3>,
 ) {
-    for (entity, transform) in chunks {
-        if positions.contains(&(transform.translation / CHUNK_SIZE as f32).as_ivec3()) {
-            commands
-                .entity(entity)
-                .try_remove::<ChunkMarker>()
-                .try_insert(ChunkMarker);
-        }
+    if chunk.entities.is_empty() {
+        return;
     }
+    unsaved_chunks.insert(chunk.pos);
+    saved_chunks.entry(chunk.pos).or_default().entities =
+        chunk.entities.iter().map(|(_, entity)| *entity).collect();
 }
 
 pub fn place_block(
@@ -34,106 +59,274 @@ pub fn place_block(
     pos: IVec3,
     block: Block,
     saved_chunks: &mut Option<&mut HashMap<IVec3, SavedChunk>>,
+    unsaved_chunks: &mut HashSet<IVec3>,
     client: Option<ResMut<RenetClient>>,
-    update: Option<(&mut Commands, Vec<(Entity, &Transform)>)>,
+    dirty_chunks: Option<&mut DirtyChunks>,
 ) {
     chunk.blocks[vec3_to_index(pos)] = block;
+    chunk.uniform[(pos.y / CHUNK_SIZE) as usize] = None;
     if let Some(saved_chunks) = saved_chunks {
+        unsaved_chunks.insert(chunk.pos);
         saved_chunks
             .entry(chunk.pos)
-            .and_modify(|c| {
-                c.blocks.insert(pos, block);
-            })
-            .or_insert(SavedChunk {
-                blocks: HashMap::from([(pos, block)]),
-                // entities: chunk.entities.clone(),
-            });
-    }
-    if let Some((commands, chunks)) = update {
-        let mut positions = vec![chunk.pos];
-        if pos.x == 0 {
-            positions.push(chunk.pos - IVec3::X);
-        }
-        if pos.x == CHUNK_SIZE - 1 {
-            positions.push(chunk.pos + IVec3::X);
-        }
-        if pos.z == 0 {
-            positions.push(chunk.pos - IVec3::Z);
-        }
-        if pos.z == CHUNK_SIZE - 1 {
-            positions.push(chunk.pos + IVec3::Z);
+            .or_default()
+            .blocks
+            .insert(pos, block);
+    }
+    if let Some(dirty_chunks) = dirty_chunks {
+        // the light changes up to LIGHT_REACH blocks away, neighbours that close need remeshing
+        for z in -1..=1 {
+            for x in -1..=1 {
+                if reaches_neighbour(pos, ivec3(x, 0, z)) {
+                    dirty_chunks.0.insert(chunk.pos + ivec3(x, 0, z));
+                }
+            }
         }
-        update_chunks(commands, chunks, positions);
     }
     ClientPacket::PlaceBlock(chunk.pos * CHUNK_SIZE + pos, block).send(client);
 }
 
-#[derive(Default, Clone, Copy)]
-pub struct NoiseFunctions {
-    pub terrain: Noise<Fbm<Simplex>>,
-    pub biome: Noise<Fbm<Simplex>>,
-    pub ferris: Noise<Perlin>,
-    pub tree: Noise<Perlin>,
+// the chunk a world position is in and the position inside it, in every quadrant
+pub fn chunk_coords(pos: IVec3) -> (IVec3, IVec3) {
+    (
+        ivec3(
+            pos.x.div_euclid(CHUNK_SIZE),
+            0,
+            pos.z.div_euclid(CHUNK_SIZE),
+        ),
+        ivec3(
+            pos.x.rem_euclid(CHUNK_SIZE),
+            pos.y,
+            pos.z.rem_euclid(CHUNK_SIZE),
+        ),
+    )
 }
 
-const OCEAN_MIN_HEIGHT: f32 = SEA_LEVEL as f32 - 40.0;
-const OCEAN_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 5.0;
-const OCEAN_FLATTENING_EXPONENT: f32 = 4.0;
-const PLAINS_MIN_HEIGHT: f32 = SEA_LEVEL as f32 + 10.0;
-const PLAINS_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 40.0;
-const PLAINS_FLATTENING_EXPONENT: f32 = 3.0;
-const MOUNTAIN_MIN_HEIGHT: f32 = SEA_LEVEL as f32 + 50.0;
-const MOUNTAIN_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 180.0;
-const MOUNTAIN_FLATTENING_EXPONENT: f32 = 1.5;
-const OCEAN_PLAINS_THRESHOLD: f32 = 0.4;
-const PLAINS_MOUNTAIN_THRESHOLD: f32 = 0.6;
+impl GameInfo {
+    // air for unloaded chunks and outside the world
+    pub fn block_at(&self, pos: IVec3) -> Block {
+        if !(0..CHUNK_HEIGHT).contains(&pos.y) {
+            return Block::AIR;
+        }
+        let (chunk_pos, local_pos) = chunk_coords(pos);
+        self.chunks
+            .read()
+            .unwrap()
+            .get(&chunk_pos)
+            .map_or(Block::AIR, |chunk| chunk.blocks[vec3_to_index(local_pos)])
+    }
 
-#[inline]
-// max_y, biome
-pub fn terrain_noise(pos: Vec2, noises: &NoiseFunctions) -> (i32, f32) {
-    let terrain_fbm = noise(noises.terrain, pos);
-    let biome_fbm = noise(noises.biome, pos);
-
-    let min_height: f32;
-    let max_height: f32;
-    let flattening_exp: f32;
-
-    if biome_fbm < OCEAN_PLAINS_THRESHOLD {
-        let t = biome_fbm / OCEAN_PLAINS_THRESHOLD;
-        min_height = OCEAN_MIN_HEIGHT.lerp(PLAINS_MIN_HEIGHT, t);
-        max_height = OCEAN_MAX_HEIGHT.lerp(PLAINS_MAX_HEIGHT, t);
-        flattening_exp = OCEAN_FLATTENING_EXPONENT.lerp(PLAINS_FLATTENING_EXPONENT, t);
-    } else if biome_fbm < PLAINS_MOUNTAIN_THRESHOLD {
-        let t = (biome_fbm - OCEAN_PLAINS_THRESHOLD)
-            / (PLAINS_MOUNTAIN_THRESHOLD - OCEAN_PLAINS_THRESHOLD);
-        min_height = PLAINS_MIN_HEIGHT.lerp(MOUNTAIN_MIN_HEIGHT, t);
-        max_height = PLAINS_MAX_HEIGHT.lerp(MOUNTAIN_MAX_HEIGHT, t);
-        flattening_exp = PLAINS_FLATTENING_EXPONENT.lerp(MOUNTAIN_FLATTENING_EXPONENT, t);
+    // place_block by world position, false if the chunk isn't loaded
+    pub fn set_block(
+        &self,
+        pos: IVec3,
+        block: Block,
+        client: Option<ResMut<RenetClient>>,
+        dirty_chunks: Option<&mut DirtyChunks>,
+    ) -> bool {
+        if !(0..CHUNK_HEIGHT).contains(&pos.y) {
+            return false;
+        }
+        let (chunk_pos, local_pos) = chunk_coords(pos);
+        let mut chunks = self.chunks.write().unwrap();
+        let Some(chunk) = chunks.get_mut(&chunk_pos).map(Arc::make_mut) else {
+            return false;
+        };
+        let mut saved_chunks = self
+            .saved_chunks
+            .as_ref()
+            .map(|saved_chunks| saved_chunks.write().unwrap());
+        place_block(
+            chunk,
+            local_pos,
+            block,
+            &mut saved_chunks.as_deref_mut(),
+            &mut self.unsaved_chunks.write().unwrap(),
+            client,
+            dirty_chunks,
+        );
+        true
+    }
+}
+
+const CAVE_THRESHOLD: f32 = 0.72;
+const COAL_MAX_HEIGHT: i32 = SEA_LEVEL + 64;
+const COAL_THRESHOLD: f32 = 0.78;
+const IRON_MAX_HEIGHT: i32 = SEA_LEVEL;
+const IRON_THRESHOLD: f32 = 0.8;
+const TREE_LINE: i32 = 140; // bare stone above, nothing grows
+const SNOW_LINE: i32 = 165;
+
+// which tree grows on a column, if any
+pub fn tree_for(
+    max_y: i32,
+    biome: f32,
+    noises: &NoiseFunctions,
+) -> Option<&'static [[[Block; 5]; 5]]> {
+    if biome < OCEAN_PLAINS_THRESHOLD || max_y <= noises.world_gen.sea_level + 2 {
+        None
+    } else if max_y > SNOW_LINE + 1 {
+        // the top block is snow from here on
+        Some(&SNOWY_SPRUCE_OBJECT)
+    } else if max_y > TREE_LINE + 1 {
+        None
+    } else if biome > PLAINS_MOUNTAIN_THRESHOLD {
+        Some(&SPRUCE_OBJECT)
     } else {
-        min_height = MOUNTAIN_MIN_HEIGHT;
-        max_height = MOUNTAIN_MAX_HEIGHT;
-        flattening_exp = MOUNTAIN_FLATTENING_EXPONENT;
+        Some(&TREE_OBJECT)
     }
+}
+
+// structures are 5 wide, centered on the column they grow from
+const STRUCTURE_REACH: i32 = 2;
+
+// the terrain, ferris and trees of a chunk, before saved edits are applied.
+// trees growing in from the neighbours are worked out again here, so they line up across the
+// border whichever order the chunks load in
+pub fn generate_chunk(pos: IVec3, noises: &NoiseFunctions, spawn_entities: bool) -> Chunk {
+    let mut chunk = Chunk::new(pos);
+
+    // every z slice is one run in the block vec, so the slices fill in parallel
+    let slices: Vec<_> = chunk
+        .blocks
+        .par_chunks_mut((CHUNK_SIZE * CHUNK_HEIGHT) as usize)
+        .enumerate()
+        .map(|(rela_z, slice)| {
+            let rela_z = rela_z as i32;
+            let mut entities = Vec::new();
+            let mut trees: Vec<(IVec2, &[[[Block; 5]; 5]])> = Vec::new();
+
+            for rela_x in 0..CHUNK_SIZE {
+                let pos = vec2(
+                    (rela_x + pos.x * CHUNK_SIZE) as f32,
+                    (rela_z + pos.z * CHUNK_SIZE) as f32,
+                );
+                let (max_y, biome) = terrain_noise(pos, noises);
+
+                for y in 0..CHUNK_HEIGHT {
+                    slice[vec3_to_index(ivec3(rela_x, y, 0))] =
+                        generate_block_at(ivec3(pos.x as i32, y, pos.y as i32), max_y, noises);
+
+                    if spawn_entities
+                        && y == max_y
+                        && max_y > noises.world_gen.sea_level
+                        && biome < 0.4
+                        && noise(noises.ferris, pos) > 0.85
+                    {
+                        entities.push((
+                            Entity::PLACEHOLDER,
+                            GameEntity {
+                                kind: GameEntityKind::Ferris,
+                                pos: vec3(pos.x, y as f32, pos.y),
+                                rot: rand::random_range(0..360) as f32,
+                            },
+                        ));
+                    }
+                }
 
-    let height = min_height + terrain_fbm.powf(flattening_exp) * (max_height - min_height);
+                if let Some(object) = structure_at(pos, max_y, biome, noises) {
+                    trees.push((ivec2(rela_x, rela_z), object));
+                }
+            }
+            (entities, trees)
+        })
+        .collect();
 
-    (height as i32, biome_fbm)
+    let mut trees = Vec::new();
+    for (entities, slice_trees) in slices {
+        chunk.entities.extend(entities);
+        trees.extend(slice_trees);
+    }
+
+    for z in -STRUCTURE_REACH..CHUNK_SIZE + STRUCTURE_REACH {
+        for x in -STRUCTURE_REACH..CHUNK_SIZE + STRUCTURE_REACH {
+            if (0..CHUNK_SIZE).contains(&x) && (0..CHUNK_SIZE).contains(&z) {
+                continue;
+            }
+            let column = (pos.xz() * CHUNK_SIZE + ivec2(x, z)).as_vec2();
+            let (max_y, biome) = terrain_noise(column, noises);
+            if let Some(object) = structure_at(column, max_y, biome, noises) {
+                trees.push((ivec2(x, z), object));
+            }
+        }
+    }
+
+    // after the terrain so later columns don't overwrite the leaves
+    for (tree, object) in trees {
+        for (y, tree_layer) in object.iter().enumerate() {
+            for (z, tree_row) in tree_layer.iter().enumerate() {
+                for (x, &block) in tree_row.iter().enumerate() {
+                    if block == Block::AIR {
+                        continue;
+                    }
+                    let mut pos = ivec3(
+                        tree.x + x as i32 - STRUCTURE_REACH,
+                        y as i32,
+                        tree.y + z as i32 - STRUCTURE_REACH,
+                    );
+                    if !(0..CHUNK_SIZE).contains(&pos.x) || !(0..CHUNK_SIZE).contains(&pos.z) {
+                        continue;
+                    }
+                    let (local_max_y, _) =
+                        terrain_noise((chunk.pos * CHUNK_SIZE + pos).as_vec3().xz(), noises);
+
+                    pos.y += local_max_y;
+
+                    if (0..CHUNK_HEIGHT).contains(&pos.y) {
+                        chunk.blocks[vec3_to_index(pos)] = block;
+                    }
+                }
+            }
+        }
+    }
+
+    chunk
+}
+
+// the tree or bush growing from a column, if any
+fn structure_at(
+    pos: Vec2,
+    max_y: i32,
+    biome: f32,
+    noises: &NoiseFunctions,
+) -> Option<&'static [[[Block; 5]; 5]]> {
+    // one tree per noise peak so they don't clump up
+    let tree_probabilty = noise(noises.tree, pos);
+    if let Some(tree) = tree_for(max_y, biome, noises)
+        && tree_probabilty > 1.0 - 0.15 * noises.world_gen.tree_density
+        && [Vec2::X, -Vec2::X, Vec2::Y, -Vec2::Y]
+            .into_iter()
+            .all(|dir| noise(noises.tree, pos + dir) <= tree_probabilty)
+    {
+        Some(tree)
+    } else if (noises.world_gen.sea_level + 1..CHUNK_HEIGHT).contains(&max_y)
+        && generate_block_at(ivec3(pos.x as i32, max_y - 1, pos.y as i32), max_y, noises)
+            == Block::GRASS
+        && ((hash((pos.x as i32, pos.y as i32)) % 1024) as f32)
+            < 16.0 * noises.world_gen.tree_density
+    {
+        Some(&BUSH_OBJECT)
+    } else {
+        None
+    }
 }
 
 #[inline]
-pub fn generate_block_at(pos: IVec3, max_y: i32) -> Block {
+pub fn generate_block_at(pos: IVec3, max_y: i32, noises: &NoiseFunctions) -> Block {
     let y = pos.y;
     if y == 0 {
         Block::BEDROCK
+    } else if y < max_y && is_cave(pos, max_y, noises) {
+        Block::AIR
     } else if y < max_y {
         match y {
-            _ if y > 165 => Block::SNOW,
-            _ if y > 140 => Block::STONE,
+            _ if y > SNOW_LINE => Block::SNOW,
+            _ if y > TREE_LINE => Block::STONE,
             _ if y == max_y - 1 => Block::GRASS,
             _ if y >= max_y - 4 => Block::DIRT,
-            _ => Block::STONE,
+            _ => ore_at(pos, noises).unwrap_or(Block::STONE),
         }
-    } else if y < SEA_LEVEL {
+    } else if y < noises.world_gen.sea_level {
         Block::WATER
     } else {
         Block::AIR
@@ -160,6 +353,30 @@ pub fn generate_block_at(pos: IVec3, max_y: i32) -> Block {
 
     // terrain_block
 }
+#[inline]
+fn is_cave(pos: IVec3, max_y: i32, noises: &NoiseFunctions) -> bool {
+    // keep the ocean floor sealed so caves don't end up under water
+    if max_y <= noises.world_gen.sea_level && pos.y >= max_y - 5 {
+        return false;
+    }
+    let threshold = 1.0 - (1.0 - CAVE_THRESHOLD) * noises.world_gen.cave_density;
+    noise_3d(noises.cave, pos.as_vec3()) > threshold
+}
+
+#[inline]
+fn ore_at(pos: IVec3, noises: &NoiseFunctions) -> Option<Block> {
+    // same noise for both ores, offset so the clusters don't overlap
+    if pos.y < IRON_MAX_HEIGHT
+        && noise_3d(noises.ore, pos.as_vec3() + Vec3::splat(1000.0)) > IRON_THRESHOLD
+    {
+        Some(Block::IRON_ORE)
+    } else if pos.y < COAL_MAX_HEIGHT && noise_3d(noises.ore, pos.as_vec3()) > COAL_THRESHOLD {
+        Some(Block::COAL_ORE)
+    } else {
+        None
+    }
+}
+
 pub struct Quad {
     pub corners: [[f32; 3]; 4],
 }
@@ -167,44 +384,9 @@ pub struct Quad {
 impl Quad {
     #[inline]
     pub fn from_direction(direction: Direction, pos: Vec3, size: Vec3) -> Self {
-        let corners = match direction {
-            Direction::Left => [
-                [pos.x, pos.y, pos.z],
-                [pos.x, pos.y, pos.z + size.z],
-                [pos.x, pos.y + size.y, pos.z + size.z],
-                [pos.x, pos.y + size.y, pos.z],
-            ],
-            Direction::Right => [
-                [pos.x, pos.y + size.y, pos.z],
-                [pos.x, pos.y + size.y, pos.z + size.z],
-                [pos.x, pos.y, pos.z + size.z],
-                [pos.x, pos.y, pos.z],
-            ],
-            Direction::Bottom => [
-                [pos.x, pos.y, pos.z],
-                [pos.x + size.x, pos.y, pos.z],
-                [pos.x + size.x, pos.y, pos.z + size.z],
-                [pos.x, pos.y, pos.z + size.z],
-            ],
-            Direction::Top => [
-                [pos.x, pos.y, pos.z + size.z],
-                [pos.x + size.x, pos.y, pos.z + size.z],
-                [pos.x + size.x, pos.y, pos.z],
-                [pos.x, pos.y, pos.z],
-            ],
-            Direction::Back => [
-                [pos.x, pos.y, pos.z],
-                [pos.x, pos.y + size.y, pos.z],
-                [pos.x + size.x, pos.y + size.y, pos.z],
-                [pos.x + size.x, pos.y, pos.z],
-            ],
-            Direction::Front => [
-                [pos.x + size.x, pos.y, pos.z],
-                [pos.x + size.x, pos.y + size.y, pos.z],
-                [pos.x, pos.y + size.y, pos.z],
-                [pos.x, pos.y, pos.z],
-            ],
-        };
+        let corners = direction
+            .unit_corners()
+            .map(|corner| (pos + corner * size).to_array());
 
         Self { corners }
     }
@@ -217,3 +399,106 @@ impl Quad {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fmt::Write, fs, path::PathBuf};
+
+    use ferriscraft::{WorldGenConfig, terrain::get_noise_functions};
+
+    use super::*;
+
+    const SEED: u32 = 1337;
+    // spread over biomes, across the origin and far out where float precision drops
+    const COLUMNS: [IVec2; 6] = [
+        IVec2::new(0, 0),
+        IVec2::new(15, -1),
+        IVec2::new(123, -456),
+        IVec2::new(-1000, 2000),
+        IVec2::new(5000, 5000),
+        IVec2::new(-20000, -3),
+    ];
+
+    // compares with the recorded file, only UPDATE_SNAPSHOTS writes it.
+    // a diff here means worlds generate differently than they used to
+    fn check_snapshot(name: &str, actual: &str) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("snapshots")
+            .join(name);
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, actual).unwrap();
+            return;
+        }
+        let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+            panic!(
+                "couldn't read {}, record it with UPDATE_SNAPSHOTS=1: {e}",
+                path.display()
+            )
+        });
+        assert!(
+            expected == actual,
+            "terrain no longer matches {}, rerun with UPDATE_SNAPSHOTS=1 if that's on purpose\n\
+             expected:\n{expected}\nactual:\n{actual}",
+            path.display()
+        );
+    }
+
+    // bottom to top, runs of the same block collapsed
+    fn describe_column(blocks: impl Iterator<Item = Block>) -> String {
+        let mut runs: Vec<(Block, usize)> = Vec::new();
+        for block in blocks {
+            match runs.last_mut() {
+                Some((last, count)) if *last == block => *count += 1,
+                _ => runs.push((block, 1)),
+            }
+        }
+        runs.iter()
+            .map(|(block, count)| format!("{:?}x{count}", block.kind))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn terrain_snapshot() {
+        let noises = get_noise_functions(SEED, WorldGenConfig::default());
+        let mut snapshot = String::new();
+        for column in COLUMNS {
+            let (max_y, biome) = terrain_noise(column.as_vec2(), &noises);
+            let blocks = (0..CHUNK_HEIGHT)
+                .map(|y| generate_block_at(ivec3(column.x, y, column.y), max_y, &noises));
+            writeln!(
+                snapshot,
+                "{column}: height {max_y}, biome {biome:.4}, {}",
+                describe_column(blocks)
+            )
+            .unwrap();
+        }
+        check_snapshot("terrain.txt", &snapshot);
+    }
+
+    #[test]
+    fn chunk_matches_block_at() {
+        // no trees or bushes, so every block comes straight from generate_block_at
+        let noises = get_noise_functions(
+            SEED,
+            WorldGenConfig {
+                tree_density: 0.0,
+                ..Default::default()
+            },
+        );
+        for column in COLUMNS {
+            let (chunk_pos, local) = chunk_coords(ivec3(column.x, 0, column.y));
+            let chunk = generate_chunk(chunk_pos, &noises, false);
+            let (max_y, _) = terrain_noise(column.as_vec2(), &noises);
+            for y in 0..CHUNK_HEIGHT {
+                let pos = ivec3(column.x, y, column.y);
+                assert_eq!(
+                    chunk.blocks[vec3_to_index(local.with_y(y))],
+                    generate_block_at(pos, max_y, &noises),
+                    "generate_chunk and generate_block_at differ at {pos}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{collections::HashSet, fs, io, path::Path};
+
+// next to the world save
+const WHITELIST_PATH: &str = "saves/whitelist.txt";
+const BANLIST_PATH: &str = "saves/banlist.txt";
+
+// player names, one per line, lowercase so matching ignores case
+#[derive(Default)]
+pub struct AccessLists {
+    pub whitelist_enabled: bool,
+    pub whitelist: HashSet<String>,
+    pub banlist: HashSet<String>,
+}
+
+impl AccessLists {
+    // missing files are just empty lists
+    pub fn load(whitelist_enabled: bool) -> Self {
+        Self {
+            whitelist_enabled,
+            whitelist: read_names(WHITELIST_PATH),
+            banlist: read_names(BANLIST_PATH),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        write_names(WHITELIST_PATH, &self.whitelist)?;
+        write_names(BANLIST_PATH, &self.banlist)
+    }
+
+    // why the player can't join, if they can't
+    pub fn deny_reason(&self, name: &str) -> Option<&'static str> {
+        let name = name.to_lowercase();
+        if self.banlist.contains(&name) {
+            Some("banned")
+        } else if self.whitelist_enabled && !self.whitelist.contains(&name) {
+            Some("not whitelisted")
+        } else {
+            None
+        }
+    }
+}
+
+fn read_names(path: &str) -> HashSet<String> {
+    fs::read_to_string(path)
+        .map(|names| {
+            names
+                .lines()
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(str::to_lowercase)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn write_names(path: &str, names: &HashSet<String>) -> io::Result<()> {
+    // sorted so the file doesn't shuffle around on every change
+    let mut names = names.iter().map(String::as_str).collect::<Vec<_>>();
+    names.sort_unstable();
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, names.join("\n"))
+}
@@ -0,0 +1,382 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Instant,
+};
+
+use bevy_math::{IVec3, Vec3, ivec3, vec3};
+use ferriscraft::{
+    Block, BlockKind, CHUNK_HEIGHT, CHUNK_SIZE, GameEntity, GameEntityKind, Persistent, SavedChunk,
+    SavedWorld, ServerPacket,
+};
+use renet::RenetServer;
+use renet_netcode::NetcodeServerTransport;
+
+use crate::{
+    access::AccessLists,
+    events::{DEFAULT_KICK_REASON, kick},
+    log,
+    mobs::Mobs,
+    save_game, stop_server,
+    utils::{broadcast_in_game, get_name, player_data},
+};
+
+// every chunk touched goes out whole, so big fills would mean huge packets
+const MAX_FILL_VOLUME: i64 = 4096; // blocks
+
+pub fn handle_command(
+    message: &str,
+    server: &mut Option<RenetServer>,
+    transport: &mut Option<NetcodeServerTransport>,
+    players: &mut HashMap<u64, (String, Vec3, f32)>,
+    access: &mut AccessLists,
+    rejected: &mut HashMap<u64, Instant>,
+    persistent_world: &mut Persistent<SavedWorld>,
+    mobs: &mut Mobs,
+    logs: &mut VecDeque<String>,
+) {
+    let mut parts = message.split_whitespace();
+    let Some(command) = parts.next() else {
+        return;
+    };
+    let args = parts.collect::<Vec<_>>();
+
+    match command {
+        "save" => {
+            save_game(persistent_world, players, mobs, logs);
+        }
+        "stop" => {
+            stop_server(server, transport, players, persistent_world, mobs, logs);
+        }
+        "say" => {
+            let Some(server) = server else {
+                return;
+            };
+            if args.is_empty() {
+                log!(logs, "Usage: /say <message>");
+                return;
+            }
+            server_chat(server, rejected, logs, args.join(" "));
+        }
+        "kick" => {
+            let (Some(server), Some(transport)) = (server, transport) else {
+                return;
+            };
+            let [name, reason @ ..] = &args[..] else {
+                log!(logs, "Usage: /kick <name> [reason]");
+                return;
+            };
+            let reason = match reason.join(" ") {
+                reason if reason.is_empty() => DEFAULT_KICK_REASON.to_string(),
+                reason => reason,
+            };
+            if let Some(client_id) = server
+                .clients_id()
+                .into_iter()
+                .find(|&id| get_name(id, transport).is_some_and(|n| n == *name))
+            {
+                kick(server, rejected, client_id, reason.clone());
+                log!(logs, "Kicked {name}: {reason}");
+            } else {
+                log!(logs, "Player {name} is not online");
+            }
+        }
+        "whitelist" => {
+            let usage = "Usage: /whitelist <add|remove> <name>";
+            let [action, name] = args[..] else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let name = name.to_lowercase();
+            match action {
+                "add" => {
+                    access.whitelist.insert(name.clone());
+                    log!(logs, "Added {name} to the whitelist");
+                }
+                "remove" => {
+                    access.whitelist.remove(&name);
+                    log!(logs, "Removed {name} from the whitelist");
+                }
+                _ => {
+                    log!(logs, "{usage}");
+                    return;
+                }
+            }
+            if let Err(error) = access.save() {
+                log!(logs, "Failed to save the whitelist - {error}");
+            }
+        }
+        "ban" => {
+            let [name] = args[..] else {
+                log!(logs, "Usage: /ban <name>");
+                return;
+            };
+            let name = name.to_lowercase();
+            access.banlist.insert(name.clone());
+            if let Err(error) = access.save() {
+                log!(logs, "Failed to save the ban list - {error}");
+            }
+            if let (Some(server), Some(transport)) = (server, transport)
+                && let Some(client_id) = server
+                    .clients_id()
+                    .into_iter()
+                    .find(|&id| get_name(id, transport).is_some_and(|n| n.to_lowercase() == name))
+            {
+                kick(server, rejected, client_id, "Banned from the server".into());
+            }
+            log!(logs, "Banned {name}");
+        }
+        "unban" => {
+            let [name] = args[..] else {
+                log!(logs, "Usage: /unban <name>");
+                return;
+            };
+            let name = name.to_lowercase();
+            if !access.banlist.remove(&name) {
+                log!(logs, "{name} is not banned");
+                return;
+            }
+            if let Err(error) = access.save() {
+                log!(logs, "Failed to save the ban list - {error}");
+            }
+            log!(logs, "Unbanned {name}");
+        }
+        "list" => {
+            let (Some(server), Some(transport)) = (server, transport) else {
+                return;
+            };
+            log!(
+                logs,
+                "Players online: {} / {}",
+                server.connected_clients(),
+                transport.max_clients()
+            );
+            for client_id in server.clients_id() {
+                let name = get_name(client_id, transport).unwrap_or_default();
+                log!(logs, "- {name} ({:.2}ms)", server.rtt(client_id));
+            }
+        }
+        "tp" => {
+            let Some(server) = server else {
+                return;
+            };
+            let usage = "Usage: /tp <name> <x> <y> <z>";
+            let [name, x, y, z] = args[..] else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let (Ok(x), Ok(y), Ok(z)) = (x.parse::<f32>(), y.parse::<f32>(), z.parse::<f32>())
+            else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let pos = vec3(x, y, z);
+            if !pos.is_finite() {
+                log!(logs, "{usage}");
+                return;
+            }
+            let Some((_, player_pos, _)) = players.values_mut().find(|(n, _, _)| n == name) else {
+                log!(logs, "Player {name} is not online");
+                return;
+            };
+            *player_pos = pos;
+            log!(logs, "Teleported {name} to {pos}");
+            broadcast_in_game(player_data(players), server, rejected);
+        }
+        "summon" => {
+            let usage = "Usage: /summon <x> <y> <z>";
+            let [x, y, z] = args[..] else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let (Ok(x), Ok(y), Ok(z)) = (x.parse::<f32>(), y.parse::<f32>(), z.parse::<f32>())
+            else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let pos = vec3(x, y, z);
+            if !pos.is_finite() {
+                log!(logs, "{usage}");
+                return;
+            }
+            mobs.spawn(GameEntity {
+                kind: GameEntityKind::Ferris,
+                pos,
+                rot: rand::random_range(0.0..std::f32::consts::TAU),
+            });
+            log!(logs, "Summoned Ferris at {pos}");
+        }
+        "setblock" => {
+            let Some(server) = server else {
+                return;
+            };
+            let usage = "Usage: /setblock <x> <y> <z> <block>";
+            let [x, y, z, kind] = args[..] else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let Some(pos) = parse_block_pos([x, y, z]) else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let Some(kind) = BlockKind::from_name(kind) else {
+                log!(logs, "Unknown block: {kind}");
+                return;
+            };
+            let block = Block {
+                kind,
+                ..Default::default()
+            };
+            set_blocks(
+                server,
+                rejected,
+                &mut persistent_world.chunks,
+                [(pos, block)],
+            );
+            log!(logs, "Set {pos} to {kind:?}");
+        }
+        "fill" => {
+            let Some(server) = server else {
+                return;
+            };
+            let usage = "Usage: /fill <x1> <y1> <z1> <x2> <y2> <z2> <block>";
+            let [x1, y1, z1, x2, y2, z2, kind] = args[..] else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let (Some(from), Some(to)) =
+                (parse_block_pos([x1, y1, z1]), parse_block_pos([x2, y2, z2]))
+            else {
+                log!(logs, "{usage}");
+                return;
+            };
+            let Some(kind) = BlockKind::from_name(kind) else {
+                log!(logs, "Unknown block: {kind}");
+                return;
+            };
+            let (min, max) = (from.min(to), from.max(to));
+            // in i64 so far apart corners can't overflow
+            let size = max.as_i64vec3() - min.as_i64vec3() + 1;
+            let volume = size.x.saturating_mul(size.y).saturating_mul(size.z);
+            if volume > MAX_FILL_VOLUME {
+                log!(
+                    logs,
+                    "Can't fill {volume} blocks, the most is {MAX_FILL_VOLUME}"
+                );
+                return;
+            }
+            let block = Block {
+                kind,
+                ..Default::default()
+            };
+            let blocks = (min.x..=max.x).flat_map(|x| {
+                (min.y..=max.y)
+                    .flat_map(move |y| (min.z..=max.z).map(move |z| (ivec3(x, y, z), block)))
+            });
+            set_blocks(server, rejected, &mut persistent_world.chunks, blocks);
+            log!(logs, "Filled {volume} blocks with {kind:?}");
+        }
+        "setspawn" => {
+            let Some(server) = server else {
+                return;
+            };
+            let usage = "Usage: /setspawn <name> or /setspawn <x> <y> <z>";
+            let pos = match args[..] {
+                [name] => {
+                    let Some((_, pos, _)) = players.values().find(|(n, _, _)| n == name) else {
+                        log!(logs, "Player {name} is not online");
+                        return;
+                    };
+                    *pos
+                }
+                [x, y, z] => {
+                    let (Ok(x), Ok(y), Ok(z)) =
+                        (x.parse::<f32>(), y.parse::<f32>(), z.parse::<f32>())
+                    else {
+                        log!(logs, "{usage}");
+                        return;
+                    };
+                    vec3(x, y, z)
+                }
+                _ => {
+                    log!(logs, "{usage}");
+                    return;
+                }
+            };
+            // players that haven't sent a position yet are still at infinity
+            if !pos.is_finite() {
+                log!(logs, "{usage}");
+                return;
+            }
+            persistent_world.spawn = Some(pos);
+            broadcast_in_game(ServerPacket::SpawnPoint(pos), server, rejected);
+            log!(logs, "Set the spawn to {pos}");
+        }
+        "seed" => {
+            log!(logs, "Seed: {}", persistent_world.seed);
+        }
+        "help" => {
+            log!(
+                logs,
+                "Commands: /save, /stop, /say, /kick, /whitelist, /ban, /unban, /list, /tp, /summon, /setblock, /fill, /setspawn, /seed, /help"
+            );
+        }
+        _ => {
+            log!(logs, "Unknown command: /{}", message);
+        }
+    }
+}
+
+// None unless it's three whole numbers inside the world height
+fn parse_block_pos([x, y, z]: [&str; 3]) -> Option<IVec3> {
+    let pos = ivec3(x.parse().ok()?, y.parse().ok()?, z.parse().ok()?);
+    (0..CHUNK_HEIGHT).contains(&pos.y).then_some(pos)
+}
+
+// edits the saved world and sends each changed chunk to every player once
+fn set_blocks(
+    server: &mut RenetServer,
+    rejected: &HashMap<u64, Instant>,
+    saved_chunks: &mut HashMap<IVec3, SavedChunk>,
+    blocks: impl IntoIterator<Item = (IVec3, Block)>,
+) {
+    let mut changed = HashMap::<IVec3, Vec<(IVec3, Block)>>::new();
+    for (pos, block) in blocks {
+        let chunk_pos = ivec3(
+            pos.x.div_euclid(CHUNK_SIZE),
+            0,
+            pos.z.div_euclid(CHUNK_SIZE),
+        );
+        let block_pos = ivec3(
+            pos.x.rem_euclid(CHUNK_SIZE),
+            pos.y,
+            pos.z.rem_euclid(CHUNK_SIZE),
+        );
+        changed
+            .entry(chunk_pos)
+            .or_default()
+            .push((block_pos, block));
+    }
+    for (chunk_pos, blocks) in changed {
+        let saved_chunk = saved_chunks.entry(chunk_pos).or_default();
+        saved_chunk.blocks.extend(blocks);
+        broadcast_in_game(
+            ServerPacket::ChunkUpdate(chunk_pos, saved_chunk.clone()),
+            server,
+            rejected,
+        );
+    }
+}
+
+pub fn server_chat(
+    server: &mut RenetServer,
+    rejected: &HashMap<u64, Instant>,
+    logs: &mut VecDeque<String>,
+    message: String,
+) {
+    log!(logs, "[Server] {}", message);
+    broadcast_in_game(
+        ServerPacket::ChatMessage("Server".to_string(), message),
+        server,
+        rejected,
+    );
+}
@@ -0,0 +1,26 @@
+use std::{
+    io,
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use eframe::egui;
+
+// reads commands typed into the terminal the server was started from, so it can be
+// run without looking at the panel
+pub fn spawn(ctx: egui::Context) -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        // ends on eof, when started without a terminal
+        for line in io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+            ctx.request_repaint();
+        }
+    });
+    receiver
+}
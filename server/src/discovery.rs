@@ -0,0 +1,53 @@
+use std::{
+    io,
+    net::{Ipv4Addr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use ferriscraft::{DISCOVERY_PORT, ServerBeacon};
+
+const BEACON_INTERVAL: Duration = Duration::from_secs(1);
+
+// tells clients on the lan where to find the server
+pub struct Beacon {
+    socket: UdpSocket,
+    name: String,
+    port: u16, // the game port
+    last_sent: Option<Instant>,
+}
+
+impl Beacon {
+    pub fn new(name: String, port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            name,
+            port,
+            last_sent: None,
+        })
+    }
+
+    pub fn update(&mut self, players: usize, max_players: usize) {
+        if self
+            .last_sent
+            .is_some_and(|last_sent| last_sent.elapsed() < BEACON_INTERVAL)
+        {
+            return;
+        }
+        self.last_sent = Some(Instant::now());
+
+        let beacon = ServerBeacon {
+            name: self.name.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            players: players as u32,
+            max_players: max_players as u32,
+            port: self.port,
+        };
+        // nobody listening or no network is fine, it's sent again in a second
+        self.socket
+            .send_to(&beacon.encode(), (Ipv4Addr::BROADCAST, DISCOVERY_PORT))
+            .ok();
+    }
+}
@@ -1,77 +1,198 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
 
-use bevy_math::{Vec3, ivec3};
-use ferriscraft::{CHUNK_SIZE, ClientPacket, Persistent, SavedChunk, SavedWorld, ServerPacket};
+use bevy_math::{IVec3, Vec2, Vec3, ivec3, vec3};
+use ferriscraft::{
+    BlockKind, CHUNK_SIZE, ClientPacket, MAX_HEALTH, Persistent, SavedWorld, ServerPacket,
+    terrain::{get_noise_functions, terrain_noise},
+};
 use renet::{DefaultChannel, RenetServer, ServerEvent};
 use renet_netcode::NetcodeServerTransport;
 
-use crate::{log, utils::get_name};
+use crate::{
+    access::AccessLists,
+    log,
+    mobs::Mobs,
+    utils::{broadcast_in_game, broadcast_in_game_except, get_name, player_data, sanitize_chat},
+};
+
+const MAX_CHAT_LENGTH: usize = 256; // characters
+const CHAT_RATE_LIMIT: usize = 5; // messages per window
+const CHAT_RATE_WINDOW: Duration = Duration::from_secs(5);
+// fastest movement speed while sprinting, or falling at terminal velocity
+const MAX_PLAYER_SPEED: f32 = 85.0; // blocks per second
+const MOVE_SLACK: f32 = 2.0; // blocks
+const RESPAWN_SLACK: f32 = 0.01; // blocks, respawn points are computed the same on both sides
+// time for the rejection reason to reach the client
+const REJECT_DELAY: Duration = Duration::from_secs(1);
+pub const DEFAULT_KICK_REASON: &str = "No reason given";
+
+// where handle_events gets names and addresses of clients from, so it can be driven
+// without a socket by anything that knows them
+pub trait ClientLookup {
+    fn name(&self, client_id: u64) -> Option<String>;
+    fn addr(&self, client_id: u64) -> Option<SocketAddr>;
+}
+
+impl ClientLookup for NetcodeServerTransport {
+    fn name(&self, client_id: u64) -> Option<String> {
+        get_name(client_id, self)
+    }
+
+    fn addr(&self, client_id: u64) -> Option<SocketAddr> {
+        self.client_addr(client_id)
+    }
+}
+
+fn reject(
+    server: &mut RenetServer,
+    rejected: &mut HashMap<u64, Instant>,
+    client_id: u64,
+    reason: String,
+) {
+    ServerPacket::ConnectionRejected(reason).send(server, client_id);
+    rejected.insert(client_id, Instant::now());
+}
+
+// like reject, but for players already in the game
+pub fn kick(
+    server: &mut RenetServer,
+    rejected: &mut HashMap<u64, Instant>,
+    client_id: u64,
+    reason: String,
+) {
+    ServerPacket::Kicked(reason).send(server, client_id);
+    rejected.insert(client_id, Instant::now());
+}
 
 pub fn handle_events(
     server: &mut RenetServer,
-    transport: &mut NetcodeServerTransport,
+    clients: &impl ClientLookup,
     logs: &mut VecDeque<String>,
-    players: &mut HashMap<u64, (String, Vec3)>,
+    players: &mut HashMap<u64, (String, Vec3, f32)>,
+    chat_times: &mut HashMap<u64, VecDeque<Instant>>, // recent messages per client
+    last_moves: &mut HashMap<u64, (Instant, Vec3)>,   // last accepted Move per client
+    access: &AccessLists,
+    rejected: &mut HashMap<u64, Instant>, // disconnected once the reason is sent
+    dead: &mut HashSet<u64>,              // allowed one Respawn each
+    loaded_chunks: &mut HashMap<u64, HashSet<IVec3>>,
     persistent_world: &mut Persistent<SavedWorld>,
+    motd: &str,
 ) {
+    rejected.retain(|&client_id, time| {
+        let waiting = time.elapsed() < REJECT_DELAY;
+        if !waiting {
+            server.disconnect(client_id);
+        }
+        waiting
+    });
+
     let SavedWorld {
         seed,
         players: saved_players,
         chunks: saved_chunks,
+        creative,
+        world_gen,
+        spawn,
     } = &mut persistent_world.data;
     while let Some(event) = server.get_event() {
         match event {
             ServerEvent::ClientConnected { client_id } => {
-                log!(
-                    logs,
-                    "Client {client_id} connecting with IP {}",
-                    transport.client_addr(client_id).unwrap()
-                );
-                let name = if let Some(name) = get_name(client_id, transport) {
+                match clients.addr(client_id) {
+                    Some(addr) => log!(logs, "Client {client_id} connecting with IP {addr}"),
+                    None => log!(logs, "Client {client_id} connecting"),
+                }
+                let name = if let Some(name) = clients.name(client_id) {
                     if name.len() < 3 || name.len() > 16 {
                         log!(logs, "Client {client_id} has invalid name");
-                        server.disconnect(client_id);
+                        reject(server, rejected, client_id, "Invalid player name".into());
+                        continue;
+                    }
+                    if let Some(reason) = access.deny_reason(&name) {
+                        log!(
+                            logs,
+                            "Client {client_id} tried joining as {name} but is {reason}"
+                        );
+                        reject(server, rejected, client_id, format!("You are {reason}"));
                         continue;
                     }
-                    if players.values().any(|(n, _)| n == &name) {
+                    if players.values().any(|(n, _, _)| n == &name) {
                         log!(
                             logs,
                             "Client {client_id} tried joining as {name} but the name is already taken"
                         );
-                        server.disconnect(client_id);
+                        reject(server, rejected, client_id, "Name already in use".into());
                         continue;
                     }
                     name
                 } else {
                     log!(logs, "Client {client_id} has invalid name");
-                    server.disconnect(client_id);
+                    reject(server, rejected, client_id, "Invalid player name".into());
                     continue;
                 };
 
                 log!(logs, "{name} joined the server");
-                let pos = saved_players
-                    .get(&name)
-                    .unwrap_or(&(Vec3::INFINITY, Vec3::ZERO, 0.0, 0.0))
-                    .0;
-                players.insert(client_id, (name.clone(), pos));
-                ServerPacket::PlayerConnected(name, pos).broadcast_except(server, client_id);
-                ServerPacket::ConnectionInfo(*seed, pos).send(server, client_id);
-                ServerPacket::PlayerData(players.values().cloned().collect()).broadcast(server);
+                let (pos, yaw, health, inventory) = saved_players.get(&name).map_or(
+                    (spawn.unwrap_or(Vec3::INFINITY), 0.0, MAX_HEALTH, Vec::new()),
+                    |player| (player.0, player.2, player.4, player.5.clone()),
+                );
+                players.insert(client_id, (name.clone(), pos, yaw));
+                // without a spawn new players start at infinity and can spawn anywhere
+                if pos.is_finite() {
+                    last_moves.insert(client_id, (Instant::now(), pos));
+                }
+                broadcast_in_game_except(
+                    ServerPacket::PlayerConnected(name, pos),
+                    server,
+                    rejected,
+                    client_id,
+                );
+                ServerPacket::ConnectionInfo(*seed, pos, *world_gen, *creative, health, inventory)
+                    .send(server, client_id);
+                if let Some(spawn) = spawn {
+                    ServerPacket::SpawnPoint(*spawn).send(server, client_id);
+                }
+                let online = players.values().map(|(name, _, _)| name.clone()).collect();
+                ServerPacket::Welcome(motd.to_string(), online).send(server, client_id);
+                broadcast_in_game(player_data(players), server, rejected);
             }
             ServerEvent::ClientDisconnected { client_id, reason } => {
-                if let Some((name, pos)) = &players.get(&client_id) {
+                if let Some((name, pos, yaw)) = &players.get(&client_id) {
                     log!(logs, "{name} left the server");
-                    ServerPacket::PlayerDisconnected(name.clone(), reason.to_string())
-                        .broadcast_except(server, client_id);
-                    saved_players.insert(name.clone(), (*pos, Vec3::ZERO, 0.0, 0.0));
+                    broadcast_in_game_except(
+                        ServerPacket::PlayerDisconnected(name.clone(), reason.to_string()),
+                        server,
+                        rejected,
+                        client_id,
+                    );
+                    // kept up to date by the Health and Inventory packets
+                    let (health, inventory) = saved_players
+                        .remove(name)
+                        .map_or((MAX_HEALTH, Vec::new()), |player| (player.4, player.5));
+                    saved_players.insert(
+                        name.clone(),
+                        (*pos, Vec3::ZERO, *yaw, 0.0, health, inventory),
+                    );
                     players.remove(&client_id);
                 }
+                chat_times.remove(&client_id);
+                last_moves.remove(&client_id);
+                rejected.remove(&client_id);
+                dead.remove(&client_id);
+                loaded_chunks.remove(&client_id);
             }
         }
     }
 
     let client_ids = server.clients_id();
     for &client_id in client_ids.iter() {
+        // rejected and kicked clients are still connected until their reason goes out
+        if !players.contains_key(&client_id) || rejected.contains_key(&client_id) {
+            continue;
+        }
         while let Some(message) = server.receive_message(client_id, DefaultChannel::ReliableOrdered)
         {
             let Ok(packet) = bincode::deserialize(&message) else {
@@ -79,10 +200,32 @@ pub fn handle_events(
             };
             match packet {
                 ClientPacket::ChatMessage(msg) => {
-                    log!(logs, "[{}] {}", players[&client_id].0, msg);
-                    ServerPacket::ChatMessage(players[&client_id].0.clone(), msg).broadcast(server);
+                    let name = &players[&client_id].0;
+                    let times = chat_times.entry(client_id).or_default();
+                    while times
+                        .front()
+                        .is_some_and(|time| time.elapsed() > CHAT_RATE_WINDOW)
+                    {
+                        times.pop_front();
+                    }
+                    if times.len() >= CHAT_RATE_LIMIT {
+                        log!(logs, "Dropped a chat message from {name}, sending too fast");
+                        continue;
+                    }
+                    // control characters and newlines would break the chat lines and the logs
+                    let Some(msg) = sanitize_chat(&msg, MAX_CHAT_LENGTH) else {
+                        continue;
+                    };
+                    times.push_back(Instant::now());
+                    log!(logs, "[{name}] {msg}");
+                    broadcast_in_game(
+                        ServerPacket::ChatMessage(name.clone(), msg),
+                        server,
+                        rejected,
+                    );
                 }
                 ClientPacket::LoadChunks(chunks) => {
+                    loaded_chunks.entry(client_id).or_default().extend(&chunks);
                     for chunk in chunks {
                         if let Some(saved_chunk) = saved_chunks.get(&chunk) {
                             ServerPacket::ChunkUpdate(chunk, saved_chunk.clone())
@@ -90,6 +233,66 @@ pub fn handle_events(
                         }
                     }
                 }
+                ClientPacket::UnloadChunks(chunks) => {
+                    if let Some(loaded) = loaded_chunks.get_mut(&client_id) {
+                        for chunk in chunks {
+                            loaded.remove(&chunk);
+                        }
+                    }
+                }
+                ClientPacket::Health(health) => {
+                    let (name, pos, yaw) = &players[&client_id];
+                    let health = health.min(MAX_HEALTH);
+                    saved_players
+                        .entry(name.clone())
+                        .and_modify(|player| player.4 = health)
+                        .or_insert((*pos, Vec3::ZERO, *yaw, 0.0, health, Vec::new()));
+                }
+                ClientPacket::Inventory(mut inventory) => {
+                    let (name, pos, yaw) = &players[&client_id];
+                    inventory.truncate(BlockKind::ALL.len());
+                    saved_players
+                        .entry(name.clone())
+                        .and_modify(|player| player.5 = inventory.clone())
+                        .or_insert((*pos, Vec3::ZERO, *yaw, 0.0, MAX_HEALTH, inventory));
+                }
+                ClientPacket::Died => {
+                    let name = players[&client_id].0.clone();
+                    log!(logs, "{name} died");
+                    dead.insert(client_id);
+                    broadcast_in_game(ServerPacket::PlayerDied(name), server, rejected);
+                }
+                ClientPacket::Respawn(pos) => {
+                    let name = players[&client_id].0.clone();
+                    if !dead.contains(&client_id) {
+                        log!(logs, "Ignored a respawn from {name}, they aren't dead");
+                        continue;
+                    }
+                    // same places the client picks from, the spawn point or the top of the
+                    // terrain at the world origin
+                    let noises = get_noise_functions(*seed, *world_gen);
+                    let origin = vec3(0.0, 1.0 + terrain_noise(Vec2::ZERO, &noises).0 as f32, 0.0);
+                    if !spawn
+                        .iter()
+                        .chain([&origin])
+                        .any(|point| point.distance(pos) <= RESPAWN_SLACK)
+                    {
+                        log!(logs, "Ignored {name} respawning away from the spawn");
+                        continue;
+                    }
+                    dead.remove(&client_id);
+                    last_moves.insert(client_id, (Instant::now(), pos));
+                    players.entry(client_id).and_modify(|x| {
+                        x.1 = pos;
+                    });
+                    broadcast_in_game_except(
+                        ServerPacket::PlayerRespawned(name),
+                        server,
+                        rejected,
+                        client_id,
+                    );
+                    broadcast_in_game(player_data(players), server, rejected);
+                }
                 ClientPacket::PlaceBlock(pos, block) => {
                     let chunk_pos = ivec3(
                         pos.x.div_euclid(CHUNK_SIZE),
@@ -105,21 +308,19 @@ pub fn handle_events(
 
                     saved_chunks
                         .entry(chunk_pos)
-                        .and_modify(|c| {
-                            c.blocks.insert(block_pos, block);
-                        })
-                        .or_insert(SavedChunk {
-                            blocks: HashMap::from([(block_pos, block)]),
-                            // entities: Vec::new(),
-                        });
+                        .or_default()
+                        .blocks
+                        .insert(block_pos, block);
 
                     let player_ids = server
                         .clients_id_iter()
+                        .filter(|id| !rejected.contains_key(id))
                         .filter(|id| {
-                            let player = players.get(id).unwrap().1;
-                            !(!player.is_nan()
-                                && player.is_finite()
-                                && player.as_ivec3().with_y(0).distance_squared(pos) <= 64)
+                            players.get(id).is_some_and(|&(_, player, _)| {
+                                !(!player.is_nan()
+                                    && player.is_finite()
+                                    && player.as_ivec3().with_y(0).distance_squared(pos) <= 64)
+                            })
                         })
                         .collect::<Vec<_>>();
 
@@ -139,14 +340,214 @@ pub fn handle_events(
                 continue;
             };
             match packet {
-                ClientPacket::Move(pos) => {
+                ClientPacket::Move(pos, yaw) => {
+                    let name = &players[&client_id].0;
+                    if !pos.is_finite() || !yaw.is_finite() {
+                        log!(logs, "Ignored an invalid position from {name}");
+                        continue;
+                    }
+                    // checked against what the client last sent, /tp only moves the server's copy.
+                    // capped so standing still or a lost packet doesn't allow a teleport
+                    if let Some((time, last_pos)) = last_moves.get(&client_id)
+                        && last_pos.distance(pos)
+                            > MAX_PLAYER_SPEED * time.elapsed().as_secs_f32().min(1.0) + MOVE_SLACK
+                    {
+                        log!(logs, "Ignored {name} moving too fast");
+                        // put them back where the server has them and check from there
+                        let pos = players[&client_id].1;
+                        if pos.is_finite() {
+                            last_moves.insert(client_id, (Instant::now(), pos));
+                            ServerPacket::Teleport(pos).send(server, client_id);
+                        }
+                        continue;
+                    }
+                    last_moves.insert(client_id, (Instant::now(), pos));
+                    // broadcast once per tick in fixed_update, however many moves came in
                     players.entry(client_id).and_modify(|x| {
                         x.1 = pos;
+                        x.2 = yaw;
                     });
-                    ServerPacket::PlayerData(players.values().cloned().collect()).broadcast(server);
                 }
                 _ => {}
             }
         }
     }
 }
+
+// mobs and time are server authoritative, clients just mirror them.
+// each client only hears about the mobs in chunks it has loaded
+pub fn sync_world(
+    server: &mut RenetServer,
+    mobs: &Mobs,
+    loaded_chunks: &HashMap<u64, HashSet<IVec3>>,
+    rejected: &HashMap<u64, Instant>,
+    time_of_day: f32,
+) {
+    for (&client_id, chunks) in loaded_chunks {
+        if !rejected.contains_key(&client_id) {
+            ServerPacket::EntityUpdate(mobs.in_chunks(chunks)).send(server, client_id);
+        }
+    }
+    broadcast_in_game(ServerPacket::TimeOfDay(time_of_day), server, rejected);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use ferriscraft::Block;
+    use renet::{ConnectionConfig, RenetClient};
+
+    use super::*;
+
+    const CLIENT_ID: u64 = 7;
+
+    // every client is called tester and has no address
+    struct FakeClients;
+
+    impl ClientLookup for FakeClients {
+        fn name(&self, _client_id: u64) -> Option<String> {
+            Some("tester".into())
+        }
+
+        fn addr(&self, _client_id: u64) -> Option<SocketAddr> {
+            None
+        }
+    }
+
+    // what ServerApp keeps between ticks, with one client wired straight to the server
+    struct TestServer {
+        server: RenetServer,
+        client: RenetClient,
+        logs: VecDeque<String>,
+        players: HashMap<u64, (String, Vec3, f32)>,
+        chat_times: HashMap<u64, VecDeque<Instant>>,
+        last_moves: HashMap<u64, (Instant, Vec3)>,
+        rejected: HashMap<u64, Instant>,
+        dead: HashSet<u64>,
+        loaded_chunks: HashMap<u64, HashSet<IVec3>>,
+        persistent_world: Persistent<SavedWorld>,
+    }
+
+    impl TestServer {
+        fn new(path: PathBuf) -> Self {
+            Self {
+                server: RenetServer::new(ConnectionConfig::default()),
+                client: RenetClient::new(ConnectionConfig::default()),
+                logs: VecDeque::new(),
+                players: HashMap::new(),
+                chat_times: HashMap::new(),
+                last_moves: HashMap::new(),
+                rejected: HashMap::new(),
+                dead: HashSet::new(),
+                loaded_chunks: HashMap::new(),
+                persistent_world: SavedWorld::persistent(
+                    path,
+                    SavedWorld {
+                        seed: 0,
+                        players: HashMap::new(),
+                        chunks: HashMap::new(),
+                        creative: true,
+                        world_gen: Default::default(),
+                        spawn: None,
+                    },
+                ),
+            }
+        }
+
+        // one fixed_update, packets are handed over directly instead of through a socket
+        fn tick(&mut self) {
+            let dt = Duration::from_millis(50);
+            for packet in self.client.get_packets_to_send() {
+                self.server.process_packet_from(&packet, CLIENT_ID).ok();
+            }
+            self.server.update(dt);
+            handle_events(
+                &mut self.server,
+                &FakeClients,
+                &mut self.logs,
+                &mut self.players,
+                &mut self.chat_times,
+                &mut self.last_moves,
+                &AccessLists::default(),
+                &mut self.rejected,
+                &mut self.dead,
+                &mut self.loaded_chunks,
+                &mut self.persistent_world,
+                "",
+            );
+            if let Ok(packets) = self.server.get_packets_to_send(CLIENT_ID) {
+                for packet in packets {
+                    self.client.process_packet(&packet);
+                }
+            }
+            self.client.update(dt);
+        }
+
+        fn send(&mut self, packet: ClientPacket) {
+            self.client.send_message(
+                DefaultChannel::ReliableOrdered,
+                bincode::serialize(&packet).unwrap(),
+            );
+        }
+
+        fn received(&mut self, channel: DefaultChannel) -> Vec<ServerPacket> {
+            let channel: u8 = channel.into();
+            let mut packets = Vec::new();
+            while let Some(message) = self.client.receive_message(channel) {
+                packets.push(bincode::deserialize(&message).unwrap());
+            }
+            packets
+        }
+    }
+
+    #[test]
+    fn connect_place_disconnect() {
+        let path = std::env::temp_dir().join(format!(
+            "ferriscraft-events-test-{}.ferris",
+            std::process::id()
+        ));
+        let mut test = TestServer::new(path.clone());
+
+        test.server.add_connection(CLIENT_ID);
+        test.client.set_connected();
+        test.tick();
+        assert!(test.players.contains_key(&CLIENT_ID));
+        assert!(
+            test.received(DefaultChannel::ReliableOrdered)
+                .iter()
+                .any(|packet| matches!(packet, ServerPacket::ConnectionInfo(..)))
+        );
+
+        // one chunk over on x and back on z, to cover the negative side
+        let block = Block {
+            kind: BlockKind::Stone,
+            ..Default::default()
+        };
+        test.send(ClientPacket::PlaceBlock(ivec3(17, 40, -3), block));
+        test.tick();
+        let chunk_pos = ivec3(1, 0, -1);
+        let saved_chunk = &test.persistent_world.chunks[&chunk_pos];
+        assert_eq!(
+            saved_chunk.blocks.iter().collect::<Vec<_>>(),
+            [(ivec3(1, 40, 13), block)]
+        );
+        // the player hasn't sent a position yet, so they aren't next to the block
+        let updates = test.received(DefaultChannel::ReliableUnordered);
+        assert!(matches!(
+            &updates[..],
+            [ServerPacket::ChunkUpdate(pos, chunk)]
+                if *pos == chunk_pos && chunk.blocks.iter().eq([(ivec3(1, 40, 13), block)])
+        ));
+
+        test.server.remove_connection(CLIENT_ID);
+        test.tick();
+        assert!(test.players.is_empty());
+        assert!(test.loaded_chunks.is_empty());
+        assert!(test.persistent_world.players.contains_key("tester"));
+        // placed blocks outlive the player
+        assert!(test.persistent_world.chunks.contains_key(&chunk_pos));
+
+        std::fs::remove_file(path).ok();
+    }
+}
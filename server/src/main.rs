@@ -5,34 +5,82 @@
 )]
 
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{HashMap, HashSet, VecDeque},
     net::{Ipv4Addr, ToSocketAddrs, UdpSocket},
     path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Receiver,
+    },
     time::{Duration, Instant, SystemTime},
 };
 
-use bevy_math::Vec3;
+use bevy_math::{IVec3, Vec3};
 use eframe::egui;
 use renet::{ConnectionConfig, RenetServer};
 use renet_netcode::{NetcodeServerTransport, ServerAuthentication, ServerConfig};
 
-use ferriscraft::{DEFAULT_SERVER_PORT, Persistent, SavedWorld, ServerPacket};
+use ferriscraft::{DEFAULT_SERVER_PORT, MAX_HEALTH, Persistent, SavedWorld};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    events::handle_events,
-    utils::{get_name, local_ip},
+    access::AccessLists,
+    commands::{handle_command, server_chat},
+    discovery::Beacon,
+    events::{DEFAULT_KICK_REASON, handle_events, kick, sync_world},
+    mobs::Mobs,
+    utils::{broadcast_in_game, get_name, local_ip, player_data},
 };
 
+mod access;
+mod commands;
+mod console;
+mod discovery;
 mod events;
+mod mobs;
 mod utils;
 
+const DAY_LENGTH: f32 = 1200.0; // seconds
+
+// set from the signal handler, the next frame saves and closes like the window was closed
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+const DEFAULT_TICK_RATE: u32 = 64; // ticks per second
+const MAX_TICK_RATE: u32 = 240;
+const DEFAULT_AUTOSAVE_INTERVAL: u64 = 600; // seconds
+// after a stall the rest of the backlog is dropped, catching up on all of it
+// would only stall the next frame even longer
+const MAX_CATCH_UP_TICKS: u32 = 8;
+
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub private_address: String,
     pub public_address: String,
     pub port: String,
     pub max_players: String,
+    #[serde(default)]
+    pub whitelist: bool,
+    #[serde(default = "default_tick_rate")]
+    pub tick_rate: String,
+    #[serde(default = "default_autosave_interval")]
+    pub autosave_interval: String, // seconds
+    #[serde(default = "default_server_name")]
+    pub server_name: String, // shown to lan clients
+    #[serde(default)]
+    pub lan_discovery: bool,
+    #[serde(default)]
+    pub motd: String, // shown to players when they join
+}
+
+fn default_tick_rate() -> String {
+    DEFAULT_TICK_RATE.to_string()
+}
+
+fn default_autosave_interval() -> String {
+    DEFAULT_AUTOSAVE_INTERVAL.to_string()
+}
+
+fn default_server_name() -> String {
+    "FerrisCraft Server".to_string()
 }
 
 struct ServerApp {
@@ -40,13 +88,26 @@ struct ServerApp {
     pub error_message: String,
     pub transport: Option<NetcodeServerTransport>,
     pub server: Option<RenetServer>,
-    pub players: HashMap<u64, (String, Vec3)>,
+    pub players: HashMap<u64, (String, Vec3, f32)>, // name, pos, yaw
+    pub chat_times: HashMap<u64, VecDeque<Instant>>,
+    pub last_moves: HashMap<u64, (Instant, Vec3)>,
+    pub access: AccessLists,
+    pub rejected: HashMap<u64, Instant>,
+    pub dead: HashSet<u64>,     // sent Died and haven't respawned yet
+    pub beacon: Option<Beacon>, // only when lan discovery is on
     pub persistent_world: Persistent<SavedWorld>,
+    pub mobs: Mobs,
+    pub loaded_chunks: HashMap<u64, HashSet<IVec3>>, // chunks each client gets entities from
     pub last_autosave: Instant,
+    pub autosave_interval: Duration,
+    pub last_sync: Instant,
     pub last_tick: Instant,
+    pub time_of_day: f32,
     pub accumulator: Duration,
+    pub tick: Duration, // fixed update step, from the tick rate
     pub logs: VecDeque<String>,
     pub user_chat_input: String,
+    pub console: Option<Receiver<String>>, // lines typed into the terminal
 }
 
 // TODO: maybe limit fps?
@@ -58,13 +119,39 @@ fn main() {
     eframe::run_native(
         "FerrisCraft Server",
         options,
-        Box::new(|_cc| Ok(Box::<ServerApp>::default())),
+        Box::new(|cc| {
+            // ctrl-c and SIGTERM in a terminal would otherwise skip saving
+            let ctx = cc.egui_ctx.clone();
+            if let Err(error) = ctrlc::set_handler(move || {
+                SHUTDOWN_REQUESTED.store(true, Ordering::Relaxed);
+                ctx.request_repaint();
+            }) {
+                println!("Couldn't set the shutdown handler: {error}");
+            }
+            Ok(Box::new(ServerApp {
+                console: Some(console::spawn(cc.egui_ctx.clone())),
+                ..Default::default()
+            }))
+        }),
     )
     .unwrap();
 }
 
 impl Default for ServerApp {
     fn default() -> Self {
+        let persistent_world = SavedWorld::persistent(
+            PathBuf::from("saves").join("world.ferris"),
+            SavedWorld {
+                seed: rand::random(),
+                players: HashMap::new(),
+                chunks: HashMap::new(),
+                creative: true,
+                world_gen: Default::default(),
+                spawn: None,
+            },
+        );
+        // mobs come out of the saved chunks, so a save right away doesn't lose them
+        let mobs = Mobs::load(&persistent_world);
         Self {
             config: Persistent::<Config>::new(
                 PathBuf::from("config.toml"),
@@ -75,6 +162,12 @@ impl Default for ServerApp {
                     public_address: "".to_string(),
                     port: DEFAULT_SERVER_PORT.to_string(),
                     max_players: 64.to_string(),
+                    whitelist: false,
+                    tick_rate: default_tick_rate(),
+                    autosave_interval: default_autosave_interval(),
+                    server_name: default_server_name(),
+                    lan_discovery: false,
+                    motd: "".to_string(),
                 },
                 true,
             ),
@@ -82,20 +175,25 @@ impl Default for ServerApp {
             transport: None,
             server: None,
             players: HashMap::new(),
-            persistent_world: Persistent::<SavedWorld>::new(
-                PathBuf::from("saves").join("world.ferris"),
-                SavedWorld {
-                    seed: rand::random(),
-                    players: HashMap::new(),
-                    chunks: HashMap::new(),
-                },
-                false,
-            ),
+            chat_times: HashMap::new(),
+            last_moves: HashMap::new(),
+            access: AccessLists::default(),
+            rejected: HashMap::new(),
+            dead: HashSet::new(),
+            beacon: None,
+            persistent_world,
+            mobs,
+            loaded_chunks: HashMap::new(),
             last_autosave: Instant::now(),
+            autosave_interval: Duration::from_secs(DEFAULT_AUTOSAVE_INTERVAL),
+            last_sync: Instant::now(),
             last_tick: Instant::now(),
+            time_of_day: 0.25,
             accumulator: Duration::ZERO,
+            tick: Duration::from_secs_f64(1.0 / DEFAULT_TICK_RATE as f64),
             logs: VecDeque::with_capacity(256),
             user_chat_input: "".to_string(),
+            console: None,
         }
     }
 }
@@ -110,14 +208,52 @@ impl ServerApp {
 
             let logs = &mut self.logs;
             let players = &mut self.players;
+            let chat_times = &mut self.chat_times;
+            let last_moves = &mut self.last_moves;
+            let access = &self.access;
+            let rejected = &mut self.rejected;
+            let dead = &mut self.dead;
             let persistent_world = &mut self.persistent_world;
+            let mobs = &mut self.mobs;
+            let loaded_chunks = &mut self.loaded_chunks;
+
+            handle_events(
+                server,
+                &*transport,
+                logs,
+                players,
+                chat_times,
+                last_moves,
+                access,
+                rejected,
+                dead,
+                loaded_chunks,
+                persistent_world,
+                &self.config.motd,
+            );
+
+            if !players.is_empty() {
+                broadcast_in_game(player_data(players), server, rejected);
+            }
 
-            handle_events(server, transport, logs, players, persistent_world);
+            mobs.wander(dt.as_secs_f32(), loaded_chunks);
+
+            self.time_of_day = (self.time_of_day + dt.as_secs_f32() / DAY_LENGTH).fract();
+            if self.last_sync.elapsed() > Duration::from_millis(250) {
+                mobs.despawn_far(players.values().map(|(_, pos, _)| *pos));
+                mobs.spawn_near(players.values().map(|(_, pos, _)| *pos));
+                sync_world(server, mobs, loaded_chunks, rejected, self.time_of_day);
+                self.last_sync = Instant::now();
+            }
 
             transport.send_packets(server);
 
-            if self.last_autosave.elapsed() > Duration::from_secs(600) {
-                save_game(persistent_world, players, logs);
+            if let Some(beacon) = &mut self.beacon {
+                beacon.update(server.connected_clients(), transport.max_clients());
+            }
+
+            if self.last_autosave.elapsed() > self.autosave_interval {
+                save_game(persistent_world, players, mobs, logs);
                 self.last_autosave = Instant::now();
             }
         }
@@ -127,8 +263,9 @@ impl ServerApp {
 fn stop_server(
     server: &mut Option<RenetServer>,
     transport: &mut Option<NetcodeServerTransport>,
-    players: &mut HashMap<u64, (String, Vec3)>,
+    players: &mut HashMap<u64, (String, Vec3, f32)>,
     persistent_world: &mut Persistent<SavedWorld>,
+    mobs: &Mobs,
     logs: &mut VecDeque<String>,
 ) {
     log!(logs, "Shutting down...");
@@ -137,7 +274,7 @@ fn stop_server(
     {
         transport.disconnect_all(server);
     }
-    save_game(persistent_world, players, logs);
+    save_game(persistent_world, players, mobs, logs);
     *server = None;
     *transport = None;
     players.clear();
@@ -151,22 +288,61 @@ impl eframe::App for ServerApp {
             &mut self.transport,
             &mut self.players,
             &mut self.persistent_world,
+            &self.mobs,
             &mut self.logs,
         );
         self.config.write().ok();
     }
 
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // on_exit does the saving and disconnecting
+        if SHUTDOWN_REQUESTED.swap(false, Ordering::Relaxed) {
+            log!(&mut self.logs, "Received shutdown signal");
+            ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+        }
+
+        // the terminal takes the same commands as the chat input, the slash is optional
+        while let Some(line) = self
+            .console
+            .as_ref()
+            .and_then(|console| console.try_recv().ok())
+        {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if self.server.is_none() || self.transport.is_none() {
+                log!(&mut self.logs, "Server is offline");
+                continue;
+            }
+            handle_command(
+                line.strip_prefix('/').unwrap_or(line),
+                &mut self.server,
+                &mut self.transport,
+                &mut self.players,
+                &mut self.access,
+                &mut self.rejected,
+                &mut self.persistent_world,
+                &mut self.mobs,
+                &mut self.logs,
+            );
+        }
+
         let now = Instant::now();
         let dt = now.duration_since(self.last_tick);
         self.last_tick = now;
         self.accumulator += dt;
 
-        let dt = Duration::from_secs_f64(1.0 / 64.0);
-
+        let dt = self.tick;
+        let mut ticks = 0;
         while self.accumulator >= dt {
+            if ticks == MAX_CATCH_UP_TICKS {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
             self.fixed_update(dt);
             self.accumulator -= dt;
+            ticks += 1;
         }
 
         let ServerApp {
@@ -175,12 +351,25 @@ impl eframe::App for ServerApp {
             transport,
             server,
             players,
+            chat_times: _,
+            last_moves: _,
+            access,
+            rejected,
+            dead,
+            beacon,
             persistent_world,
-            last_autosave: _,
+            mobs,
+            loaded_chunks,
+            last_autosave,
+            autosave_interval,
+            last_sync: _,
             last_tick: _,
+            time_of_day: _,
             accumulator: _,
+            tick,
             logs,
             user_chat_input,
+            console: _,
         } = self;
 
         ctx.style_mut(|style| {
@@ -227,14 +416,14 @@ impl eframe::App for ServerApp {
 
                     egui::ScrollArea::vertical().show(ui, |ui| {
                         for client_id in server.clients_id() {
-                            let username = get_name(client_id, transport).unwrap();
+                            let username = get_name(client_id, transport).unwrap_or_default();
                             let rtt = server.rtt(client_id);
                             let addr = transport.client_addr(client_id).unwrap();
 
                             ui.group(|ui| {
                                 ui.horizontal(|ui| {
                                     ui.vertical(|ui| {
-                                        ui.label(egui::RichText::new(username).strong());
+                                        ui.label(egui::RichText::new(&username).strong());
                                         ui.label(
                                             egui::RichText::new(format!("Ping: {:.2}ms", rtt))
                                                 .color(egui::Color32::LIGHT_BLUE),
@@ -255,7 +444,13 @@ impl eframe::App for ServerApp {
                                                 )
                                                 .clicked()
                                             {
-                                                server.disconnect(client_id);
+                                                kick(
+                                                    server,
+                                                    rejected,
+                                                    client_id,
+                                                    DEFAULT_KICK_REASON.into(),
+                                                );
+                                                log!(logs, "Kicked {username}");
                                             }
                                         },
                                     );
@@ -300,6 +495,47 @@ impl eframe::App for ServerApp {
                                 egui::TextEdit::singleline(&mut config.max_players)
                                     .horizontal_align(egui::Align::Center),
                             );
+
+                            ui.label("Tick Rate:").on_hover_text_at_pointer(
+                                "Simulation steps per second, lower saves bandwidth",
+                            );
+                            ui.add_sized(
+                                [200.0, 28.0],
+                                egui::TextEdit::singleline(&mut config.tick_rate)
+                                    .horizontal_align(egui::Align::Center),
+                            );
+
+                            ui.label("Autosave Interval (s):");
+                            ui.add_sized(
+                                [200.0, 28.0],
+                                egui::TextEdit::singleline(&mut config.autosave_interval)
+                                    .horizontal_align(egui::Align::Center),
+                            );
+
+                            ui.label("Server Name:");
+                            ui.add_sized(
+                                [200.0, 28.0],
+                                egui::TextEdit::singleline(&mut config.server_name)
+                                    .horizontal_align(egui::Align::Center),
+                            );
+
+                            ui.label("MOTD:")
+                                .on_hover_text_at_pointer("Shown to players when they join");
+                            ui.add_sized(
+                                [200.0, 28.0],
+                                egui::TextEdit::singleline(&mut config.motd)
+                                    .horizontal_align(egui::Align::Center),
+                            );
+
+                            ui.add_space(6.0);
+                            ui.checkbox(&mut config.whitelist, "Whitelist")
+                                .on_hover_text_at_pointer(
+                                    "Only players in saves/whitelist.txt can join",
+                                );
+                            ui.checkbox(&mut config.lan_discovery, "LAN Discovery")
+                                .on_hover_text_at_pointer(
+                                    "Show up in the server list of clients on this network",
+                                );
                         });
                     });
                 }
@@ -332,7 +568,7 @@ impl eframe::App for ServerApp {
                         .add_sized([240.0, 40.0], egui::Button::new("Stop Server"))
                         .clicked()
                     {
-                        stop_server(server, transport, players, persistent_world, logs);
+                        stop_server(server, transport, players, persistent_world, mobs, logs);
                     }
                 } else {
                     ui.colored_label(egui::Color32::LIGHT_RED, "Server is offline");
@@ -379,6 +615,24 @@ impl eframe::App for ServerApp {
                                 *error_message = "Max players too high".to_string();
                                 return;
                             }
+                            let Some(tick_rate) = config
+                                .tick_rate
+                                .parse::<u32>()
+                                .ok()
+                                .filter(|rate| (1..=MAX_TICK_RATE).contains(rate))
+                            else {
+                                *error_message = "Invalid tick rate".to_string();
+                                return;
+                            };
+                            let Some(autosave_secs) = config
+                                .autosave_interval
+                                .parse::<u64>()
+                                .ok()
+                                .filter(|&secs| secs > 0)
+                            else {
+                                *error_message = "Invalid autosave interval".to_string();
+                                return;
+                            };
                             let Ok(current_time) =
                                 SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
                             else {
@@ -407,6 +661,10 @@ impl eframe::App for ServerApp {
                                 + split[2].parse::<u64>().unwrap();
 
                             log!(logs, "Protocol ID - {protocol_id}");
+                            log!(logs, "Tick rate - {tick_rate} Hz");
+                            *tick = Duration::from_secs_f64(1.0 / tick_rate as f64);
+                            *autosave_interval = Duration::from_secs(autosave_secs);
+                            *last_autosave = Instant::now();
 
                             let server_config = ServerConfig {
                                 current_time,
@@ -421,6 +679,21 @@ impl eframe::App for ServerApp {
                             log!(logs, "Initializing transport layer...");
                             *transport =
                                 Some(NetcodeServerTransport::new(server_config, socket).unwrap());
+                            *access = AccessLists::load(config.whitelist);
+                            loaded_chunks.clear();
+                            dead.clear();
+                            *beacon = None;
+                            if config.lan_discovery {
+                                match Beacon::new(config.server_name.clone(), port) {
+                                    Ok(lan_beacon) => {
+                                        log!(logs, "Broadcasting on the LAN");
+                                        *beacon = Some(lan_beacon);
+                                    }
+                                    Err(error) => {
+                                        log!(logs, "Failed to start LAN discovery: {error}");
+                                    }
+                                }
+                            }
                             log!(logs, "Up and running!");
                         });
                     }
@@ -459,41 +732,26 @@ impl eframe::App for ServerApp {
                     input_response.request_focus();
                     if server.is_some() && transport.is_some() {
                         let message = user_chat_input.trim();
-                        if !message.is_empty() {
-                            let mut parts = message.split_whitespace();
-                            let command = parts.next();
-                            let args = parts.map(|s| s.to_string()).collect::<Vec<_>>();
-                            if let Some(cmd) = command {
-                                match cmd {
-                                    "save" => {
-                                        save_game(persistent_world, players, logs);
-                                    }
-                                    "stop" => {
-                                        stop_server(
-                                            server,
-                                            transport,
-                                            players,
-                                            persistent_world,
-                                            logs,
-                                        );
-                                    }
-                                    "say" => {
-                                        if !args.is_empty() {
-                                            let msg = args.join(" ");
-                                            log!(logs, "[Server] {}", msg);
-                                            ServerPacket::ChatMessage("Server".to_string(), msg)
-                                                .broadcast(server.as_mut().unwrap());
-                                        } else {
-                                            log!(logs, "Usage: /say <message>");
-                                        }
-                                    }
-                                    _ => {
-                                        log!(logs, "Unknown command: {}", message);
-                                    }
-                                }
-                            } else {
-                                log!(logs, "Unknown command: {}", message);
-                            }
+                        if let Some(command) = message.strip_prefix('/') {
+                            handle_command(
+                                command,
+                                server,
+                                transport,
+                                players,
+                                access,
+                                rejected,
+                                persistent_world,
+                                mobs,
+                                logs,
+                            );
+                            user_chat_input.clear();
+                        } else if !message.is_empty() {
+                            server_chat(
+                                server.as_mut().unwrap(),
+                                rejected,
+                                logs,
+                                message.to_string(),
+                            );
                             user_chat_input.clear();
                         }
                     }
@@ -506,18 +764,27 @@ impl eframe::App for ServerApp {
 
 pub fn save_game(
     persistent_world: &mut Persistent<SavedWorld>,
-    players: &HashMap<u64, (String, Vec3)>,
+    players: &HashMap<u64, (String, Vec3, f32)>,
+    mobs: &Mobs,
     logs: &mut VecDeque<String>,
 ) {
     log!(logs, "Saving...");
-    // chunks are updated in Persistent<SavedWorld>
+    // chunks are updated in Persistent<SavedWorld>, mobs only get written back here
     if let Err(error) = persistent_world.update(|saved_world| {
-        for (_player_id, (name, pos)) in players.iter() {
-            saved_world
+        mobs.store(saved_world);
+        for (_player_id, (name, pos, yaw)) in players.iter() {
+            let (health, inventory) = saved_world
                 .players
-                .insert(name.clone(), (*pos, Vec3::ZERO, 0.0, 0.0));
+                .remove(name)
+                .map_or((MAX_HEALTH, Vec::new()), |player| (player.4, player.5));
+            saved_world.players.insert(
+                name.clone(),
+                (*pos, Vec3::ZERO, *yaw, 0.0, health, inventory),
+            );
         }
     }) {
         log!(logs, "Failed to save game - {error}");
+    } else {
+        log!(logs, "World saved.");
     }
 }
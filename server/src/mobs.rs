@@ -0,0 +1,177 @@
+use std::{
+    collections::{HashMap, HashSet},
+    f32::consts::TAU,
+};
+
+use bevy_math::{IVec3, Quat, Vec2, Vec3, Vec3Swizzles, ivec3, vec3};
+use ferriscraft::{
+    CHUNK_SIZE, GameEntity, GameEntityKind, SavedWorld,
+    terrain::{NoiseFunctions, OCEAN_PLAINS_THRESHOLD, get_noise_functions, terrain_noise},
+};
+
+const WANDER_SPEED: f32 = 1.5; // blocks per second, same as singleplayer
+const MAX_MOBS_NEARBY: usize = 6; // per player
+const SPAWN_RADIUS: f32 = 48.0; // blocks, mobs further than this don't count as nearby
+const MIN_SPAWN_DISTANCE: f32 = 24.0; // not right in front of anyone
+const DESPAWN_DISTANCE: f32 = 128.0; // blocks from the closest player
+
+// every mob in the world, taken from the saved chunks when the server starts and written
+// back into them on save. ids stay the same while the server runs, clients match on them
+#[derive(Default)]
+pub struct Mobs {
+    mobs: HashMap<u64, Mob>,
+    next_id: u64,
+    noises: NoiseFunctions,
+}
+
+struct Mob {
+    entity: GameEntity,
+    timer: f32,
+    walking: bool,
+}
+
+impl Mobs {
+    pub fn load(world: &SavedWorld) -> Self {
+        let mut mobs = Mobs {
+            noises: get_noise_functions(world.seed, world.world_gen),
+            ..Default::default()
+        };
+        for chunk in world.chunks.values() {
+            for &entity in &chunk.entities {
+                mobs.spawn(entity);
+            }
+        }
+        mobs
+    }
+
+    pub fn spawn(&mut self, entity: GameEntity) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.mobs.insert(
+            id,
+            Mob {
+                entity,
+                timer: 0.0,
+                walking: false,
+            },
+        );
+    }
+
+    // every saved chunk ends up with exactly the mobs standing in it
+    pub fn store(&self, world: &mut SavedWorld) {
+        for chunk in world.chunks.values_mut() {
+            chunk.entities.clear();
+        }
+        for mob in self.mobs.values() {
+            world
+                .chunks
+                .entry(chunk_pos(mob.entity.pos))
+                .or_default()
+                .entities
+                .push(mob.entity);
+        }
+    }
+
+    pub fn in_chunks(&self, chunks: &HashSet<IVec3>) -> Vec<(u64, GameEntity)> {
+        self.mobs
+            .iter()
+            .filter(|(_, mob)| chunks.contains(&chunk_pos(mob.entity.pos)))
+            .map(|(&id, mob)| (id, mob.entity))
+            .collect()
+    }
+
+    // one attempt per player short on mobs, they only spawn on beaches like in singleplayer
+    pub fn spawn_near(&mut self, players: impl Iterator<Item = Vec3>) {
+        for player in players.filter(|player| player.is_finite()) {
+            let nearby = self
+                .mobs
+                .values()
+                .filter(|mob| mob.entity.pos.xz().distance(player.xz()) < SPAWN_RADIUS)
+                .count();
+            if nearby >= MAX_MOBS_NEARBY {
+                continue;
+            }
+
+            let angle = rand::random_range(0.0..TAU);
+            let distance = rand::random_range(MIN_SPAWN_DISTANCE..SPAWN_RADIUS);
+            let column = (player.xz() + Vec2::from_angle(angle) * distance).floor();
+            let (height, biome) = terrain_noise(column, &self.noises);
+            if biome < OCEAN_PLAINS_THRESHOLD && height > self.noises.world_gen.sea_level {
+                self.spawn(GameEntity {
+                    kind: GameEntityKind::Ferris,
+                    pos: vec3(column.x, height as f32, column.y),
+                    rot: rand::random_range(0.0..TAU),
+                });
+            }
+        }
+    }
+
+    // spawn_near keeps filling the area around players, this drops mobs nobody is near any
+    // more so they don't pile up wherever players have been. with nobody online they all stay
+    pub fn despawn_far(&mut self, players: impl Iterator<Item = Vec3>) {
+        let players: Vec<Vec3> = players.filter(|player| player.is_finite()).collect();
+        if players.is_empty() {
+            return;
+        }
+        self.mobs.retain(|_, mob| {
+            players
+                .iter()
+                .any(|player| mob.entity.pos.xz().distance(player.xz()) < DESPAWN_DISTANCE)
+        });
+    }
+
+    // only mobs some client has loaded move, on the terrain as it was generated. trees and
+    // player edits aren't known here
+    pub fn wander(&mut self, dt: f32, loaded_chunks: &HashMap<u64, HashSet<IVec3>>) {
+        for mob in self.mobs.values_mut() {
+            let entity = &mut mob.entity;
+            let chunk = chunk_pos(entity.pos);
+            if entity.kind != GameEntityKind::Ferris
+                || !loaded_chunks.values().any(|chunks| chunks.contains(&chunk))
+            {
+                continue;
+            }
+
+            mob.timer -= dt;
+            if mob.timer <= 0.0 {
+                mob.walking = !mob.walking;
+                if mob.walking {
+                    entity.rot = rand::random_range(0.0..TAU);
+                    mob.timer = rand::random_range(2.0..5.0);
+                } else {
+                    mob.timer = rand::random_range(1.0..4.0);
+                }
+            }
+            if !mob.walking {
+                continue;
+            }
+
+            let forward = Quat::from_rotation_y(entity.rot) * Vec3::Z;
+            let next = entity.pos + forward * WANDER_SPEED * dt;
+            let column = (next.xz() + Vec2::splat(0.5)).floor();
+            let (height, _) = terrain_noise(column, &self.noises);
+            let ground = height as f32;
+
+            // walls, cliffs higher than a block and water
+            if ground > entity.pos.y
+                || ground < entity.pos.y - 1.0
+                || height <= self.noises.world_gen.sea_level
+            {
+                mob.walking = false;
+                mob.timer = rand::random_range(1.0..4.0);
+                continue;
+            }
+            entity.pos = next.with_y(ground);
+        }
+    }
+}
+
+// same as the client, from the middle of the mob
+fn chunk_pos(pos: Vec3) -> IVec3 {
+    let center = (pos + vec3(0.5, 0.0, 0.5)).floor().as_ivec3();
+    ivec3(
+        center.x.div_euclid(CHUNK_SIZE),
+        0,
+        center.z.div_euclid(CHUNK_SIZE),
+    )
+}
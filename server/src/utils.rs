@@ -1,8 +1,12 @@
 use std::{
-    collections::VecDeque,
+    collections::{HashMap, VecDeque},
     net::{IpAddr, Ipv4Addr, UdpSocket},
+    time::Instant,
 };
 
+use bevy_math::Vec3;
+use ferriscraft::ServerPacket;
+use renet::RenetServer;
 use renet_netcode::NetcodeServerTransport;
 
 #[macro_export]
@@ -23,6 +27,46 @@ fn trimmed(b: &[u8]) -> &[u8] {
     &b[..b.iter().rposition(|&x| x != 0).map_or(0, |p| p + 1)]
 }
 
+// None when nothing is left worth sending
+pub fn sanitize_chat(message: &str, max_length: usize) -> Option<String> {
+    let message: String = message
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(max_length)
+        .collect();
+    let message = message.trim();
+    (!message.is_empty()).then(|| message.to_string())
+}
+
+pub fn player_data(players: &HashMap<u64, (String, Vec3, f32)>) -> ServerPacket {
+    ServerPacket::PlayerData(
+        players
+            .values()
+            .map(|(name, pos, yaw)| (name.clone(), (*pos, *yaw)))
+            .collect(),
+    )
+}
+
+// rejected and kicked clients stay connected until their reason arrives, they get nothing else
+pub fn broadcast_in_game(
+    mut packet: ServerPacket,
+    server: &mut RenetServer,
+    rejected: &HashMap<u64, Instant>,
+) {
+    packet.broadcast_except_where(server, |client_id| rejected.contains_key(&client_id));
+}
+
+pub fn broadcast_in_game_except(
+    mut packet: ServerPacket,
+    server: &mut RenetServer,
+    rejected: &HashMap<u64, Instant>,
+    except: u64,
+) {
+    packet.broadcast_except_where(server, |client_id| {
+        client_id == except || rejected.contains_key(&client_id)
+    });
+}
+
 pub fn get_name(client_id: u64, transport: &NetcodeServerTransport) -> Option<String> {
     Some(String::from_utf8_lossy(trimmed(&transport.user_data(client_id)?)).into_owned())
 }
@@ -8,11 +8,12 @@
 use std::{
     collections::HashMap,
     hash::{DefaultHasher, Hasher},
+    io::Read,
     ops::{Deref, DerefMut},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use bevy_math::{IVec3, Vec2, Vec3, vec2, vec3};
+use bevy_math::{IVec3, UVec2, Vec2, Vec3, ivec3, uvec2, vec2, vec3};
 use renet::{DefaultChannel, RenetServer};
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
@@ -21,18 +22,67 @@ use bevy_ecs::prelude::*;
 #[cfg(feature = "client")]
 use renet::RenetClient;
 
+pub mod terrain;
+
 pub const DEFAULT_SERVER_PORT: u16 = 42069;
+pub const DISCOVERY_PORT: u16 = 42070; // lan beacons, see ServerBeacon
 
 pub const CHUNK_SIZE: i32 = 16; // MAX 63
 pub const CHUNK_HEIGHT: i32 = 256; // MAX 511
 pub const SEA_LEVEL: i32 = 64; // MAX CHUNK_HEIGHT - 180
 
+// written in front of every bincode save, bump SAVE_VERSION when SavedWorld changes
+pub const SAVE_MAGIC: [u8; 4] = *b"FRSC";
+pub const SAVE_VERSION: u32 = 1;
+
+// sparse edits get packed into a full chunk once there are more than this
+const DENSE_THRESHOLD: usize = 1024;
+
+pub const MAX_HEALTH: u32 = 20;
+
+// broadcast on the lan by servers that want to be found, plain udp next to renet
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ServerBeacon {
+    pub name: String,
+    pub version: String,
+    pub players: u32,
+    pub max_players: u32,
+    pub port: u16, // the game port, the beacon comes from another one
+}
+
+const BEACON_MAGIC: [u8; 4] = *b"FRSB";
+
+impl ServerBeacon {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = BEACON_MAGIC.to_vec();
+        bytes.extend(bincode::serialize(self).unwrap());
+        bytes
+    }
+
+    // anything else on the port is ignored
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize(bytes.strip_prefix(&BEACON_MAGIC)?).ok()
+    }
+}
+
+// tile grid of atlas.png, BlockKind::tiles says which tile each face uses
+pub const ATLAS: AtlasLayout = AtlasLayout {
+    columns: 3,
+    rows: 13,
+    tile_pixels: 16,
+};
+
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ClientPacket {
     ChatMessage(String),
     PlaceBlock(IVec3, Block),
     LoadChunks(Vec<IVec3>),
-    Move(Vec3),
+    Move(Vec3, f32), // pos, yaw
+    Died,
+    Respawn(Vec3),            // pos
+    UnloadChunks(Vec<IVec3>), // despawned, no more entity updates for these
+    Health(u32),
+    Inventory(Vec<u32>), // block counts indexed by BlockKind
 }
 
 #[cfg(feature = "client")]
@@ -42,7 +92,12 @@ impl ClientPacket {
             ClientPacket::ChatMessage(_) => DefaultChannel::ReliableOrdered,
             ClientPacket::PlaceBlock(_, _) => DefaultChannel::ReliableOrdered,
             ClientPacket::LoadChunks(_) => DefaultChannel::ReliableOrdered,
-            ClientPacket::Move(_) => DefaultChannel::Unreliable,
+            ClientPacket::Move(_, _) => DefaultChannel::Unreliable,
+            ClientPacket::Died => DefaultChannel::ReliableOrdered,
+            ClientPacket::Respawn(_) => DefaultChannel::ReliableOrdered,
+            ClientPacket::UnloadChunks(_) => DefaultChannel::ReliableOrdered,
+            ClientPacket::Health(_) => DefaultChannel::ReliableOrdered,
+            ClientPacket::Inventory(_) => DefaultChannel::ReliableOrdered,
         }
     }
     pub fn send(&mut self, client: Option<ResMut<RenetClient>>) {
@@ -57,9 +112,19 @@ pub enum ServerPacket {
     ChatMessage(String, String),        // player, message
     PlayerConnected(String, Vec3),      // player, pos
     PlayerDisconnected(String, String), // player, reason
-    ConnectionInfo(u32, Vec3),          // seed, pos
-    ChunkUpdate(IVec3, SavedChunk),     // pos, chunk
-    PlayerData(HashMap<String, Vec3>),  // player, pos
+    // seed, pos, generation, creative, health, inventory
+    ConnectionInfo(u32, Vec3, WorldGenConfig, bool, u32, Vec<u32>),
+    ChunkUpdate(IVec3, SavedChunk),           // pos, chunk
+    PlayerData(HashMap<String, (Vec3, f32)>), // player, (pos, yaw)
+    EntityUpdate(Vec<(u64, GameEntity)>),     // id, entity
+    TimeOfDay(f32),                           // 0.0 - 1.0, 0.0 being midnight
+    PlayerDied(String),                       // player
+    PlayerRespawned(String),                  // player
+    ConnectionRejected(String),               // reason
+    Welcome(String, Vec<String>),             // motd, players online
+    Kicked(String),                           // reason
+    SpawnPoint(Vec3),                         // pos
+    Teleport(Vec3),                           // pos, where the server has the player
 }
 
 impl ServerPacket {
@@ -68,9 +133,18 @@ impl ServerPacket {
             ServerPacket::ChatMessage(_, _) => DefaultChannel::ReliableOrdered,
             ServerPacket::PlayerConnected(_, _) => DefaultChannel::ReliableOrdered,
             ServerPacket::PlayerDisconnected(_, _) => DefaultChannel::ReliableOrdered,
-            ServerPacket::ConnectionInfo(_, _) => DefaultChannel::ReliableOrdered,
+            ServerPacket::ConnectionInfo(_, _, _, _, _, _) => DefaultChannel::ReliableOrdered,
             ServerPacket::ChunkUpdate(_, _) => DefaultChannel::ReliableUnordered,
             ServerPacket::PlayerData(_) => DefaultChannel::Unreliable,
+            ServerPacket::EntityUpdate(_) => DefaultChannel::Unreliable,
+            ServerPacket::TimeOfDay(_) => DefaultChannel::Unreliable,
+            ServerPacket::PlayerDied(_) => DefaultChannel::ReliableOrdered,
+            ServerPacket::PlayerRespawned(_) => DefaultChannel::ReliableOrdered,
+            ServerPacket::ConnectionRejected(_) => DefaultChannel::ReliableOrdered,
+            ServerPacket::Welcome(_, _) => DefaultChannel::ReliableOrdered,
+            ServerPacket::Kicked(_) => DefaultChannel::ReliableOrdered,
+            ServerPacket::SpawnPoint(_) => DefaultChannel::ReliableOrdered,
+            ServerPacket::Teleport(_) => DefaultChannel::ReliableOrdered,
         }
     }
     pub fn broadcast(&mut self, server: &mut RenetServer) {
@@ -83,6 +157,15 @@ impl ServerPacket {
             bincode::serialize(self).unwrap(),
         );
     }
+    // like broadcast_except, skipping every client `skip` returns true for
+    pub fn broadcast_except_where(&mut self, server: &mut RenetServer, skip: impl Fn(u64) -> bool) {
+        let message = bincode::serialize(self).unwrap();
+        for client_id in server.clients_id() {
+            if !skip(client_id) {
+                server.send_message(client_id, self.channel(), message.clone());
+            }
+        }
+    }
     pub fn send(&mut self, server: &mut RenetServer, client_id: u64) {
         server.send_message(client_id, self.channel(), bincode::serialize(self).unwrap());
     }
@@ -93,14 +176,26 @@ pub struct Persistent<R: Serialize + DeserializeOwned> {
     pub path: PathBuf,
     pub data: R,
     human: bool,
+    loader: Option<fn(&Path) -> Result<R, String>>,
 }
 
 impl<R: Serialize + DeserializeOwned> Persistent<R> {
     pub fn new(path: PathBuf, default: R, human: bool) -> Self {
+        Self::with_loader(path, default, human, None)
+    }
+
+    // loader replaces the default reading, used for migrating old files
+    pub fn with_loader(
+        path: PathBuf,
+        default: R,
+        human: bool,
+        loader: Option<fn(&Path) -> Result<R, String>>,
+    ) -> Self {
         let mut persistent = Self {
             path: path.clone(),
             data: default,
             human,
+            loader,
         };
 
         if !path.exists() {
@@ -141,6 +236,15 @@ impl<R: Serialize + DeserializeOwned> Persistent<R> {
     }
 
     fn read(&self) -> Result<R, String> {
+        if let Some(loader) = self.loader {
+            return loader(&self.path).inspect_err(|error| {
+                println!(
+                    "Couldn't load '{}', reverting to default. error: {error}",
+                    self.path.display()
+                );
+            });
+        }
+
         let bytes = std::fs::read(&self.path).map_err(|e| e.to_string())?;
         if self.human {
             let s = String::from_utf8(bytes).map_err(|e| e.to_string())?;
@@ -153,26 +257,41 @@ impl<R: Serialize + DeserializeOwned> Persistent<R> {
                 msg
             })
         } else {
-            bincode::deserialize(&bytes).map_err(|_| {
-                let msg = format!(
-                    "Couldn't deserialize bincode, reverting '{}' to default.",
-                    self.path.display()
-                );
-                println!("{msg}");
-                msg
-            })
+            strip_save_header(&bytes)
+                .filter(|&(version, _)| version == SAVE_VERSION)
+                .and_then(|(_, data)| bincode::deserialize(data).ok())
+                .ok_or_else(|| {
+                    let msg = format!(
+                        "Couldn't deserialize bincode, reverting '{}' to default.",
+                        self.path.display()
+                    );
+                    println!("{msg}");
+                    msg
+                })
         }
     }
 
     pub fn write(&self) -> Result<(), String> {
-        let bytes = if self.human {
+        Self::write_to(&self.path, &self.data, self.human)
+    }
+
+    // for data that was moved out to be written on another thread
+    pub fn write_to(path: &Path, data: &R, human: bool) -> Result<(), String> {
+        let bytes = if human {
             ("# don't modify if you don't know what you're doing.\n\n".to_string()
-                + &toml::to_string(&self.data).map_err(|e| e.to_string())?)
+                + &toml::to_string(data).map_err(|e| e.to_string())?)
                 .into_bytes()
         } else {
-            bincode::serialize(&self.data).map_err(|e| e.to_string())?
+            let mut bytes = SAVE_MAGIC.to_vec();
+            bytes.extend(SAVE_VERSION.to_le_bytes());
+            bytes.extend(bincode::serialize(data).map_err(|e| e.to_string())?);
+            bytes
         };
-        std::fs::write(&self.path, bytes).map_err(|e| e.to_string())
+        std::fs::write(path, bytes).map_err(|e| e.to_string())
+    }
+
+    pub fn human(&self) -> bool {
+        self.human
     }
 }
 
@@ -198,16 +317,356 @@ pub fn hash(value: impl std::hash::Hash) -> u64 {
 
 #[derive(Serialize, Deserialize, Clone, Default, Debug)]
 pub struct SavedChunk {
-    // pub entities: Vec<(Entity, GameEntity)>,
-    pub blocks: HashMap<IVec3, Block>, // placed/broken blocks
+    pub blocks: SavedBlocks,
+    pub entities: Vec<GameEntity>, // entities as they were when the chunk was last saved
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum SavedBlocks {
+    Sparse(HashMap<IVec3, Block>), // placed/broken blocks
+    // every block in the chunk, run length encoded going up each column
+    Dense {
+        palette: Vec<Option<Block>>, // None is whatever generation put there
+        runs: Vec<(u16, u32)>,       // palette index, length
+    },
+}
+
+impl Default for SavedBlocks {
+    fn default() -> Self {
+        SavedBlocks::Sparse(HashMap::new())
+    }
+}
+
+impl SavedBlocks {
+    fn dense(block_at: impl Fn(IVec3) -> Option<Block>) -> Self {
+        let mut palette = Vec::new();
+        let mut runs: Vec<(u16, u32)> = Vec::new();
+
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_HEIGHT {
+                    let index = palette_index(&mut palette, block_at(ivec3(x, y, z)));
+                    match runs.last_mut() {
+                        Some((last, len)) if *last == index => *len += 1,
+                        _ => runs.push((index, 1)),
+                    }
+                }
+            }
+        }
+
+        SavedBlocks::Dense { palette, runs }
+    }
+
+    // sparse edits get packed into the dense form once there are more than DENSE_THRESHOLD
+    pub fn insert(&mut self, pos: IVec3, block: Block) {
+        match self {
+            SavedBlocks::Sparse(blocks) => {
+                blocks.insert(pos, block);
+                self.pack_if_full();
+            }
+            SavedBlocks::Dense { palette, runs } => {
+                let index = palette_index(palette, Some(block));
+                set_run(runs, column_index(pos), index);
+            }
+        }
+    }
+
+    // like insert for many blocks, a big batch on a dense chunk is unpacked and packed once
+    // instead of patching the runs for every block
+    pub fn extend(&mut self, edits: impl IntoIterator<Item = (IVec3, Block)>) {
+        match self {
+            SavedBlocks::Sparse(blocks) => {
+                blocks.extend(edits);
+                self.pack_if_full();
+            }
+            SavedBlocks::Dense { .. } => {
+                let edits = edits.into_iter().collect::<Vec<_>>();
+                if edits.len() <= DENSE_THRESHOLD {
+                    for (pos, block) in edits {
+                        self.insert(pos, block);
+                    }
+                    return;
+                }
+
+                let mut blocks = vec![None; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize];
+                for (pos, block) in self.iter().chain(edits) {
+                    blocks[column_index(pos)] = Some(block);
+                }
+                *self = SavedBlocks::dense(|pos| blocks[column_index(pos)]);
+            }
+        }
+    }
+
+    fn pack_if_full(&mut self) {
+        if let SavedBlocks::Sparse(blocks) = self
+            && blocks.len() > DENSE_THRESHOLD
+        {
+            let blocks = std::mem::take(blocks);
+            *self = SavedBlocks::dense(|pos| blocks.get(&pos).copied());
+        }
+    }
+
+    // only the blocks that differ from generation, same as the sparse form
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (IVec3, Block)> + '_> {
+        match self {
+            SavedBlocks::Sparse(blocks) => {
+                Box::new(blocks.iter().map(|(&pos, &block)| (pos, block)))
+            }
+            SavedBlocks::Dense { palette, runs } => Box::new(
+                runs.iter()
+                    .flat_map(|&(index, len)| {
+                        std::iter::repeat_n(palette[index as usize], len as usize)
+                    })
+                    .enumerate()
+                    .filter_map(|(i, block)| {
+                        let (column, y) = (i as i32 / CHUNK_HEIGHT, i as i32 % CHUNK_HEIGHT);
+                        Some((ivec3(column % CHUNK_SIZE, y, column / CHUNK_SIZE), block?))
+                    }),
+            ),
+        }
+    }
+}
+
+fn palette_index(palette: &mut Vec<Option<Block>>, block: Option<Block>) -> u16 {
+    match palette.iter().position(|&b| b == block) {
+        Some(index) => index as u16,
+        None => {
+            palette.push(block);
+            (palette.len() - 1) as u16
+        }
+    }
+}
+
+// points one block of the runs at another palette entry, splitting the run it's in and
+// merging with the runs next to it
+fn set_run(runs: &mut Vec<(u16, u32)>, block: usize, index: u16) {
+    let mut start = 0;
+    let mut i = 0;
+    while start + runs[i].1 as usize <= block {
+        start += runs[i].1 as usize;
+        i += 1;
+    }
+    let (old, len) = runs[i];
+    if old == index {
+        return;
+    }
+
+    let before = (block - start) as u32;
+    let after = len - before - 1;
+    let mut replacement = Vec::with_capacity(3);
+    if before > 0 {
+        replacement.push((old, before));
+    }
+    replacement.push((index, 1));
+    if after > 0 {
+        replacement.push((old, after));
+    }
+    runs.splice(i..=i, replacement);
+
+    // the new block sits at i + 1 if the old run was split before it
+    let at = if before > 0 { i + 1 } else { i };
+    if at + 1 < runs.len() && runs[at + 1].0 == index {
+        runs[at].1 += runs[at + 1].1;
+        runs.remove(at + 1);
+    }
+    if at > 0 && runs[at - 1].0 == index {
+        runs[at - 1].1 += runs[at].1;
+        runs.remove(at);
+    }
+}
+
+#[inline]
+fn column_index(pos: IVec3) -> usize {
+    ((pos.z * CHUNK_SIZE + pos.x) * CHUNK_HEIGHT + pos.y) as usize
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct SavedWorld {
     pub seed: u32,
-    // name, (transform, velocity, yaw, pitch)
-    pub players: HashMap<String, (Vec3, Vec3, f32, f32)>,
+    // name, (transform, velocity, yaw, pitch, health, inventory)
+    pub players: HashMap<String, (Vec3, Vec3, f32, f32, u32, Vec<u32>)>,
     pub chunks: HashMap<IVec3, SavedChunk>,
+    pub creative: bool, // infinite blocks and no fall damage
+    pub world_gen: WorldGenConfig,
+    pub spawn: Option<Vec3>, // None is on top of the terrain at the world origin
+}
+
+// terrain knobs picked when the world is created, the same values always give the same world
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldGenConfig {
+    pub amplitude: f32, // height above or below sea level gets scaled by this
+    pub sea_level: i32,
+    pub cave_density: f32, // 0.0 for no caves
+    pub tree_density: f32, // 0.0 for no trees
+    pub biome_scale: f32,  // bigger means wider biomes
+}
+
+impl Default for WorldGenConfig {
+    fn default() -> Self {
+        WorldPreset::Default.config()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum WorldPreset {
+    #[default]
+    Default,
+    Flat,
+    Islands,
+    Amplified,
+}
+
+impl WorldPreset {
+    pub const ALL: [WorldPreset; 4] = [
+        WorldPreset::Default,
+        WorldPreset::Flat,
+        WorldPreset::Islands,
+        WorldPreset::Amplified,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WorldPreset::Default => "Default",
+            WorldPreset::Flat => "Flat",
+            WorldPreset::Islands => "Islands",
+            WorldPreset::Amplified => "Amplified",
+        }
+    }
+
+    pub fn config(self) -> WorldGenConfig {
+        let default = WorldGenConfig {
+            amplitude: 1.0,
+            sea_level: SEA_LEVEL,
+            cave_density: 1.0,
+            tree_density: 1.0,
+            biome_scale: 1.0,
+        };
+        match self {
+            WorldPreset::Default => default,
+            WorldPreset::Flat => WorldGenConfig {
+                amplitude: 0.0,
+                // everything sits right at SEA_LEVEL, keep it dry
+                sea_level: SEA_LEVEL - 4,
+                cave_density: 0.0,
+                ..default
+            },
+            WorldPreset::Islands => WorldGenConfig {
+                sea_level: SEA_LEVEL + 30,
+                biome_scale: 0.5,
+                ..default
+            },
+            WorldPreset::Amplified => WorldGenConfig {
+                amplitude: 1.4,
+                cave_density: 1.3,
+                ..default
+            },
+        }
+    }
+}
+
+impl SavedWorld {
+    pub fn persistent(path: PathBuf, default: SavedWorld) -> Persistent<SavedWorld> {
+        Persistent::with_loader(path, default, false, Some(SavedWorld::load_migrating))
+    }
+
+    // every format so far starts with the seed, the world list reads just that instead of
+    // loading all the chunks
+    pub fn read_seed(path: &Path) -> Option<u32> {
+        let mut bytes = [0; 12];
+        std::fs::File::open(path)
+            .ok()?
+            .read_exact(&mut bytes)
+            .ok()?;
+        let data = strip_save_header(&bytes).map_or(&bytes[..], |(_, data)| data);
+        Some(u32::from_le_bytes(data.get(..4)?.try_into().ok()?))
+    }
+
+    // reads the current format and upgrades anything older
+    pub fn load_migrating(path: &Path) -> Result<SavedWorld, String> {
+        let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+
+        let Some((version, data)) = strip_save_header(&bytes) else {
+            // saves from before the header existed
+            return legacy_world(&bytes).ok_or("Unknown save format".to_string());
+        };
+
+        if version != SAVE_VERSION {
+            return Err(format!(
+                "Save version {version} doesn't match {SAVE_VERSION}"
+            ));
+        }
+        bincode::deserialize(data).map_err(|e| e.to_string())
+    }
+}
+
+fn strip_save_header(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let (version, data) = bytes.strip_prefix(&SAVE_MAGIC)?.split_at_checked(4)?;
+    Some((u32::from_le_bytes(version.try_into().ok()?), data))
+}
+
+#[derive(Deserialize)]
+struct LegacySavedChunk {
+    blocks: HashMap<IVec3, Block>,
+}
+
+// seed, players, chunks
+#[derive(Deserialize)]
+struct LegacySavedWorld(
+    u32,
+    HashMap<String, (Vec3, Vec3, f32, f32)>,
+    HashMap<IVec3, LegacySavedChunk>,
+);
+
+// seed, (position, yaw, pitch), chunks. from before multiplayer
+#[derive(Deserialize)]
+struct LegacySinglePlayerWorld(u32, (Vec3, f32, f32), HashMap<IVec3, LegacySavedChunk>);
+
+fn legacy_world(bytes: &[u8]) -> Option<SavedWorld> {
+    use bincode::Options;
+    // same encoding as bincode::deserialize, but garbage left over means it's the wrong layout
+    let options = || {
+        bincode::DefaultOptions::new()
+            .with_fixint_encoding()
+            .reject_trailing_bytes()
+    };
+    let chunks = |chunks: HashMap<IVec3, LegacySavedChunk>| {
+        chunks
+            .into_iter()
+            .map(|(pos, chunk)| {
+                let saved_chunk = SavedChunk {
+                    blocks: SavedBlocks::Sparse(chunk.blocks),
+                    ..Default::default()
+                };
+                (pos, saved_chunk)
+            })
+            .collect()
+    };
+
+    // everything was creative and nobody had taken damage or picked anything up yet
+    let world = |seed, players: HashMap<String, (Vec3, Vec3, f32, f32)>, old_chunks| SavedWorld {
+        seed,
+        players: players
+            .into_iter()
+            .map(|(name, (pos, velocity, yaw, pitch))| {
+                (name, (pos, velocity, yaw, pitch, MAX_HEALTH, Vec::new()))
+            })
+            .collect(),
+        chunks: chunks(old_chunks),
+        creative: true,
+        world_gen: WorldGenConfig::default(),
+        spawn: None,
+    };
+
+    if let Ok(LegacySavedWorld(seed, players, old_chunks)) = options().deserialize(bytes) {
+        return Some(world(seed, players, old_chunks));
+    }
+    if let Ok(LegacySinglePlayerWorld(seed, (pos, yaw, pitch), old_chunks)) =
+        options().deserialize(bytes)
+    {
+        let players = HashMap::from([("Player".to_string(), (pos, Vec3::ZERO, yaw, pitch))]);
+        return Some(world(seed, players, old_chunks));
+    }
+    None
 }
 
 #[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Debug)]
@@ -232,6 +691,9 @@ pub enum BlockKind {
     Wood,
     Leaf,
     Snow,
+    CoalOre,
+    IronOre,
+    Glowstone,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -239,6 +701,15 @@ pub enum GameEntityKind {
     Ferris,
 }
 
+impl GameEntityKind {
+    // hitbox from GameEntity.pos, ignores rotation
+    pub fn size(&self) -> Vec3 {
+        match self {
+            GameEntityKind::Ferris => vec3(1.0, 0.4, 1.0),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[cfg_attr(feature = "client", derive(Component))]
 pub struct Block {
@@ -304,9 +775,46 @@ impl Block {
         kind: BlockKind::Snow,
         ..Self::DEFAULT
     };
+    pub const COAL_ORE: Self = Self {
+        kind: BlockKind::CoalOre,
+        ..Self::DEFAULT
+    };
+    pub const IRON_ORE: Self = Self {
+        kind: BlockKind::IronOre,
+        ..Self::DEFAULT
+    };
+    pub const GLOWSTONE: Self = Self {
+        kind: BlockKind::Glowstone,
+        ..Self::DEFAULT
+    };
 }
 
 impl BlockKind {
+    pub const ALL: [BlockKind; 14] = [
+        BlockKind::Air,
+        BlockKind::Stone,
+        BlockKind::Dirt,
+        BlockKind::Grass,
+        BlockKind::Plank,
+        BlockKind::Bedrock,
+        BlockKind::Water,
+        BlockKind::Sand,
+        BlockKind::Wood,
+        BlockKind::Leaf,
+        BlockKind::Snow,
+        BlockKind::CoalOre,
+        BlockKind::IronOre,
+        BlockKind::Glowstone,
+    ];
+
+    // case insensitive and underscores are ignored, so coal_ore and CoalOre both work
+    pub fn from_name(name: &str) -> Option<BlockKind> {
+        let name = name.replace('_', "");
+        BlockKind::ALL
+            .into_iter()
+            .find(|kind| format!("{kind:?}").eq_ignore_ascii_case(&name))
+    }
+
     #[inline]
     pub fn is_solid(self) -> bool {
         self != BlockKind::Air && self != BlockKind::Water
@@ -315,13 +823,67 @@ impl BlockKind {
     pub fn is_air(self) -> bool {
         self == BlockKind::Air
     }
+    // lets sky and block light through
+    #[inline]
+    pub fn is_transparent(self) -> bool {
+        matches!(self, BlockKind::Air | BlockKind::Water | BlockKind::Leaf)
+    }
+    // block light given off, 0 - 15
+    #[inline]
+    pub fn light_emission(self) -> u8 {
+        match self {
+            BlockKind::Glowstone => 15,
+            _ => 0,
+        }
+    }
+    // adding a block means adding its tiles to atlas.png and a row here
+    #[inline]
+    pub fn tiles(self) -> BlockTiles {
+        match self {
+            BlockKind::Air => BlockTiles::row(0), // never meshed
+            BlockKind::Stone => BlockTiles::row(0),
+            BlockKind::Dirt => BlockTiles::row(1),
+            // plain dirt underneath
+            BlockKind::Grass => BlockTiles {
+                bottom: BlockTiles::row(1).top,
+                ..BlockTiles::row(2)
+            },
+            BlockKind::Plank => BlockTiles::row(3),
+            BlockKind::Bedrock => BlockTiles::row(4),
+            BlockKind::Water => BlockTiles::row(5),
+            BlockKind::Sand => BlockTiles::row(6),
+            BlockKind::Wood => BlockTiles::row(7),
+            BlockKind::Leaf => BlockTiles::row(8),
+            BlockKind::Snow => BlockTiles::row(9),
+            BlockKind::CoalOre => BlockTiles::row(10),
+            BlockKind::IronOre => BlockTiles::row(11),
+            BlockKind::Glowstone => BlockTiles::row(12),
+        }
+    }
     #[inline]
     pub fn can_rotate(self) -> bool {
         match self {
-            BlockKind::Wood => true,
+            BlockKind::Wood
+            | BlockKind::Plank
+            | BlockKind::Stone
+            | BlockKind::CoalOre
+            | BlockKind::IronOre => true,
             _ => false,
         }
     }
+    // seconds of holding the mouse to break in survival
+    #[inline]
+    pub fn break_time(self) -> f32 {
+        match self {
+            BlockKind::Air | BlockKind::Water => 0.0,
+            BlockKind::Leaf | BlockKind::Glowstone => 0.3,
+            BlockKind::Dirt | BlockKind::Grass | BlockKind::Sand | BlockKind::Snow => 0.75,
+            BlockKind::Plank | BlockKind::Wood => 2.0,
+            BlockKind::Stone => 2.5,
+            BlockKind::CoalOre | BlockKind::IronOre => 3.0,
+            BlockKind::Bedrock => f32::INFINITY,
+        }
+    }
     #[inline]
     pub fn from_u32(value: u32) -> BlockKind {
         match value {
@@ -336,12 +898,70 @@ impl BlockKind {
             8 => BlockKind::Wood,
             9 => BlockKind::Leaf,
             10 => BlockKind::Snow,
+            11 => BlockKind::CoalOre,
+            12 => BlockKind::IronOre,
+            13 => BlockKind::Glowstone,
             _ => BlockKind::Air,
         }
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub struct AtlasLayout {
+    pub columns: u32,
+    pub rows: u32,
+    pub tile_pixels: u32,
+}
+
+impl AtlasLayout {
+    #[inline]
+    pub fn tile_size(self) -> Vec2 {
+        vec2(1.0 / self.columns as f32, 1.0 / self.rows as f32)
+    }
+
+    // top left corner of a (column, row) tile, in uvs
+    #[inline]
+    pub fn tile_origin(self, tile: UVec2) -> Vec2 {
+        tile.as_vec2() * self.tile_size()
+    }
+
+    // min and max corners of a tile, in pixels
+    #[inline]
+    pub fn tile_rect(self, tile: UVec2) -> (Vec2, Vec2) {
+        let min = (tile * self.tile_pixels).as_vec2();
+        (min, min + Vec2::splat(self.tile_pixels as f32))
+    }
+}
+
+// (column, row) atlas tiles, top and bottom are along the block's direction
+#[derive(Clone, Copy, Debug)]
+pub struct BlockTiles {
+    pub top: UVec2,
+    pub side: UVec2,
+    pub bottom: UVec2,
+}
+
+impl BlockTiles {
+    // top, side and bottom next to each other on one row
+    pub const fn row(row: u32) -> Self {
+        BlockTiles {
+            top: uvec2(0, row),
+            side: uvec2(1, row),
+            bottom: uvec2(2, row),
+        }
+    }
+}
+
 impl Direction {
+    pub const ALL: [Direction; 6] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Bottom,
+        Direction::Top,
+        Direction::Back,
+        Direction::Front,
+    ];
+
     pub const NORMALS: &[Vec3; 6] = &[
         vec3(-1.0, 0.0, 0.0), // Left
         vec3(1.0, 0.0, 0.0),  // Right
@@ -368,53 +988,153 @@ impl Direction {
         }
     }
 
+    // corners of a unit face, in the order the meshers emit them
+    #[inline]
+    pub fn unit_corners(self) -> [Vec3; 4] {
+        match self {
+            Direction::Left => [
+                vec3(0.0, 0.0, 0.0),
+                vec3(0.0, 0.0, 1.0),
+                vec3(0.0, 1.0, 1.0),
+                vec3(0.0, 1.0, 0.0),
+            ],
+            Direction::Right => [
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 1.0, 1.0),
+                vec3(0.0, 0.0, 1.0),
+                vec3(0.0, 0.0, 0.0),
+            ],
+            Direction::Bottom => [
+                vec3(0.0, 0.0, 0.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(1.0, 0.0, 1.0),
+                vec3(0.0, 0.0, 1.0),
+            ],
+            Direction::Top => [
+                vec3(0.0, 0.0, 1.0),
+                vec3(1.0, 0.0, 1.0),
+                vec3(1.0, 0.0, 0.0),
+                vec3(0.0, 0.0, 0.0),
+            ],
+            Direction::Back => [
+                vec3(0.0, 0.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(1.0, 1.0, 0.0),
+                vec3(1.0, 0.0, 0.0),
+            ],
+            Direction::Front => [
+                vec3(1.0, 0.0, 0.0),
+                vec3(1.0, 1.0, 0.0),
+                vec3(0.0, 1.0, 0.0),
+                vec3(0.0, 0.0, 0.0),
+            ],
+        }
+    }
+
     #[inline]
     pub fn get_uvs(self, block: Block) -> [Vec2; 4] {
-        const ATLAS_SIZE_X: f32 = 3.0;
-        const ATLAS_SIZE_Y: f32 = 10.0;
+        // blocks that can't rotate always have their top facing up in the world
+        let direction = if block.kind.can_rotate() {
+            block.direction
+        } else {
+            Direction::Top
+        };
+        let tiles = block.kind.tiles();
+        let tile = ATLAS.tile_origin(match self {
+            d if d == direction => tiles.top,
+            d if d == direction.get_opposite() => tiles.bottom,
+            _ => tiles.side,
+        });
+        let tile_size = ATLAS.tile_size();
 
-        let face_idx = match self {
-            d if d == block.direction => 0.0,
-            d if d == block.direction.get_opposite() => 2.0,
-            _ => 1.0,
+        // the texture's up follows the block's axis on the sides, the ends just need a fixed up
+        let normal = self.as_vec3();
+        let axis = direction.as_vec3();
+        let up = if normal.dot(axis) != 0.0 {
+            if normal.y != 0.0 {
+                Vec3::NEG_Z
+            } else {
+                Vec3::Y
+            }
+        } else {
+            axis
         };
+        // right as seen from outside the face, so nothing comes out mirrored
+        let right = up.cross(normal);
 
-        let pos = vec2(
-            face_idx / ATLAS_SIZE_X,
-            (block.kind as u32 - 1) as f32 / ATLAS_SIZE_Y,
+        let corners = self.unit_corners();
+        let center = corners.iter().sum::<Vec3>() / 4.0;
+        corners.map(|corner| {
+            let offset = corner - center;
+            let (s, t) = (offset.dot(right) + 0.5, offset.dot(up) + 0.5);
+            // v grows downwards in the atlas
+            tile + vec2(s, 1.0 - t) * tile_size
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // digging out a whole chunk one block at a time ends up as a single run of air
+    #[test]
+    fn hollow_chunk_round_trip() {
+        let mut chunk = SavedChunk::default();
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_HEIGHT {
+                    chunk.blocks.insert(ivec3(x, y, z), Block::AIR);
+                }
+            }
+        }
+        assert!(matches!(chunk.blocks, SavedBlocks::Dense { .. }));
+
+        let bytes = bincode::serialize(&chunk).unwrap();
+        assert!(bytes.len() < 64, "hollow chunk took {} bytes", bytes.len());
+
+        let chunk: SavedChunk = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(
+            chunk.blocks.iter().count(),
+            (CHUNK_SIZE * CHUNK_SIZE * CHUNK_HEIGHT) as usize
         );
+        assert!(chunk.blocks.iter().all(|(_, block)| block == Block::AIR));
+    }
+
+    // patching a dense chunk only touches the block it's given
+    #[test]
+    fn dense_insert() {
+        let edits = (0..=DENSE_THRESHOLD as i32)
+            .map(|i| (ivec3(i % CHUNK_SIZE, i / CHUNK_SIZE, 0), Block::AIR))
+            .collect::<HashMap<_, _>>();
+        let mut blocks = SavedBlocks::default();
+        blocks.extend(edits.clone());
+        assert!(matches!(blocks, SavedBlocks::Dense { .. }));
 
-        let base = [
-            vec2(pos.x, pos.y + 1.0 / ATLAS_SIZE_Y),
-            vec2(pos.x, pos.y),
-            vec2(pos.x + 1.0 / ATLAS_SIZE_X, pos.y),
-            vec2(pos.x + 1.0 / ATLAS_SIZE_X, pos.y + 1.0 / ATLAS_SIZE_Y),
-        ];
-        let rotate_90 = [base[3], base[0], base[1], base[2]];
-        let rotate_180 = [base[2], base[3], base[0], base[1]];
-        let rotate_270 = [base[1], base[2], base[3], base[0]];
-
-        // HOLY BAD CODE
-        use Direction::*;
-        match (block.direction, self) {
-            (Right, Top | Bottom) => base,
-            (Right, Back) => rotate_90,
-            (Right, _) => rotate_270,
-            (Top, Front | Back) => base,
-            (Top, Left) => rotate_90,
-            (Top, _) => rotate_270,
-            (Front, Right | Left) => base,
-            (Front, Bottom) => rotate_90,
-            (Front, _) => rotate_270,
-            (Left, Top | Bottom) => rotate_180,
-            (Left, Back) => rotate_270,
-            (Left, _) => rotate_90,
-            (Bottom, Front | Back) => rotate_180,
-            (Bottom, Left) => rotate_270,
-            (Bottom, _) => rotate_90,
-            (Back, Right | Left) => rotate_180,
-            (Back, Bottom) => rotate_270,
-            (Back, _) => rotate_90,
+        let mut expected = edits;
+        for pos in [ivec3(3, 10, 0), ivec3(5, 5, 5), ivec3(15, 255, 15)] {
+            blocks.insert(pos, Block::STONE);
+            expected.insert(pos, Block::STONE);
         }
+        assert_eq!(blocks.iter().collect::<HashMap<_, _>>(), expected);
+    }
+
+    // the save committed with the repo, written before saves had a header
+    #[test]
+    fn load_baseline_save() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("server/saves/world.ferris");
+        let world = SavedWorld::load_migrating(&path).unwrap();
+
+        assert_eq!(world.seed, 810536639);
+        assert_eq!(world.players.len(), 2);
+        assert!(world.players.values().all(|player| player.4 == MAX_HEALTH));
+        assert!(world.creative);
+        assert_eq!(
+            world.chunks[&ivec3(2, 0, -10)]
+                .blocks
+                .iter()
+                .collect::<Vec<_>>(),
+            [(ivec3(5, 147, 11), Block::STONE)]
+        );
     }
 }
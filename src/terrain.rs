@@ -0,0 +1,193 @@
+// terrain height and biomes, shared so the server can find the ground without
+// generating whole chunks
+
+use bevy_math::{FloatExt, Vec2, Vec3, vec2};
+use noiz::{
+    Noise, NoiseFunction, SampleableFor,
+    prelude::{
+        FractalLayers, Normed, Persistence,
+        common_noise::{Fbm, Perlin, Simplex},
+    },
+    rng::NoiseRng,
+};
+
+use crate::{CHUNK_HEIGHT, SEA_LEVEL, WorldGenConfig};
+
+#[derive(Default, Clone, Copy)]
+pub struct NoiseFunctions {
+    pub terrain: Noise<Fbm<Simplex>>,
+    pub biome: Noise<Fbm<Simplex>>,
+    pub ferris: Noise<Perlin>,
+    pub tree: Noise<Perlin>,
+    pub cave: Noise<Fbm<Perlin>>,
+    pub ore: Noise<Perlin>,
+    pub warp: Noise<Perlin>,
+    pub world_gen: WorldGenConfig,
+}
+
+const OCEAN_MIN_HEIGHT: f32 = SEA_LEVEL as f32 - 40.0;
+const OCEAN_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 5.0;
+const OCEAN_FLATTENING_EXPONENT: f32 = 4.0;
+const PLAINS_MIN_HEIGHT: f32 = SEA_LEVEL as f32 + 10.0;
+const PLAINS_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 40.0;
+const PLAINS_FLATTENING_EXPONENT: f32 = 3.0;
+const MOUNTAIN_MIN_HEIGHT: f32 = SEA_LEVEL as f32 + 50.0;
+const MOUNTAIN_MAX_HEIGHT: f32 = SEA_LEVEL as f32 + 180.0;
+const MOUNTAIN_FLATTENING_EXPONENT: f32 = 1.5;
+pub const OCEAN_PLAINS_THRESHOLD: f32 = 0.4;
+pub const PLAINS_MOUNTAIN_THRESHOLD: f32 = 0.6;
+const MOUNTAIN_BLEND_WIDTH: f32 = 0.1;
+const BIOME_WARP_STRENGTH: f32 = 200.0; // blocks
+const BIOME_WARP_OFFSET: Vec2 = Vec2::splat(5000.0); // decorrelates the two warp axes
+
+#[inline]
+pub fn noise<T: NoiseFunction<Vec2, Output = f32>>(noise: Noise<T>, pos: Vec2) -> f32 {
+    let n: f32 = noise.sample(pos);
+    (n + 1.0) / 2.0
+}
+
+#[inline]
+pub fn noise_3d<T: NoiseFunction<Vec3, Output = f32>>(noise: Noise<T>, pos: Vec3) -> f32 {
+    let n: f32 = noise.sample(pos);
+    (n + 1.0) / 2.0
+}
+
+// the client and the server both build their noises here, any change to
+// these parameters changes the terrain of every existing seed
+#[inline]
+pub fn get_noise_functions(seed: u32, world_gen: WorldGenConfig) -> NoiseFunctions {
+    NoiseFunctions {
+        terrain: Noise {
+            noise: Fbm::<Simplex>::new(
+                Normed::default(),
+                Persistence(0.5),
+                FractalLayers {
+                    amount: 4,
+                    lacunarity: 2.0,
+                    ..Default::default()
+                },
+            ),
+            frequency: 0.00200,
+            seed: NoiseRng(seed),
+        },
+        biome: Noise {
+            noise: Fbm::<Simplex>::new(
+                Normed::default(),
+                Persistence(0.6),
+                FractalLayers {
+                    amount: 3,
+                    lacunarity: 2.0,
+                    ..Default::default()
+                },
+            ),
+            frequency: 0.0001 / world_gen.biome_scale,
+            seed: NoiseRng(seed + 1),
+        },
+        tree: Noise {
+            noise: Perlin::default(),
+            frequency: 0.069,
+            seed: NoiseRng(seed),
+        },
+        ferris: Noise {
+            noise: Perlin::default(),
+            frequency: 0.42,
+            seed: NoiseRng(seed),
+        },
+        cave: Noise {
+            noise: Fbm::<Perlin>::new(
+                Normed::default(),
+                Persistence(0.5),
+                FractalLayers {
+                    amount: 2,
+                    lacunarity: 2.0,
+                    ..Default::default()
+                },
+            ),
+            frequency: 0.04,
+            seed: NoiseRng(seed + 2),
+        },
+        ore: Noise {
+            noise: Perlin::default(),
+            frequency: 0.2,
+            seed: NoiseRng(seed + 3),
+        },
+        warp: Noise {
+            noise: Perlin::default(),
+            frequency: 0.003,
+            seed: NoiseRng(seed + 4),
+        },
+        world_gen,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Biome {
+    Ocean,
+    Plains,
+    Mountains,
+}
+
+impl Biome {
+    // from the biome value terrain_noise returns, ignores the blending between them
+    pub fn from_noise(biome: f32) -> Self {
+        if biome < OCEAN_PLAINS_THRESHOLD {
+            Biome::Ocean
+        } else if biome > PLAINS_MOUNTAIN_THRESHOLD {
+            Biome::Mountains
+        } else {
+            Biome::Plains
+        }
+    }
+}
+
+#[inline]
+// max_y, biome
+pub fn terrain_noise(pos: Vec2, noises: &NoiseFunctions) -> (i32, f32) {
+    let terrain_fbm = noise(noises.terrain, pos);
+    // offset where the biome is sampled so borders meander instead of running straight
+    let warp = vec2(
+        noise(noises.warp, pos),
+        noise(noises.warp, pos + BIOME_WARP_OFFSET),
+    ) * 2.0
+        - 1.0;
+    let biome_fbm = noise(noises.biome, pos + warp * BIOME_WARP_STRENGTH);
+
+    // min height, max height, flattening exponent
+    let ocean = (
+        OCEAN_MIN_HEIGHT,
+        OCEAN_MAX_HEIGHT,
+        OCEAN_FLATTENING_EXPONENT,
+    );
+    let plains = (
+        PLAINS_MIN_HEIGHT,
+        PLAINS_MAX_HEIGHT,
+        PLAINS_FLATTENING_EXPONENT,
+    );
+    let mountains = (
+        MOUNTAIN_MIN_HEIGHT,
+        MOUNTAIN_MAX_HEIGHT,
+        MOUNTAIN_FLATTENING_EXPONENT,
+    );
+
+    // mountains keep rising a bit past their threshold instead of clamping right at it
+    let mountain_end = PLAINS_MOUNTAIN_THRESHOLD + MOUNTAIN_BLEND_WIDTH;
+    let (from, to, t) = if biome_fbm < OCEAN_PLAINS_THRESHOLD {
+        (ocean, plains, biome_fbm / OCEAN_PLAINS_THRESHOLD)
+    } else {
+        let t = (biome_fbm - OCEAN_PLAINS_THRESHOLD) / (mountain_end - OCEAN_PLAINS_THRESHOLD);
+        (plains, mountains, t.min(1.0))
+    };
+    // smoothstep so the slope doesn't kink where one band hands over to the next
+    let t = t * t * (3.0 - 2.0 * t);
+
+    let min_height = from.0.lerp(to.0, t);
+    let max_height = from.1.lerp(to.1, t);
+    let flattening_exp = from.2.lerp(to.2, t);
+
+    let height = min_height + terrain_fbm.powf(flattening_exp) * (max_height - min_height);
+    // leave headroom for trees at the top of the world
+    let height = (SEA_LEVEL as f32 + (height - SEA_LEVEL as f32) * noises.world_gen.amplitude)
+        .clamp(1.0, (CHUNK_HEIGHT - 10) as f32);
+
+    (height as i32, biome_fbm)
+}